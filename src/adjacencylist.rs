@@ -22,6 +22,7 @@ use crate::storage::{FileLoad, FileStore, SyncableFile};
 use super::bitarray::*;
 use super::bitindex::*;
 use super::logarray::*;
+use super::util::calculate_width;
 use futures::future;
 use futures::stream::{Stream, StreamExt, TryStreamExt};
 use futures::task::{Context, Poll};
@@ -109,7 +110,8 @@ impl AdjacencyList {
         self.nums.entry(pos.try_into().unwrap())
     }
 
-    pub fn get(&self, index: u64) -> LogArray {
+    /// Returns the start and (exclusive) end position of the segment for the given left-hand side.
+    fn segment_for(&self, index: u64) -> (u64, u64) {
         if index < 1 {
             panic!("minimum index has to be 1");
         }
@@ -122,10 +124,65 @@ impl AdjacencyList {
         }
 
         let start = self.offset_for(index);
-        let end = self.bits.select1(index).unwrap();
-        let length = end - start + 1;
+        let end = self.bits.select1(index).unwrap() + 1;
+
+        (start, end)
+    }
+
+    pub fn get(&self, index: u64) -> LogArray {
+        let (start, end) = self.segment_for(index);
 
-        self.nums.slice(start as usize, length as usize)
+        self.nums.slice(start as usize, (end - start) as usize)
+    }
+
+    /// Returns the number of right-hand sides paired with `left`.
+    ///
+    /// A left-hand side without any pairs still takes up one padding position, which is not
+    /// counted.
+    pub fn degree(&self, left: u64) -> u64 {
+        let (start, end) = self.segment_for(left);
+        if end - start == 1 && self.nums.entry(start as usize) == 0 {
+            0
+        } else {
+            end - start
+        }
+    }
+
+    /// Returns an iterator over the right-hand sides paired with `left`, in increasing order.
+    pub fn neighbors(&self, left: u64) -> impl Iterator<Item = u64> {
+        let (start, end) = self.segment_for(left);
+
+        self.nums
+            .iter_range(start as usize, end as usize)
+            .filter(|&right| right != 0)
+    }
+
+    /// Returns an iterator over all pairs, ordered by left-hand side and then right-hand side.
+    pub fn edges(&self) -> impl Iterator<Item = (u64, u64)> {
+        self.iter()
+    }
+
+    /// Returns an iterator over the right-hand sides of `node`, each paired with the entry of
+    /// `payload` at the same position.
+    ///
+    /// `payload` is expected to be aligned with the nums of this adjacency list, i.e., be of the
+    /// same length. Just like with `iter`, padding entries for nodes without any right-hand sides
+    /// are skipped.
+    pub fn neighbors_with_payload<'a>(
+        &'a self,
+        node: u64,
+        payload: &'a LogArray,
+    ) -> impl Iterator<Item = (u64, u64)> + 'a {
+        assert_eq!(
+            self.nums.len(),
+            payload.len(),
+            "expected payload length to equal nums length"
+        );
+        let (start, end) = self.segment_for(node);
+
+        (start as usize..end as usize)
+            .map(move |pos| (self.nums.entry(pos), payload.entry(pos)))
+            .filter(|(right, _)| *right != 0)
     }
 
     pub fn iter(&self) -> AdjacencyListIterator {
@@ -137,6 +194,56 @@ impl AdjacencyList {
         }
     }
 
+    /// Returns the reverse of this adjacency list, mapping every right-hand side to the
+    /// left-hand sides it is paired with.
+    ///
+    /// The pairs are bucketed by their right-hand side with a counting sort, so rather than a
+    /// copy of all pairs, only the left-hand sides are held in memory. Right-hand sides without
+    /// any pairs end up as padding, just like holes in the left-hand sides of this list.
+    pub fn transpose(&self) -> AdjacencyList {
+        let max_right = self.nums.max().unwrap_or(0);
+        let mut offsets = vec![0_usize; max_right as usize + 1];
+        for (_, right) in self.iter() {
+            offsets[right as usize] += 1;
+        }
+        let counts = offsets.clone();
+        let mut total = 0;
+        for offset in offsets.iter_mut() {
+            let count = *offset;
+            *offset = total;
+            total += count;
+        }
+
+        let mut lefts = vec![0; total];
+        for (left, right) in self.iter() {
+            lefts[offsets[right as usize]] = left;
+            offsets[right as usize] += 1;
+        }
+
+        let width = calculate_width(self.left_count() as u64);
+        let mut nums = LogArrayBufBuilder::new(BytesMut::new(), width);
+        let mut bits = BitIndexBuilder::new(BytesMut::new(), BytesMut::new(), BytesMut::new());
+        let mut lefts = lefts.into_iter();
+        for &count in counts.iter().skip(1) {
+            if count == 0 {
+                nums.push(0);
+                bits.push(true);
+                continue;
+            }
+
+            for i in 0..count {
+                nums.push(lefts.next().unwrap());
+                bits.push(i == count - 1);
+            }
+        }
+
+        let (bits, blocks, sblocks) = bits.finalize();
+        Self::from_parts(
+            LogArray::parse(nums.finalize().freeze()).unwrap(),
+            BitIndex::from_maps(bits.freeze(), blocks.freeze(), sblocks.freeze()),
+        )
+    }
+
     pub fn bits(&self) -> &BitIndex {
         &self.bits
     }
@@ -918,6 +1025,106 @@ mod tests {
         );
     }
 
+    #[test]
+    fn neighbors_with_payload_pairs_weights_with_targets() {
+        let adjacencies = [(1, 1), (1, 5), (2, 3), (2, 7), (2, 9), (4, 8)];
+        let mut builder = AdjacencyListBufBuilder::new(8);
+        builder.push_all(adjacencies.iter().copied());
+        let aj = AdjacencyList::from_buffers(builder.finalize());
+
+        // one weight per flat position, including the padding entry for node 3
+        let mut weights = LogArrayBufBuilder::new(BytesMut::new(), 8);
+        weights.push_vec(vec![10, 50, 30, 70, 90, 0, 80]);
+        let weights = LogArray::parse(weights.finalize().freeze()).unwrap();
+
+        let result: Vec<_> = aj.neighbors_with_payload(1, &weights).collect();
+        assert_eq!(vec![(1, 10), (5, 50)], result);
+        let result: Vec<_> = aj.neighbors_with_payload(2, &weights).collect();
+        assert_eq!(vec![(3, 30), (7, 70), (9, 90)], result);
+        assert_eq!(0, aj.neighbors_with_payload(3, &weights).count());
+        let result: Vec<_> = aj.neighbors_with_payload(4, &weights).collect();
+        assert_eq!(vec![(8, 80)], result);
+    }
+
+    #[test]
+    fn degree_and_neighbors_match_pairs() {
+        let adjacencies = [(1, 1), (1, 5), (2, 3), (2, 7), (2, 9), (4, 8), (6, 2)];
+        let mut builder = AdjacencyListBufBuilder::new(8);
+        builder.push_all(adjacencies.iter().copied());
+        let aj = AdjacencyList::from_buffers(builder.finalize());
+
+        for left in 1..=aj.left_count() as u64 {
+            let expected: Vec<u64> = adjacencies
+                .iter()
+                .filter(|&&(l, _)| l == left)
+                .map(|&(_, r)| r)
+                .collect();
+            assert_eq!(expected.len() as u64, aj.degree(left));
+            assert_eq!(expected, aj.neighbors(left).collect::<Vec<_>>());
+        }
+        assert_eq!(0, aj.degree(3));
+        assert_eq!(0, aj.neighbors(5).count());
+
+        assert_eq!(&adjacencies[..], &aj.edges().collect::<Vec<_>>()[..]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 7 too large for adjacency list of length 6")]
+    fn degree_beyond_last_left_panics() {
+        let mut builder = AdjacencyListBufBuilder::new(8);
+        builder.push_all([(1, 1), (6, 2)].into_iter());
+        let aj = AdjacencyList::from_buffers(builder.finalize());
+
+        aj.degree(7);
+    }
+
+    #[test]
+    fn transpose_reverses_pairs() {
+        let adjacencies = [(1, 3), (1, 5), (2, 3), (2, 7), (4, 1), (4, 3), (5, 9)];
+        let mut builder = AdjacencyListBufBuilder::new(8);
+        builder.push_all(adjacencies.iter().copied());
+        let aj = AdjacencyList::from_buffers(builder.finalize());
+
+        let transposed = aj.transpose();
+        let mut expected: Vec<_> = adjacencies.iter().map(|&(l, r)| (r, l)).collect();
+        expected.sort();
+        assert_eq!(expected, transposed.iter().collect::<Vec<_>>());
+        assert_eq!(9, transposed.left_count());
+        assert_eq!(0, transposed.get(2).iter().filter(|&r| r != 0).count());
+        assert_eq!(vec![1, 2, 4], transposed.get(3).to_vec());
+
+        let roundtrip = transposed.transpose();
+        assert_eq!(&adjacencies[..], &roundtrip.iter().collect::<Vec<_>>()[..]);
+    }
+
+    #[test]
+    fn transpose_of_larger_list_roundtrips() {
+        let adjacencies: Vec<(u64, u64)> = (1..=500_u64)
+            .flat_map(|left| {
+                (1..=left % 7)
+                    .map(move |i| (left, (left * 31 + i * 97) % 1000 + 1))
+                    .collect::<std::collections::BTreeSet<_>>()
+            })
+            .collect();
+        let mut builder = AdjacencyListBufBuilder::new(10);
+        builder.push_all(adjacencies.iter().copied());
+        let aj = AdjacencyList::from_buffers(builder.finalize());
+
+        let transposed = aj.transpose();
+        assert!(transposed
+            .iter()
+            .collect::<Vec<_>>()
+            .windows(2)
+            .all(|w| w[0] < w[1]));
+        assert_eq!(
+            adjacencies,
+            transposed.transpose().iter().collect::<Vec<_>>()
+        );
+
+        let empty = AdjacencyList::from_buffers(AdjacencyListBufBuilder::new(8).finalize());
+        assert_eq!(0, empty.transpose().iter().count());
+    }
+
     #[test]
     fn adjacencylist_buf_builder_works() {
         let adjacencies = [(1, 1), (1, 5), (2, 3), (2, 7), (4, 8)];
@@ -28,7 +28,7 @@
 //!
 //! * length: the number of usable bits in the bit array
 
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWriteExt};
 
 use super::util;
 use crate::bititer::BitIter;
@@ -402,13 +402,11 @@ impl<B: Buf> Iterator for BitArrayBlockIterator<B> {
 
 /// Read the length (number of bits) from a `FileLoad`.
 pub async fn bitarray_len_from_file<F: FileLoad>(f: F) -> io::Result<u64> {
-    BitArrayError::validate_input_buf_size(f.size().await?)?;
-    let mut control_word = vec![0; 8];
-    f.open_read_from(f.size().await? - 8)
-        .await?
-        .read_exact(&mut control_word)
-        .await?;
-    Ok(read_control_word(&control_word, f.size().await?)?)
+    let size = f.size().await?;
+    BitArrayError::validate_input_buf_size(size)?;
+
+    let control_word = f.map_range(size - 8, 8).await?;
+    Ok(read_control_word(&control_word, size)?)
 }
 
 pub async fn bitarray_stream_bits<F: FileLoad>(
@@ -436,6 +434,8 @@ mod tests {
     use super::*;
     use futures::executor::block_on;
     use futures::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     #[test]
     fn bit_array_error() {
@@ -623,4 +623,51 @@ mod tests {
 
         assert_eq!(contents, result);
     }
+
+    /// A store that counts how often its size is requested.
+    #[derive(Clone)]
+    struct SizeCountingStore {
+        inner: MemoryBackedStore,
+        size_calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl FileLoad for SizeCountingStore {
+        type Read = <MemoryBackedStore as FileLoad>::Read;
+
+        async fn exists(&self) -> io::Result<bool> {
+            self.inner.exists().await
+        }
+
+        async fn size(&self) -> io::Result<usize> {
+            self.size_calls.fetch_add(1, Ordering::Relaxed);
+            self.inner.size().await
+        }
+
+        async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+            self.inner.open_read_from(offset).await
+        }
+
+        async fn map(&self) -> io::Result<Bytes> {
+            self.inner.map().await
+        }
+    }
+
+    #[tokio::test]
+    async fn reading_length_from_file_requests_size_once() {
+        let bits = MemoryBackedStore::new();
+        let mut builder = BitArrayFileBuilder::new(bits.open_write().await.unwrap());
+        builder
+            .push_all(util::stream_iter_ok(vec![true, false, true]))
+            .await
+            .unwrap();
+        builder.finalize().await.unwrap();
+
+        let store = SizeCountingStore {
+            inner: bits,
+            size_calls: Default::default(),
+        };
+        assert_eq!(3, bitarray_len_from_file(store.clone()).await.unwrap());
+        assert_eq!(1, store.size_calls.load(Ordering::Relaxed));
+    }
 }
@@ -3,15 +3,17 @@ use byteorder::{BigEndian, ByteOrder};
 use bytes::Buf;
 use bytes::BufMut;
 use bytes::Bytes;
+use bytes::BytesMut;
 use itertools::Itertools;
 
-use crate::storage::SyncableFile;
+use crate::storage::{BitIndexMaps, SyncableFile};
 
 use super::bitarray::*;
 use super::logarray::*;
 
 use futures::io;
 use futures::stream::StreamExt;
+use thiserror::Error;
 use tokio::io::AsyncRead;
 
 // a block is 64 bit, which is the register size on modern architectures
@@ -27,6 +29,41 @@ fn use_linear_bitscan(start: u64, end: u64) -> bool {
     start / (8 * 64) == end / (8 * 64)
 }
 
+/// The version of the bit index layout written by this crate.
+///
+/// A bit index can be accompanied by a one-byte header containing this version, so that a layout
+/// written by a newer version of this crate is rejected rather than misinterpreted.
+pub const BITINDEX_FORMAT_VERSION: u8 = 1;
+
+/// Write the header for the current bit index layout.
+pub fn write_bitindex_header<B: BufMut>(buf: &mut B) {
+    buf.put_u8(BITINDEX_FORMAT_VERSION);
+}
+
+/// An error that occurred while parsing a versioned bit index.
+#[derive(Debug, Error)]
+pub enum BitIndexError {
+    #[error("the bit index header is empty")]
+    MissingHeader,
+    #[error(
+        "unsupported bit index format version {0} (supported up to {})",
+        BITINDEX_FORMAT_VERSION
+    )]
+    UnsupportedVersion(u8),
+    #[error("expected bit indexes of equal length, got {0} and {1}")]
+    LengthMismatch(usize, usize),
+    #[error(transparent)]
+    BitArray(#[from] BitArrayError),
+    #[error(transparent)]
+    LogArray(#[from] LogArrayError),
+}
+
+impl From<BitIndexError> for io::Error {
+    fn from(err: BitIndexError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
 /// A bitarray with an index, supporting rank and select queries.
 #[derive(Clone)]
 pub struct BitIndex {
@@ -44,6 +81,30 @@ impl BitIndex {
         BitIndex::from_parts(bitarray, blocks_logarray, sblocks_logarray)
     }
 
+    /// Parse a bit index along with its header, checking that the layout version is supported.
+    pub fn from_maps_with_header(
+        header: &[u8],
+        bitarray_map: Bytes,
+        blocks_map: Bytes,
+        sblocks_map: Bytes,
+    ) -> Result<BitIndex, BitIndexError> {
+        match header.first() {
+            None => Err(BitIndexError::MissingHeader),
+            Some(&BITINDEX_FORMAT_VERSION) => {
+                let bitarray = BitArray::from_bits(bitarray_map)?;
+                let blocks_logarray = LogArray::parse(blocks_map)?;
+                let sblocks_logarray = LogArray::parse(sblocks_map)?;
+
+                Ok(BitIndex::from_parts(
+                    bitarray,
+                    blocks_logarray,
+                    sblocks_logarray,
+                ))
+            }
+            Some(&version) => Err(BitIndexError::UnsupportedVersion(version)),
+        }
+    }
+
     pub fn from_parts(array: BitArray, blocks: LogArray, sblocks: LogArray) -> BitIndex {
         assert!(sblocks.len() == (blocks.len() + SBLOCK_SIZE - 1) / SBLOCK_SIZE);
         assert!(blocks.len() == (array.len() + 63) / 64);
@@ -350,6 +411,12 @@ impl BitIndex {
             0 => subrank,
             n => self.rank0(n - 1) + subrank,
         };
+        // the index counts the padding after the last bit as zeros, so a rank beyond the actual
+        // zeros would find a position past the end
+        let ones = self.sblocks.entry(self.sblocks.len() - 1);
+        if rank > self.len() as u64 - ones {
+            return None;
+        }
         let sblock = self.select0_sblock_from_range(rank, start, end);
         let sblock_rank = ((1 + sblock) * SBLOCK_SIZE * 64) as u64 - self.sblocks.entry(sblock);
 
@@ -394,6 +461,380 @@ impl BitIndex {
     pub fn iter(&self) -> impl Iterator<Item = bool> {
         self.array.iter()
     }
+
+    /// Returns an iterator over the positions of the 1-bits, in ascending order.
+    ///
+    /// This scans the bitarray a word at a time, which is much cheaper than calling `select1` for
+    /// every rank.
+    pub fn iter_ones(&self) -> impl Iterator<Item = u64> + '_ {
+        self.iter_positions(true)
+    }
+
+    /// Returns an iterator over the positions of the 0-bits, in ascending order.
+    pub fn iter_zeros(&self) -> impl Iterator<Item = u64> + '_ {
+        self.iter_positions(false)
+    }
+
+    fn iter_positions(&self, find: bool) -> impl Iterator<Item = u64> + '_ {
+        let len = self.len() as u64;
+        self.array
+            .bits()
+            .chunks(8)
+            .enumerate()
+            .flat_map(move |(block_index, bits)| {
+                let mut bits_num = BigEndian::read_u64(bits);
+                if !find {
+                    bits_num = !bits_num;
+                }
+                let offset = block_index as u64 * 64;
+
+                // bits are stored most significant bit first, so the next position is the
+                // amount of leading zeros
+                std::iter::from_fn(move || {
+                    if bits_num == 0 {
+                        return None;
+                    }
+                    let i = bits_num.leading_zeros() as u64;
+                    bits_num &= !(0x8000_0000_0000_0000 >> i);
+
+                    Some(offset + i)
+                })
+            })
+            // the padding in the last block is all zeros, and shouldn't be reported
+            .take_while(move |&position| position < len)
+    }
+
+    /// Build the buffers of a `BitIndex` over `universe` bits, in which exactly the bits at the
+    /// given positions are set.
+    ///
+    /// Panics if a position is >= `universe`.
+    pub fn from_positions(positions: &MonotonicLogArray, universe: u64) -> BitIndexMaps {
+        let mut words = vec![0; universe.div_ceil(64) as usize];
+        for position in positions.iter() {
+            assert!(
+                position < universe,
+                "expected position ({}) < universe ({})",
+                position,
+                universe
+            );
+            words[(position / 64) as usize] |= 0x8000_0000_0000_0000 >> (position % 64);
+        }
+
+        bitindex_maps_from_words(&words, universe)
+    }
+
+    /// Build a `BitIndex` in memory over the given bits.
+    pub fn from_bools<I: IntoIterator<Item = bool>>(bits: I) -> BitIndex {
+        let mut builder = BitIndexBuilder::new(BytesMut::new(), BytesMut::new(), BytesMut::new());
+        builder.push_bits(bits.into_iter());
+        let (bits, blocks, sblocks) = builder.finalize();
+
+        BitIndex::from_maps(bits.freeze(), blocks.freeze(), sblocks.freeze())
+    }
+
+    /// Returns the bitwise and of this index and `other`, which have to be of the same length.
+    pub fn and(&self, other: &BitIndex) -> Result<BitIndex, BitIndexError> {
+        self.combine(other, |a, b| a & b)
+    }
+
+    /// Returns the bitwise or of this index and `other`, which have to be of the same length.
+    pub fn or(&self, other: &BitIndex) -> Result<BitIndex, BitIndexError> {
+        self.combine(other, |a, b| a | b)
+    }
+
+    /// Returns the bitwise xor of this index and `other`, which have to be of the same length.
+    pub fn xor(&self, other: &BitIndex) -> Result<BitIndex, BitIndexError> {
+        self.combine(other, |a, b| a ^ b)
+    }
+
+    /// Combine the bits of both indexes a word at a time, and build a new index over the result.
+    ///
+    /// The padding after the last bit is 0 in both, so it stays 0 for any `op` that maps two
+    /// zeros to zero.
+    fn combine<F: Fn(u64, u64) -> u64>(
+        &self,
+        other: &BitIndex,
+        op: F,
+    ) -> Result<BitIndex, BitIndexError> {
+        if self.len() != other.len() {
+            return Err(BitIndexError::LengthMismatch(self.len(), other.len()));
+        }
+
+        let words: Vec<u64> = self
+            .array
+            .bits()
+            .chunks(8)
+            .zip(other.array.bits().chunks(8))
+            .map(|(a, b)| op(BigEndian::read_u64(a), BigEndian::read_u64(b)))
+            .collect();
+
+        Ok(bitindex_maps_from_words(&words, self.len() as u64).into())
+    }
+
+    /// Returns a view of this index in which every bit is flipped.
+    pub fn complement(&self) -> Complement<'_> {
+        Complement(self)
+    }
+}
+
+/// A view of a `BitIndex` with all bits flipped.
+///
+/// Queries for 1-bits are answered by querying the underlying index for 0-bits and vice versa, so
+/// no second index is needed to run rank and select over the zeros.
+#[derive(Clone, Copy)]
+pub struct Complement<'a>(pub &'a BitIndex);
+
+impl<'a> Complement<'a> {
+    /// Returns the length of the underlying bitarray.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the underlying bitarray is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+
+    /// Returns the flipped bit at the given index.
+    pub fn get(&self, index: u64) -> bool {
+        !self.0.get(index)
+    }
+
+    /// Returns the amount of 1-bits in the view up to and including the given index.
+    pub fn rank1(&self, index: u64) -> u64 {
+        self.0.rank0(index)
+    }
+
+    /// Returns the amount of 0-bits in the view up to and including the given index.
+    pub fn rank0(&self, index: u64) -> u64 {
+        self.0.rank1(index)
+    }
+
+    /// Returns the index of the 1-bit in the view corresponding with the given rank.
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        self.0.select0(rank)
+    }
+
+    /// Returns the index of the 0-bit in the view corresponding with the given rank.
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        self.0.select1(rank)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> + 'a {
+        self.0.iter().map(|bit| !bit)
+    }
+}
+
+/// A bitarray with a rank and select index that can be modified in place.
+///
+/// This is meant for the construction phase of a structure, where bits are flipped in memory and
+/// rank or select queries are needed in between. Rather than rebuilding the entire index after
+/// every modification, `set` only updates the popcount of the affected block and the superblock
+/// counts, which are kept in a Fenwick tree.
+///
+/// Once construction is done, `to_bitindex` produces an immutable `BitIndex` over the same bits.
+#[derive(Clone, Debug)]
+pub struct MutableBitIndex {
+    /// Number of usable bits.
+    len: u64,
+    /// The bits, stored most significant bit first, just like in a `BitArray`.
+    words: Vec<u64>,
+    /// Popcount of each word.
+    block_counts: Vec<u8>,
+    /// Fenwick tree over the popcounts of each superblock, 1-indexed.
+    sblock_tree: Vec<u64>,
+}
+
+impl MutableBitIndex {
+    /// Construct a `MutableBitIndex` of `len` bits, all of which are 0.
+    pub fn new(len: u64) -> Self {
+        let num_words = len.div_ceil(64) as usize;
+        let num_sblocks = num_words.div_ceil(SBLOCK_SIZE);
+
+        Self {
+            len,
+            words: vec![0; num_words],
+            block_counts: vec![0; num_words],
+            sblock_tree: vec![0; num_sblocks + 1],
+        }
+    }
+
+    /// Construct a `MutableBitIndex` containing the same bits as the given `BitArray`.
+    pub fn from_bitarray(array: &BitArray) -> Self {
+        let mut result = Self::new(array.len() as u64);
+        for (word_index, chunk) in array.bits().chunks(8).enumerate() {
+            let word = BigEndian::read_u64(chunk);
+            result.words[word_index] = word;
+            result.block_counts[word_index] = word.count_ones() as u8;
+            result.sblock_tree_add(word_index / SBLOCK_SIZE, word.count_ones() as i64);
+        }
+
+        result
+    }
+
+    fn sblock_tree_add(&mut self, sblock: usize, delta: i64) {
+        let mut i = sblock + 1;
+        while i < self.sblock_tree.len() {
+            self.sblock_tree[i] = (self.sblock_tree[i] as i64 + delta) as u64;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Returns the amount of 1-bits in all superblocks before the given one.
+    fn sblock_tree_prefix(&self, sblock: usize) -> u64 {
+        let mut result = 0;
+        let mut i = sblock;
+        while i > 0 {
+            result += self.sblock_tree[i];
+            i -= i & i.wrapping_neg();
+        }
+
+        result
+    }
+
+    /// Returns the length of the underlying bitarray.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns `true` if there are no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bit at the given index.
+    pub fn get(&self, index: u64) -> bool {
+        assert!(
+            index < self.len,
+            "expected index ({}) < length ({})",
+            index,
+            self.len
+        );
+        self.words[(index / 64) as usize] & (0x8000_0000_0000_0000 >> (index % 64)) != 0
+    }
+
+    /// Sets the bit at the given index, updating the index accordingly.
+    pub fn set(&mut self, index: u64, bit: bool) {
+        if self.get(index) == bit {
+            return;
+        }
+
+        let word_index = (index / 64) as usize;
+        let mask = 0x8000_0000_0000_0000 >> (index % 64);
+        if bit {
+            self.words[word_index] |= mask;
+            self.block_counts[word_index] += 1;
+            self.sblock_tree_add(word_index / SBLOCK_SIZE, 1);
+        } else {
+            self.words[word_index] &= !mask;
+            self.block_counts[word_index] -= 1;
+            self.sblock_tree_add(word_index / SBLOCK_SIZE, -1);
+        }
+    }
+
+    /// Returns the amount of 1-bits in the bitarray up to and including the given index.
+    pub fn rank1(&self, index: u64) -> u64 {
+        assert!(
+            index < self.len,
+            "expected index ({}) < length ({})",
+            index,
+            self.len
+        );
+        let block_index = (index / 64) as usize;
+        let sblock_index = block_index / SBLOCK_SIZE;
+
+        let sblock_rank = self.sblock_tree_prefix(sblock_index);
+        let block_rank: u64 = self.block_counts[sblock_index * SBLOCK_SIZE..block_index]
+            .iter()
+            .map(|&c| c as u64)
+            .sum();
+        let bits_rank = (self.words[block_index] >> (63 - index % 64)).count_ones() as u64;
+
+        sblock_rank + block_rank + bits_rank
+    }
+
+    /// Returns the amount of 0-bits in the bitarray up to and including the given index.
+    pub fn rank0(&self, index: u64) -> u64 {
+        1 + index - self.rank1(index)
+    }
+
+    /// Returns the index of the 1-bit in the bitarray corresponding with the given rank.
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        if rank == 0 {
+            return None;
+        }
+
+        // Descend the Fenwick tree to find the last superblock whose preceding superblocks
+        // contain fewer than `rank` 1-bits.
+        let num_sblocks = self.sblock_tree.len() - 1;
+        let mut sblock = 0;
+        let mut remaining = rank;
+        let mut step = num_sblocks.checked_next_power_of_two().unwrap_or(0);
+        while step > 0 {
+            let next = sblock + step;
+            if next <= num_sblocks && self.sblock_tree[next] < remaining {
+                sblock = next;
+                remaining -= self.sblock_tree[next];
+            }
+            step >>= 1;
+        }
+        if sblock == num_sblocks {
+            return None;
+        }
+
+        let first_block = sblock * SBLOCK_SIZE;
+        let last_block = std::cmp::min(first_block + SBLOCK_SIZE, self.words.len());
+        for block in first_block..last_block {
+            let count = self.block_counts[block] as u64;
+            if count < remaining {
+                remaining -= count;
+                continue;
+            }
+
+            let mut word = self.words[block];
+            for i in 0..64 {
+                if word & 0x8000_0000_0000_0000 != 0 {
+                    remaining -= 1;
+                    if remaining == 0 {
+                        return Some(block as u64 * 64 + i);
+                    }
+                }
+                word <<= 1;
+            }
+        }
+
+        None
+    }
+
+    /// Returns an iterator over the bits.
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(move |index| self.get(index))
+    }
+
+    /// Build an immutable `BitIndex` over the current bits.
+    pub fn to_bitindex(&self) -> BitIndex {
+        bitindex_maps_from_words(&self.words, self.len).into()
+    }
+}
+
+/// Build the bitarray and index buffers for `len` bits, stored most significant bit first in
+/// `words`.
+fn bitindex_maps_from_words(words: &[u64], len: u64) -> BitIndexMaps {
+    let mut bits = BytesMut::with_capacity(words.len() * 8 + 8);
+    for &word in words.iter() {
+        bits.put_u64(word);
+    }
+    bits.put_u64(len);
+
+    let mut blocks = BytesMut::new();
+    let mut sblocks = BytesMut::new();
+    build_bitindex_from_block_iter(words.iter().copied(), &mut blocks, &mut sblocks);
+
+    BitIndexMaps {
+        bits_map: bits.freeze(),
+        blocks_map: blocks.freeze(),
+        sblocks_map: sblocks.freeze(),
+    }
 }
 
 pub async fn build_bitindex<
@@ -452,25 +893,119 @@ pub fn build_bitindex_from_block_iter<I: Iterator<Item = u64>, B1: BufMut, B2: B
     let chunks = blocks_iter.chunks(SBLOCK_SIZE);
     let mut iter = chunks.into_iter();
     while let Some(chunk) = iter.next() {
-        let chunk: Vec<_> = chunk.collect();
-        let mut block_ranks = Vec::with_capacity(chunk.len());
-        for num in chunk {
-            block_ranks.push(num.count_ones() as u64);
+        let block_ranks: Vec<_> = chunk.map(|num| num.count_ones() as u64).collect();
+        push_sblock(
+            &block_ranks,
+            &mut sblock_rank,
+            &mut blocks_builder,
+            &mut sblocks_builder,
+        );
+    }
+
+    blocks_builder.finalize();
+    sblocks_builder.finalize();
+}
+
+/// Push the index entries for a superblock consisting of blocks with the given popcounts.
+fn push_sblock<B1: BufMut, B2: BufMut>(
+    block_ranks: &[u64],
+    sblock_rank: &mut u64,
+    blocks_builder: &mut LogArrayBufBuilder<B1>,
+    sblocks_builder: &mut LogArrayBufBuilder<B2>,
+) {
+    let mut sblock_subrank = block_ranks.iter().sum();
+    *sblock_rank += sblock_subrank;
+
+    for block_rank in block_ranks {
+        blocks_builder.push(sblock_subrank);
+        sblock_subrank -= block_rank;
+    }
+
+    sblocks_builder.push(*sblock_rank);
+}
+
+/// A builder for the bitarray and index buffers of a `BitIndex`, taking one bit at a time.
+///
+/// The index is built alongside the bits, so that the input only has to be traversed once. The
+/// resulting buffers are the ones `BitIndex::from_maps` expects.
+pub struct BitIndexBuilder<B1, B2: BufMut, B3: BufMut> {
+    bits: BitArrayBufBuilder<B1>,
+    blocks: LogArrayBufBuilder<B2>,
+    sblocks: LogArrayBufBuilder<B3>,
+    /// Popcounts of the completed blocks in the current superblock.
+    block_ranks: Vec<u64>,
+    /// Popcount of the block currently being filled.
+    block_rank: u64,
+    sblock_rank: u64,
+}
+
+impl<B1: BufMut, B2: BufMut, B3: BufMut> BitIndexBuilder<B1, B2, B3> {
+    pub fn new(bits: B1, blocks: B2, sblocks: B3) -> Self {
+        Self {
+            bits: BitArrayBufBuilder::new(bits),
+            // the same widths as build_bitindex_from_block_iter uses
+            blocks: LogArrayBufBuilder::new(blocks, 64 - (SBLOCK_SIZE * 64).leading_zeros() as u8),
+            sblocks: LogArrayBufBuilder::new(sblocks, 64),
+            block_ranks: Vec::with_capacity(SBLOCK_SIZE),
+            block_rank: 0,
+            sblock_rank: 0,
         }
+    }
 
-        let mut sblock_subrank = block_ranks.iter().sum();
-        sblock_rank += sblock_subrank;
+    pub fn push(&mut self, bit: bool) {
+        self.bits.push(bit);
+        self.block_rank += bit as u64;
 
-        for block_rank in block_ranks {
-            blocks_builder.push(sblock_subrank);
-            sblock_subrank -= block_rank;
+        if self.bits.count().is_multiple_of(64) {
+            self.finish_block();
+        }
+    }
+
+    pub fn push_bits<I: Iterator<Item = bool>>(&mut self, iter: I) {
+        for bit in iter {
+            self.push(bit);
         }
+    }
 
-        sblocks_builder.push(sblock_rank);
+    /// Returns the amount of bits pushed so far.
+    pub fn count(&self) -> u64 {
+        self.bits.count()
     }
 
-    blocks_builder.finalize();
-    sblocks_builder.finalize();
+    fn finish_block(&mut self) {
+        self.block_ranks.push(self.block_rank);
+        self.block_rank = 0;
+
+        if self.block_ranks.len() == SBLOCK_SIZE {
+            self.finish_sblock();
+        }
+    }
+
+    fn finish_sblock(&mut self) {
+        push_sblock(
+            &self.block_ranks,
+            &mut self.sblock_rank,
+            &mut self.blocks,
+            &mut self.sblocks,
+        );
+        self.block_ranks.clear();
+    }
+
+    /// Finish the last block and superblock, returning the bits, blocks and sblocks buffers.
+    pub fn finalize(mut self) -> (B1, B2, B3) {
+        if !self.bits.count().is_multiple_of(64) {
+            self.finish_block();
+        }
+        if !self.block_ranks.is_empty() {
+            self.finish_sblock();
+        }
+
+        (
+            self.bits.finalize(),
+            self.blocks.finalize(),
+            self.sblocks.finalize(),
+        )
+    }
 }
 
 pub fn build_bitindex_from_buf<B1: Buf, B2: BufMut, B3: BufMut>(
@@ -778,4 +1313,275 @@ mod tests {
         assert_eq!(Some(10), index.select0_from_range(4, 5, 11));
         assert_eq!(None, index.select0_from_range(123456, 5, 10));
     }
+
+    #[test]
+    fn from_positions_selects_positions() {
+        let positions: Vec<u64> = (0..2000).map(|i| i * 5 + (i % 3)).collect();
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 14);
+        builder.push_vec(positions.clone());
+        let positions = MonotonicLogArray::parse(builder.finalize().freeze()).unwrap();
+
+        let index: BitIndex = BitIndex::from_positions(&positions, 10_010).into();
+        assert_eq!(10_010, index.len());
+        assert_eq!(positions.len() as u64, index.rank1(10_009));
+        for n in 0..positions.len() {
+            assert_eq!(Some(positions.entry(n)), index.select1(n as u64 + 1));
+        }
+        assert_eq!(None, index.select1(positions.len() as u64 + 1));
+    }
+
+    #[test]
+    fn complement_flips_rank_and_select() {
+        let mut builder = BitArrayBufBuilder::new(BytesMut::new());
+        builder.push_all((0..10_000).map(|n| n % 5 == 0 || n % 7 == 0));
+        let bits = builder.finalize().freeze();
+        let mut blocks = BytesMut::new();
+        let mut sblocks = BytesMut::new();
+        build_bitindex_from_buf(&bits[..], &mut blocks, &mut sblocks);
+        let index = BitIndex::from_maps(bits, blocks.freeze(), sblocks.freeze());
+
+        let complement = index.complement();
+        assert_eq!(index.len(), complement.len());
+        for p in 0..10_000 {
+            assert_eq!(!index.get(p), complement.get(p));
+            assert_eq!(index.rank0(p), complement.rank1(p));
+            assert_eq!(index.rank1(p), complement.rank0(p));
+        }
+
+        let zeros = index.rank0(9_999);
+        for rank in 1..=zeros {
+            assert_eq!(index.select0(rank), complement.select1(rank));
+        }
+        let ones = index.rank1(9_999);
+        for rank in 1..=ones {
+            assert_eq!(index.select1(rank), Complement(&index).select0(rank));
+        }
+    }
+
+    #[test]
+    fn mutable_bitindex_matches_rebuilt_bitindex() {
+        let len = 10_000;
+        let mut index = MutableBitIndex::new(len);
+        let mut expected = vec![false; len as usize];
+
+        let positions = LogArray::parse(LogArray::build_from_seed(42, 20_000, len - 1)).unwrap();
+        for (i, pos) in positions.iter().enumerate() {
+            let bit = i % 3 != 0;
+            index.set(pos, bit);
+            expected[pos as usize] = bit;
+        }
+
+        let mut builder = BitArrayBufBuilder::new(BytesMut::new());
+        builder.push_all(expected.iter().copied());
+        let bits = builder.finalize().freeze();
+        let mut blocks = BytesMut::new();
+        let mut sblocks = BytesMut::new();
+        build_bitindex_from_buf(&bits[..], &mut blocks, &mut sblocks);
+        let rebuilt = BitIndex::from_maps(bits, blocks.freeze(), sblocks.freeze());
+
+        assert_eq!(expected, index.iter().collect::<Vec<_>>());
+        for i in 0..len {
+            assert_eq!(rebuilt.rank1(i), index.rank1(i));
+        }
+        let ones = rebuilt.rank1(len - 1);
+        for rank in 1..=ones {
+            assert_eq!(rebuilt.select1(rank), index.select1(rank));
+        }
+        assert_eq!(None, index.select1(ones + 1));
+
+        let converted = index.to_bitindex();
+        for i in 0..len {
+            assert_eq!(rebuilt.rank1(i), converted.rank1(i));
+        }
+
+        let from_array = MutableBitIndex::from_bitarray(&converted.array);
+        for i in 0..len {
+            assert_eq!(index.get(i), from_array.get(i));
+        }
+        for i in (0..len).step_by(7) {
+            assert_eq!(index.rank1(i), from_array.rank1(i));
+        }
+    }
+
+    /// Returns `len` reproducible random bits, of which roughly `density` percent are set.
+    fn random_bits(len: usize, density: u64) -> Vec<bool> {
+        let seed = len as u64 * 101 + density;
+        LogArray::parse(LogArray::build_from_seed(seed, len, 99))
+            .unwrap()
+            .iter()
+            .map(|r| r < density)
+            .collect()
+    }
+
+    #[test]
+    fn bitindex_builder_matches_naive_rank_and_select() {
+        let sblock_bits = SBLOCK_SIZE * 64;
+        let lengths = [0, 1, 63, 64, 65, sblock_bits, sblock_bits + 1, 12_345];
+        for len in lengths {
+            for density in [0, 1, 16, 50, 100] {
+                let contents = random_bits(len, density);
+
+                let mut builder =
+                    BitIndexBuilder::new(BytesMut::new(), BytesMut::new(), BytesMut::new());
+                builder.push_bits(contents.iter().copied());
+                assert_eq!(len as u64, builder.count());
+                let (bits, blocks, sblocks) = builder.finalize();
+
+                // the index is the same as one built over the finished bitarray
+                let mut expected_blocks = BytesMut::new();
+                let mut expected_sblocks = BytesMut::new();
+                build_bitindex_from_buf(&bits[..], &mut expected_blocks, &mut expected_sblocks);
+                assert_eq!(expected_blocks, blocks);
+                assert_eq!(expected_sblocks, sblocks);
+
+                let index = BitIndex::from_bools(contents.iter().copied());
+                assert_eq!(len, index.len());
+                let mut rank = 0;
+                for (i, &bit) in contents.iter().enumerate() {
+                    assert_eq!(bit, index.get(i as u64));
+                    if bit {
+                        rank += 1;
+                        assert_eq!(Some(i as u64), index.select1(rank));
+                    }
+                    assert_eq!(rank, index.rank1(i as u64));
+                }
+                assert_eq!(None, index.select1(rank + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn rank0_and_select0_match_brute_force() {
+        let mut patterns: Vec<Vec<bool>> = Vec::new();
+        for len in [
+            1,
+            64,
+            100,
+            600,
+            SBLOCK_SIZE * 64,
+            SBLOCK_SIZE * 64 + 5,
+            7_000,
+        ] {
+            patterns.push(vec![false; len]);
+            patterns.push(vec![true; len]);
+            for density in [3, 50, 97] {
+                patterns.push(random_bits(len, density));
+            }
+        }
+
+        for contents in patterns {
+            let index = BitIndex::from_bools(contents.iter().copied());
+            let mut rank = 0;
+            for (i, &bit) in contents.iter().enumerate() {
+                if !bit {
+                    rank += 1;
+                    assert_eq!(Some(i as u64), index.select0(rank));
+                }
+                assert_eq!(rank, index.rank0(i as u64));
+            }
+            assert_eq!(None, index.select0(rank + 1));
+            assert_eq!(None, index.select0(rank + 100));
+        }
+    }
+
+    #[test]
+    fn iter_ones_and_zeros_match_bits() {
+        for len in [0, 1, 63, 64, 65, 1000, 4099] {
+            let contents: Vec<bool> = (0..len).map(|n| n % 3 == 0 || n % 11 == 5).collect();
+            let index = BitIndex::from_bools(contents.iter().copied());
+
+            let positions = |bit: bool| -> Vec<u64> {
+                (0..len as u64)
+                    .filter(|&i| contents[i as usize] == bit)
+                    .collect()
+            };
+            assert_eq!(positions(true), index.iter_ones().collect::<Vec<_>>());
+            assert_eq!(positions(false), index.iter_zeros().collect::<Vec<_>>());
+        }
+
+        let full = BitIndex::from_bools(std::iter::repeat_n(true, 130));
+        assert_eq!(
+            (0..130).collect::<Vec<u64>>(),
+            full.iter_ones().collect::<Vec<_>>()
+        );
+        assert_eq!(0, full.iter_zeros().count());
+    }
+
+    #[test]
+    fn bitwise_operations_combine_bits() {
+        let len = 5_000;
+        let a_bits: Vec<bool> = (0..len).map(|n| n % 3 == 0).collect();
+        let b_bits: Vec<bool> = (0..len).map(|n| n % 5 == 0 || n > 4_000).collect();
+        let a = BitIndex::from_bools(a_bits.iter().copied());
+        let b = BitIndex::from_bools(b_bits.iter().copied());
+
+        let and = a.and(&b).unwrap();
+        let or = a.or(&b).unwrap();
+        let xor = a.xor(&b).unwrap();
+        let (mut and_rank, mut or_rank, mut xor_rank) = (0, 0, 0);
+        for i in 0..len {
+            let (x, y) = (a_bits[i], b_bits[i]);
+            and_rank += (x && y) as u64;
+            or_rank += (x || y) as u64;
+            xor_rank += (x != y) as u64;
+
+            assert_eq!(x && y, and.get(i as u64));
+            assert_eq!(x || y, or.get(i as u64));
+            assert_eq!(x != y, xor.get(i as u64));
+            assert_eq!(and_rank, and.rank1(i as u64));
+            assert_eq!(or_rank, or.rank1(i as u64));
+            assert_eq!(xor_rank, xor.rank1(i as u64));
+        }
+        assert_eq!(len, xor.len());
+        assert_eq!(None, and.select1(and_rank + 1));
+        assert_eq!(0, a.xor(&a).unwrap().iter_ones().count());
+    }
+
+    #[test]
+    fn bitwise_operations_reject_differing_lengths() {
+        let a = BitIndex::from_bools((0..100).map(|n| n % 2 == 0));
+        let b = BitIndex::from_bools((0..101).map(|n| n % 2 == 0));
+
+        assert!(matches!(
+            a.and(&b).err().unwrap(),
+            BitIndexError::LengthMismatch(100, 101)
+        ));
+        assert!(b.or(&a).is_err());
+        assert!(a.xor(&b).is_err());
+    }
+
+    #[test]
+    fn versioned_bitindex_checks_header() {
+        let contents: Vec<bool> = (0..1000).map(|n| n % 7 == 0).collect();
+        let mut bits = BitArrayBufBuilder::new(BytesMut::new());
+        bits.push_all(contents.iter().copied());
+        let bits = bits.finalize().freeze();
+        let mut blocks = BytesMut::new();
+        let mut sblocks = BytesMut::new();
+        build_bitindex_from_buf(&bits[..], &mut blocks, &mut sblocks);
+        let blocks = blocks.freeze();
+        let sblocks = sblocks.freeze();
+
+        let mut header = BytesMut::new();
+        write_bitindex_header(&mut header);
+        let index =
+            BitIndex::from_maps_with_header(&header, bits.clone(), blocks.clone(), sblocks.clone())
+                .unwrap();
+        assert_eq!(143, index.rank1(999));
+
+        let bumped = [BITINDEX_FORMAT_VERSION + 1];
+        let err =
+            BitIndex::from_maps_with_header(&bumped, bits.clone(), blocks.clone(), sblocks.clone())
+                .err()
+                .unwrap();
+        assert!(
+            matches!(err, BitIndexError::UnsupportedVersion(v) if v == BITINDEX_FORMAT_VERSION + 1)
+        );
+        assert_eq!(io::ErrorKind::InvalidData, io::Error::from(err).kind());
+
+        let err = BitIndex::from_maps_with_header(&[], bits, blocks, sblocks)
+            .err()
+            .unwrap();
+        assert!(matches!(err, BitIndexError::MissingHeader));
+    }
 }
@@ -0,0 +1,333 @@
+//! A delta encoded array of monotonically increasing integers.
+//!
+//! A `MonotonicLogArray` stores every element at the width of the largest one, even when the
+//! elements are close together. A `DeltaLogArray` instead stores the difference between each
+//! element and its predecessor, so the width only depends on the largest gap.
+//!
+//! Reconstructing an element means summing all deltas before it. To keep this cheap, the absolute
+//! value of every `stride`th element is stored in a second `LogArray` of samples. An element is
+//! then found by summing at most `stride - 1` deltas after its sample. The first delta is always
+//! 0, as the first element is kept in the first sample.
+//!
+//! A smaller stride makes lookups faster at the cost of a larger sample index. The index of an
+//! existing array can be rebuilt with a different stride using `build_sampled_index`.
+use bytes::{Bytes, BytesMut};
+
+use crate::util::calculate_width;
+
+use super::logarray::*;
+
+/// The default number of elements between two samples of absolute values.
+pub const DELTA_SAMPLE_RATE: usize = 64;
+
+/// A monotonically increasing array of integers, stored as the deltas between successive elements.
+#[derive(Clone, Debug)]
+pub struct DeltaLogArray {
+    deltas: LogArray,
+    samples: MonotonicLogArray,
+    stride: usize,
+}
+
+impl DeltaLogArray {
+    pub fn from_parts(deltas: LogArray, samples: LogArray, stride: usize) -> DeltaLogArray {
+        assert!(stride > 0, "expected a stride > 0");
+        debug_assert_eq!(deltas.len().div_ceil(stride), samples.len());
+        DeltaLogArray {
+            deltas,
+            samples: MonotonicLogArray::from_logarray(samples),
+            stride,
+        }
+    }
+
+    pub fn from_buffers(buffers: DeltaLogArrayBuffers) -> DeltaLogArray {
+        Self::parse(buffers.deltas, buffers.samples, buffers.stride).unwrap()
+    }
+
+    /// Parse an array from its buffers. The stride has to be the one the samples were built with.
+    pub fn parse(
+        deltas_slice: Bytes,
+        samples_slice: Bytes,
+        stride: usize,
+    ) -> Result<DeltaLogArray, LogArrayError> {
+        let deltas = LogArray::parse(deltas_slice)?;
+        let samples = LogArray::parse(samples_slice)?;
+
+        Ok(Self::from_parts(deltas, samples, stride))
+    }
+
+    /// Construct an array from an iterator over monotonically increasing values, with a sample
+    /// every `DELTA_SAMPLE_RATE` elements.
+    pub fn from_sorted_iter<I: IntoIterator<Item = u64>>(iter: I) -> DeltaLogArray {
+        Self::from_buffers(build_delta_log_array_bufs(iter, DELTA_SAMPLE_RATE))
+    }
+
+    /// Returns a copy of this array with a new sample index, sampling every `stride`th element.
+    ///
+    /// The deltas are shared with this array, only the samples are rebuilt.
+    pub fn build_sampled_index(&self, stride: usize) -> DeltaLogArray {
+        assert!(stride > 0, "expected a stride > 0");
+        let max = self.len().checked_sub(1).map_or(0, |last| self.entry(last));
+        let samples = build_samples(self.iter(), max, stride);
+
+        Self::from_parts(
+            self.deltas.clone(),
+            LogArray::parse(samples).unwrap(),
+            stride,
+        )
+    }
+
+    /// Returns the number of elements between two samples.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the size in bytes of the sample index.
+    pub fn index_size(&self) -> usize {
+        logarray_length_from_len_width(self.samples.len() as u64, self.samples.width())
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Returns the element at `index`, reconstructed from the nearest preceding sample.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn entry(&self, index: usize) -> u64 {
+        self.get(index).unwrap_or_else(|| {
+            panic!(
+                "expected index ({}) < length ({})",
+                index,
+                self.deltas.len()
+            )
+        })
+    }
+
+    /// Returns the element at `index`, or `None` if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> Option<u64> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let anchor = index / self.stride;
+        let start = anchor * self.stride;
+        let sample = self.samples.entry(anchor);
+
+        Some(
+            self.deltas
+                .iter_range(start + 1, index + 1)
+                .fold(sample, |value, delta| value + delta),
+        )
+    }
+
+    /// Returns an iterator over the elements in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        let first = self.samples.get(0).unwrap_or(0);
+        self.deltas.iter_ref().scan(first, |value, delta| {
+            *value += delta;
+            Some(*value)
+        })
+    }
+
+    /// Returns the index of an occurrence of `element`, or `None` if it does not occur.
+    ///
+    /// The samples are binary searched for the last one that is not larger than `element`, after
+    /// which at most `stride - 1` deltas are scanned.
+    pub fn index_of(&self, element: u64) -> Option<usize> {
+        let anchor = self.samples.upper_bound(element).checked_sub(1)?;
+        let start = anchor * self.stride;
+        let end = std::cmp::min(start + self.stride, self.len());
+
+        let mut value = self.samples.entry(anchor);
+        let mut index = start;
+        while value < element && index + 1 < end {
+            index += 1;
+            value += self.deltas.entry(index);
+        }
+
+        if value == element {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    pub fn deltas(&self) -> &LogArray {
+        &self.deltas
+    }
+
+    pub fn samples(&self) -> &MonotonicLogArray {
+        &self.samples
+    }
+}
+
+pub struct DeltaLogArrayBuffers {
+    pub deltas: Bytes,
+    pub samples: Bytes,
+    pub stride: usize,
+}
+
+/// Build a `LogArray` containing every `stride`th value, starting at the first.
+fn build_samples<I: Iterator<Item = u64>>(values: I, max: u64, stride: usize) -> Bytes {
+    let mut samples = LogArrayBufBuilder::new(BytesMut::new(), calculate_width(max));
+    for value in values.step_by(stride) {
+        samples.push(value);
+    }
+
+    samples.finalize().freeze()
+}
+
+/// Build the buffers for a `DeltaLogArray` from an iterator over monotonically increasing values,
+/// with a sample every `stride` elements.
+pub fn build_delta_log_array_bufs<I: IntoIterator<Item = u64>>(
+    iter: I,
+    stride: usize,
+) -> DeltaLogArrayBuffers {
+    assert!(stride > 0, "expected a stride > 0");
+    let values: Vec<u64> = iter.into_iter().collect();
+    let mut max_delta = 0;
+    for window in values.windows(2) {
+        assert!(
+            window[0] <= window[1],
+            "not monotonic: expected predecessor ({}) <= successor ({})",
+            window[0],
+            window[1]
+        );
+        max_delta = std::cmp::max(max_delta, window[1] - window[0]);
+    }
+
+    let mut deltas = LogArrayBufBuilder::new(BytesMut::new(), calculate_width(max_delta));
+    let mut previous = values.first().copied().unwrap_or(0);
+    for &value in values.iter() {
+        deltas.push(value - previous);
+        previous = value;
+    }
+    let max = values.last().copied().unwrap_or(0);
+
+    DeltaLogArrayBuffers {
+        deltas: deltas.finalize().freeze(),
+        samples: build_samples(values.into_iter(), max, stride),
+        stride,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_values() -> Vec<u64> {
+        // large values with small gaps of varying size
+        (0..5000u64)
+            .map(|i| (1 << 40) + i * 3 + (i % 7) / 3)
+            .collect()
+    }
+
+    #[test]
+    fn delta_log_array_matches_source() {
+        let values = sample_values();
+        let array = DeltaLogArray::from_sorted_iter(values.iter().copied());
+
+        assert_eq!(values.len(), array.len());
+        assert_eq!(values, array.iter().collect::<Vec<_>>());
+        for (i, &v) in values.iter().enumerate() {
+            assert_eq!(v, array.entry(i));
+        }
+        assert_eq!(None, array.get(values.len()));
+    }
+
+    #[test]
+    fn delta_log_array_index_of_matches_monotonic_logarray() {
+        let values = sample_values();
+        let array = DeltaLogArray::from_sorted_iter(values.iter().copied());
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 41);
+        builder.push_vec(values.clone());
+        let monotonic = MonotonicLogArray::parse(builder.finalize().freeze()).unwrap();
+
+        assert_eq!(None, array.index_of(0));
+        for x in values[0] - 2..values[values.len() - 1] + 2 {
+            let index = array.index_of(x);
+            assert_eq!(monotonic.index_of(x).is_some(), index.is_some());
+            if let Some(index) = index {
+                assert_eq!(x, values[index]);
+            }
+        }
+    }
+
+    #[test]
+    fn delta_log_array_is_smaller_than_monotonic_logarray_for_small_gaps() {
+        let values = sample_values();
+        let buffers = build_delta_log_array_bufs(values.iter().copied(), DELTA_SAMPLE_RATE);
+
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 41);
+        builder.push_vec(values);
+        let logarray_size = builder.finalize().len();
+
+        assert_eq!(3, LogArray::parse(buffers.deltas.clone()).unwrap().width());
+        assert!(buffers.deltas.len() + buffers.samples.len() < logarray_size / 5);
+    }
+
+    #[test]
+    fn sampled_index_with_any_stride_matches_source() {
+        let values = sample_values();
+        let array = DeltaLogArray::from_sorted_iter(values.iter().copied());
+        assert_eq!(DELTA_SAMPLE_RATE, array.stride());
+
+        for stride in [1, 2, 7, 64, 1000, 5000, 10000] {
+            let resampled = array.build_sampled_index(stride);
+            assert_eq!(stride, resampled.stride());
+            assert_eq!(values.len().div_ceil(stride), resampled.samples().len());
+            for (i, &v) in values.iter().enumerate().step_by(13) {
+                assert_eq!(v, resampled.entry(i));
+                assert_eq!(Some(i), resampled.index_of(v));
+            }
+            assert_eq!(values, resampled.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn smaller_stride_gives_larger_index() {
+        let array = DeltaLogArray::from_sorted_iter(sample_values());
+        let sizes: Vec<usize> = [1, 8, 64, 512]
+            .iter()
+            .map(|&stride| array.build_sampled_index(stride).index_size())
+            .collect();
+
+        assert_eq!(array.index_size(), sizes[2]);
+        assert!(sizes.windows(2).all(|w| w[0] > w[1]));
+        // sampling every element takes as much space as a plain monotonic log array
+        assert_eq!(
+            logarray_length_from_len_width(5000, 41),
+            array.build_sampled_index(1).index_size()
+        );
+    }
+
+    #[test]
+    fn parse_with_stride_from_buffers() {
+        let values = sample_values();
+        let buffers = build_delta_log_array_bufs(values.iter().copied(), 10);
+        let array = DeltaLogArray::parse(buffers.deltas, buffers.samples, 10).unwrap();
+
+        assert_eq!(10, array.stride());
+        assert_eq!(values, array.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn empty_delta_log_array() {
+        let array = DeltaLogArray::from_sorted_iter(std::iter::empty());
+
+        assert!(array.is_empty());
+        assert_eq!(None, array.get(0));
+        assert_eq!(None, array.index_of(0));
+        assert_eq!(0, array.iter().count());
+    }
+
+    #[test]
+    #[should_panic(expected = "not monotonic: expected predecessor (5) <= successor (4)")]
+    fn delta_log_array_rejects_decreasing_values() {
+        DeltaLogArray::from_sorted_iter(vec![1, 5, 4]);
+    }
+}
@@ -0,0 +1,370 @@
+//! An Elias-Fano encoded set of integers, supporting membership, rank and select queries.
+//!
+//! Every element is split into a low and a high part. The low parts are stored verbatim in a
+//! `LogArray`. The high parts are stored in unary in a bitarray: the element with index `i` and
+//! high part `h` sets bit `h + i`. This bitarray gets a `BitIndex`, so that the start of each
+//! bucket of high parts can be found using `select0`.
+//!
+//! The number of low bits is chosen as `floor(log2(universe / len))`, but is at least 1, as a
+//! `LogArray` needs a width of at least one bit. This means the amount of low bits can always be
+//! recovered from the width of the low `LogArray`.
+use std::io;
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::AsyncWriteExt;
+
+use crate::storage::SyncableFile;
+
+use super::bitarray::*;
+use super::bitindex::*;
+use super::logarray::*;
+
+/// A sorted set of integers in Elias-Fano encoding.
+#[derive(Clone)]
+pub struct EliasFanoSet {
+    low: LogArray,
+    high: BitIndex,
+}
+
+impl EliasFanoSet {
+    pub fn from_parts(low: LogArray, high: BitIndex) -> EliasFanoSet {
+        debug_assert_eq!(low.len() as u64, high.rank1(high.len() as u64 - 1));
+        EliasFanoSet { low, high }
+    }
+
+    pub fn from_buffers(buffers: EliasFanoSetBuffers) -> EliasFanoSet {
+        Self::parse(
+            buffers.low,
+            buffers.high,
+            buffers.high_blocks,
+            buffers.high_sblocks,
+        )
+    }
+
+    pub fn parse(
+        low_slice: Bytes,
+        high_slice: Bytes,
+        high_block_slice: Bytes,
+        high_sblock_slice: Bytes,
+    ) -> EliasFanoSet {
+        let low = LogArray::parse(low_slice).unwrap();
+        let high = BitIndex::from_maps(high_slice, high_block_slice, high_sblock_slice);
+
+        Self::from_parts(low, high)
+    }
+
+    /// Construct a set from an iterator over strictly increasing values.
+    pub fn from_sorted_iter<I: IntoIterator<Item = u64>>(iter: I) -> EliasFanoSet {
+        Self::from_buffers(build_elias_fano_set_bufs(iter))
+    }
+
+    /// Returns the amount of elements in the set.
+    pub fn len(&self) -> usize {
+        self.low.len()
+    }
+
+    /// Returns `true` if the set has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.low.len() == 0
+    }
+
+    fn low_bits(&self) -> u8 {
+        self.low.width()
+    }
+
+    /// Returns the amount of elements with a high part smaller than `high`.
+    fn bucket_start(&self, high: u64) -> u64 {
+        if high == 0 {
+            0
+        } else {
+            // The bucket for `high` starts right after the `high`th 0-bit.
+            self.high.select0(high).unwrap() + 1 - high
+        }
+    }
+
+    /// Returns the amount of elements smaller than `value`.
+    pub fn rank(&self, value: u64) -> u64 {
+        let low_bits = self.low_bits();
+        let high = value >> low_bits;
+        let low = value & ((1 << low_bits) - 1);
+        // The high bitarray ends in a 0-bit, so the number of 0-bits is the number of buckets.
+        let num_buckets = self.high.len() as u64 - self.len() as u64;
+        if high >= num_buckets {
+            return self.len() as u64;
+        }
+
+        let start = self.bucket_start(high);
+        let end = self.bucket_start(high + 1);
+        let mut rank = start;
+        while rank < end && self.low.entry(rank as usize) < low {
+            rank += 1;
+        }
+
+        rank
+    }
+
+    /// Returns the `n`th smallest element, starting at 0.
+    pub fn select(&self, n: u64) -> Option<u64> {
+        if n >= self.len() as u64 {
+            return None;
+        }
+
+        let pos = self.high.select1(n + 1)?;
+        let high = pos - n;
+        Some((high << self.low_bits()) | self.low.entry(n as usize))
+    }
+
+    /// Returns `true` if the value is an element of the set.
+    pub fn contains(&self, value: u64) -> bool {
+        self.select(self.rank(value)) == Some(value)
+    }
+
+    /// Returns an iterator over the elements in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.len() as u64).map(move |n| self.select(n).unwrap())
+    }
+
+    pub fn low(&self) -> &LogArray {
+        &self.low
+    }
+
+    pub fn high(&self) -> &BitIndex {
+        &self.high
+    }
+}
+
+impl SuccinctSet for EliasFanoSet {
+    fn rank(&self, x: u64) -> u64 {
+        EliasFanoSet::rank(self, x)
+    }
+
+    fn select(&self, n: u64) -> Option<u64> {
+        EliasFanoSet::select(self, n)
+    }
+
+    fn contains(&self, x: u64) -> bool {
+        EliasFanoSet::contains(self, x)
+    }
+}
+
+pub struct EliasFanoSetBuffers {
+    low: Bytes,
+    high: Bytes,
+    high_blocks: Bytes,
+    high_sblocks: Bytes,
+}
+
+/// Build the buffers for an `EliasFanoSet` from an iterator over strictly increasing values.
+pub fn build_elias_fano_set_bufs<I: IntoIterator<Item = u64>>(iter: I) -> EliasFanoSetBuffers {
+    let values: Vec<u64> = iter.into_iter().collect();
+    for window in values.windows(2) {
+        assert!(
+            window[0] < window[1],
+            "expected strictly increasing values, but {} came before {}",
+            window[0],
+            window[1]
+        );
+    }
+
+    let len = values.len() as u64;
+    let universe = values.last().map(|&max| max.saturating_add(1)).unwrap_or(0);
+    let low_bits = if len == 0 || universe <= len {
+        1
+    } else {
+        std::cmp::max(1, 63 - (universe / len).leading_zeros() as u8)
+    };
+    let low_mask = (1 << low_bits) - 1;
+
+    let mut low_builder = LogArrayBufBuilder::new(BytesMut::new(), low_bits);
+    let mut high_builder = BitArrayBufBuilder::new(BytesMut::new());
+    let mut last_high = 0;
+    for &value in values.iter() {
+        let high = value >> low_bits;
+        for _ in last_high..high {
+            high_builder.push(false);
+        }
+        high_builder.push(true);
+        last_high = high;
+        low_builder.push(value & low_mask);
+    }
+    // terminate the final bucket
+    high_builder.push(false);
+
+    let low = low_builder.finalize().freeze();
+    let high = high_builder.finalize().freeze();
+    let mut high_blocks = BytesMut::new();
+    let mut high_sblocks = BytesMut::new();
+    build_bitindex_from_buf(&high[..], &mut high_blocks, &mut high_sblocks);
+
+    EliasFanoSetBuffers {
+        low,
+        high,
+        high_blocks: high_blocks.freeze(),
+        high_sblocks: high_sblocks.freeze(),
+    }
+}
+
+/// Build an `EliasFanoSet` from an iterator over strictly increasing values, writing it to the
+/// given files.
+pub async fn build_elias_fano_set<
+    I: IntoIterator<Item = u64>,
+    W1: SyncableFile,
+    W2: SyncableFile,
+    W3: SyncableFile,
+    W4: SyncableFile,
+>(
+    iter: I,
+    mut low: W1,
+    mut high: W2,
+    mut high_blocks: W3,
+    mut high_sblocks: W4,
+) -> io::Result<()> {
+    let buffers = build_elias_fano_set_bufs(iter);
+
+    low.write_all(&buffers.low).await?;
+    low.sync_all().await?;
+    high.write_all(&buffers.high).await?;
+    high.sync_all().await?;
+    high_blocks.write_all(&buffers.high_blocks).await?;
+    high_blocks.sync_all().await?;
+    high_sblocks.write_all(&buffers.high_sblocks).await?;
+    high_sblocks.sync_all().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{memory::MemoryBackedStore, FileLoad, FileStore};
+
+    fn sample_values() -> Vec<u64> {
+        // a dense-ish set with gaps of varying size
+        (0..5000u64).map(|i| i * 3 + (i % 7) / 3).collect()
+    }
+
+    #[test]
+    fn elias_fano_set_queries_match_source() {
+        let values = sample_values();
+        let set = EliasFanoSet::from_sorted_iter(values.iter().copied());
+
+        assert_eq!(values.len(), set.len());
+        assert_eq!(values, set.iter().collect::<Vec<_>>());
+
+        let max = *values.last().unwrap();
+        for v in 0..max + 10 {
+            let expected_rank = values.iter().filter(|&&x| x < v).count() as u64;
+            assert_eq!(expected_rank, set.rank(v));
+            assert_eq!(values.binary_search(&v).is_ok(), set.contains(v));
+        }
+        for (i, &v) in values.iter().enumerate() {
+            assert_eq!(Some(v), set.select(i as u64));
+        }
+        assert_eq!(None, set.select(values.len() as u64));
+    }
+
+    #[test]
+    fn sparse_elias_fano_set_queries_match_source() {
+        let values: Vec<u64> = vec![3, 1000, 1001, 65536, 1 << 40];
+        let set = EliasFanoSet::from_sorted_iter(values.iter().copied());
+
+        assert_eq!(values, set.iter().collect::<Vec<_>>());
+        for &v in values.iter() {
+            assert!(set.contains(v));
+            assert!(!set.contains(v + 1) || values.contains(&(v + 1)));
+        }
+        assert_eq!(0, set.rank(3));
+        assert_eq!(1, set.rank(4));
+        assert_eq!(3, set.rank(60000));
+        assert_eq!(5, set.rank(u64::MAX >> 1));
+    }
+
+    #[test]
+    fn succinct_set_matches_monotonic_logarray() {
+        let values = sample_values();
+        let set = EliasFanoSet::from_sorted_iter(values.iter().copied());
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 15);
+        builder.push_vec(values.clone());
+        let monotonic = MonotonicLogArray::parse(builder.finalize().freeze()).unwrap();
+
+        for x in 0..200 {
+            assert_eq!(SuccinctSet::rank(&monotonic, x), SuccinctSet::rank(&set, x));
+            assert_eq!(
+                SuccinctSet::select(&monotonic, x),
+                SuccinctSet::select(&set, x)
+            );
+            assert_eq!(
+                SuccinctSet::contains(&monotonic, x),
+                SuccinctSet::contains(&set, x)
+            );
+        }
+    }
+
+    #[test]
+    fn inherent_and_succinct_set_methods_agree() {
+        let values = sample_values();
+        let set = EliasFanoSet::from_sorted_iter(values.iter().copied());
+
+        let max = *values.last().unwrap();
+        for x in 0..max + 10 {
+            assert_eq!(set.rank(x), SuccinctSet::rank(&set, x));
+            assert_eq!(set.select(x), SuccinctSet::select(&set, x));
+            assert_eq!(set.contains(x), SuccinctSet::contains(&set, x));
+        }
+    }
+
+    #[test]
+    fn empty_elias_fano_set() {
+        let set = EliasFanoSet::from_sorted_iter(std::iter::empty());
+
+        assert!(set.is_empty());
+        assert!(!set.contains(0));
+        assert_eq!(0, set.rank(100));
+        assert_eq!(None, set.select(0));
+    }
+
+    #[test]
+    fn elias_fano_set_is_smaller_than_monotonic_logarray_for_dense_sets() {
+        let values = sample_values();
+        let buffers = build_elias_fano_set_bufs(values.iter().copied());
+        let elias_fano_size = buffers.low.len()
+            + buffers.high.len()
+            + buffers.high_blocks.len()
+            + buffers.high_sblocks.len();
+
+        let width = crate::util::calculate_width(*values.last().unwrap());
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), width);
+        builder.push_vec(values);
+        let logarray_size = builder.finalize().len();
+
+        assert!(elias_fano_size < logarray_size);
+    }
+
+    #[tokio::test]
+    async fn build_and_load_elias_fano_set_from_files() {
+        let values = sample_values();
+        let low = MemoryBackedStore::new();
+        let high = MemoryBackedStore::new();
+        let high_blocks = MemoryBackedStore::new();
+        let high_sblocks = MemoryBackedStore::new();
+
+        build_elias_fano_set(
+            values.iter().copied(),
+            low.open_write().await.unwrap(),
+            high.open_write().await.unwrap(),
+            high_blocks.open_write().await.unwrap(),
+            high_sblocks.open_write().await.unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let set = EliasFanoSet::parse(
+            low.map().await.unwrap(),
+            high.map().await.unwrap(),
+            high_blocks.map().await.unwrap(),
+            high_sblocks.map().await.unwrap(),
+        );
+
+        assert_eq!(values, set.iter().collect::<Vec<_>>());
+    }
+}
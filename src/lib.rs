@@ -6,6 +6,8 @@ pub mod adjacencylist;
 pub mod bitarray;
 pub mod bitindex;
 pub mod bititer;
+pub mod deltalogarray;
+pub mod eliasfano;
 pub mod logarray;
 pub mod smallbitarray;
 //pub mod mapped_dict;
@@ -20,6 +22,8 @@ pub use adjacencylist::*;
 pub use bitarray::*;
 pub use bitindex::*;
 pub use decimal::{Decimal, DecimalValidationError};
+pub use deltalogarray::*;
+pub use eliasfano::*;
 pub use logarray::*;
 pub use tfc::*;
 pub use wavelettree::*;
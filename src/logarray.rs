@@ -23,6 +23,9 @@
 //! * All integers are stored in a standard big-endian encoding.
 //! * The maximum bit width W is 64.
 //! * The maximum number of elements is 2^32-1.
+//! * Bit indexes can exceed 2^32, so they are calculated in `u64` regardless of the target. On a
+//!   32-bit target, the data buffer itself must still be addressable, which limits W * N to less
+//!   than 2^35 bits.
 //!
 //! # Naming
 //!
@@ -51,15 +54,22 @@
 
 use crate::storage::{FileLoad, SyncableFile};
 
-use super::util::{self, calculate_width};
+use super::bitarray::BitArrayBufBuilder;
+use super::util::{self, calculate_width, Crc32};
 use byteorder::{BigEndian, ByteOrder};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use futures::stream::{Stream, StreamExt};
+use futures::future;
+use futures::stream::{self, Stream, StreamExt};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use std::{cmp::Ordering, convert::TryFrom, error, fmt, io};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWriteExt, ReadBuf};
 use tokio_util::codec::{Decoder, FramedRead};
 
-use itertools::Itertools;
+use itertools::{EitherOrBoth, Itertools};
 
 // Static assertion: We expect the system architecture bus width to be >= 32 bits. If it is not,
 // the following line will cause a compiler error. (Ignore the unrelated error message itself.)
@@ -89,18 +99,114 @@ pub struct LogArray {
     input_buf: Bytes,
 }
 
+/// Number of elements shown at each end of a log array in its `Debug` output, unless overridden
+/// by the formatter's precision.
+const DEBUG_EDGE_ELEMENTS: usize = 8;
+
+/// Write the `Debug` output for a log array, eliding the middle if it is large.
+///
+/// At most `n` elements are shown at the start and at the end of the array, where `n` is the
+/// formatter's precision if set, or `DEBUG_EDGE_ELEMENTS` otherwise.
+fn fmt_logarray_debug(
+    name: &str,
+    logarray: &LogArray,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    let edge = f.precision().unwrap_or(DEBUG_EDGE_ELEMENTS);
+    let len = logarray.len();
+    if len <= edge * 2 {
+        return write!(f, "{}([{}])", name, logarray.iter().format(", "));
+    }
+
+    write!(
+        f,
+        "{}([{}, ..., {}] len={} width={})",
+        name,
+        logarray.iter().take(edge).format(", "),
+        logarray.slice(len - edge, edge).iter().format(", "),
+        len,
+        logarray.width()
+    )
+}
+
 impl std::fmt::Debug for LogArray {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "LogArray([{}])", self.iter().format(", "))
+        fmt_logarray_debug("LogArray", self, f)
+    }
+}
+
+impl Default for LogArray {
+    /// Returns an empty log array, consisting of only an all-zero control word.
+    fn default() -> Self {
+        LogArray::parse(Bytes::from_static(&[0; 8])).unwrap()
+    }
+}
+
+/// Log arrays are equal if they have the same width and elements, regardless of how they are
+/// stored. Slices compare equal to log arrays of their elements, and padding bits are ignored.
+impl PartialEq for LogArray {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.width == other.width && self.iter_ref().eq(other.iter_ref())
+    }
+}
+
+impl Eq for LogArray {}
+
+impl Hash for LogArray {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        self.width.hash(state);
+        for val in self.iter_ref() {
+            val.hash(state);
+        }
+    }
+}
+
+impl FromIterator<u64> for LogArray {
+    /// Builds a log array in memory, with the minimal width needed for the largest value.
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        Self::build_in_memory(iter).unwrap()
     }
 }
 
+/// An element read from a log array, along with the bit width of the array it came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Element {
+    pub value: u64,
+    pub width: u8,
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (width {})", self.value, self.width)
+    }
+}
+
+/// The result of analyzing how well a log array would compress with delta encoding.
+///
+/// Deltas are taken between consecutive elements, with the first element taken relative to 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeltaReport {
+    /// Whether the elements are non-decreasing, so that all deltas are non-negative.
+    pub is_monotonic: bool,
+    /// The width needed to store every delta. If the array is not monotonic, this includes a
+    /// sign bit, as the deltas would have to be zigzag encoded.
+    pub delta_width: u8,
+    /// The width of the analyzed log array.
+    pub original_width: u8,
+    /// The size in bytes of a delta encoded log array, including the control word.
+    pub estimated_bytes: usize,
+}
+
 /// An error that occurred during a log array operation.
 #[derive(Debug, PartialEq)]
 pub enum LogArrayError {
     InputBufferTooSmall(usize),
     WidthTooLarge(u8),
     UnexpectedInputBufferSize(u64, u64, u64, u8),
+    ValueExceedsBound(u64, u64),
+    ValueOutsideUniverse(u64, u64),
+    ValueTooWide(u64, u8),
 }
 
 impl LogArrayError {
@@ -189,6 +295,15 @@ impl fmt::Display for LogArrayError {
                 "expected input buffer size ({}) to be {} for {} elements and width {}",
                 input_buf_size, expected_buf_size, len, width
             ),
+            ValueExceedsBound(value, bound) => {
+                write!(f, "expected value ({}) <= bound ({})", value, bound)
+            }
+            ValueOutsideUniverse(value, universe) => {
+                write!(f, "expected value ({}) < universe ({})", value, universe)
+            }
+            ValueTooWide(value, width) => {
+                write!(f, "expected value ({}) to fit in {} bits", value, width)
+            }
         }
     }
 }
@@ -220,6 +335,51 @@ impl Iterator for LogArrayIterator {
             Some(result)
         }
     }
+
+    fn nth(&mut self, n: usize) -> Option<u64> {
+        // skip ahead without decoding the skipped elements
+        self.pos = self.pos.saturating_add(n).min(self.end);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+/// An iterator over the elements of a borrowed `LogArray`.
+///
+/// Unlike `LogArrayIterator`, this does not clone the array, but can't outlive it either.
+#[derive(Clone)]
+pub struct LogArrayRefIterator<'a> {
+    logarray: &'a LogArray,
+    pos: usize,
+    end: usize,
+}
+
+impl Iterator for LogArrayRefIterator<'_> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.pos == self.end {
+            None
+        } else {
+            let result = self.logarray.entry(self.pos);
+            self.pos += 1;
+
+            Some(result)
+        }
+    }
+
+    fn nth(&mut self, n: usize) -> Option<u64> {
+        self.pos = self.pos.saturating_add(n).min(self.end);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end - self.pos;
+        (remaining, Some(remaining))
+    }
 }
 
 const MAX_LOGARRAY_LEN: u64 = (1 << 56) - 1;
@@ -252,12 +412,20 @@ fn read_control_word_trailing(
     Ok((len, width))
 }
 
-fn logarray_length_from_len_width(len: u64, width: u8) -> usize {
-    let num_bits = width as usize * len as usize;
+/// Returns the amount of bytes needed for the data of a logarray, not including the control word.
+///
+/// Panics if the size does not fit in a `usize`, which is only possible on 32-bit targets.
+pub fn logarray_length_from_len_width(len: u64, width: u8) -> usize {
+    let num_bits = u64::from(width) * len;
     let num_u64 = num_bits / 64 + (if num_bits % 64 == 0 { 0 } else { 1 });
     let num_bytes = num_u64 * 8;
 
-    num_bytes
+    usize::try_from(num_bytes).unwrap_or_else(|_| {
+        panic!(
+            "size of log array data for length ({}) and width ({}) overflows usize",
+            len, width
+        )
+    })
 }
 
 pub fn logarray_length_from_control_word(buf: &[u8]) -> usize {
@@ -280,6 +448,59 @@ impl LogArray {
         })
     }
 
+    /// Builds a log array in memory from a slice of values, with the minimal width needed for the
+    /// largest value.
+    ///
+    /// This is the fallible counterpart of collecting into a `LogArray`.
+    pub fn try_from_slice(vals: &[u64]) -> Result<LogArray, LogArrayError> {
+        Self::build_in_memory(vals.iter().copied())
+    }
+
+    fn build_in_memory<I: IntoIterator<Item = u64>>(iter: I) -> Result<LogArray, LogArrayError> {
+        let mut builder = LateLogArrayBufBuilder::new(BytesMut::new());
+        for val in iter {
+            builder.push(val);
+        }
+
+        LogArray::parse(builder.finalize().freeze())
+    }
+
+    /// Builds a log array in memory from a slice of values like `try_from_slice`, but encodes the
+    /// values on multiple threads.
+    ///
+    /// Every run of 64 values fills a whole number of words, so the values are split into runs
+    /// of such runs, which are encoded into their own part of the buffer in parallel. The result
+    /// is identical to building the log array sequentially.
+    #[cfg(feature = "rayon")]
+    pub fn from_slice_parallel(vals: &[u64]) -> LogArray {
+        use rayon::prelude::*;
+
+        // values per chunk, which has to be a multiple of 64 for chunks to start on a word
+        const CHUNK_LEN: usize = 64 * 1024;
+
+        let width = vals
+            .par_iter()
+            .map(|&val| calculate_width(val))
+            .max()
+            .unwrap_or(0);
+        let data_len = logarray_length_from_len_width(vals.len() as u64, width);
+        let chunk_data_len = CHUNK_LEN / 8 * width as usize;
+
+        let mut buf = BytesMut::zeroed(data_len);
+        buf.par_chunks_mut(chunk_data_len.max(1))
+            .zip(vals.par_chunks(CHUNK_LEN))
+            .for_each(|(chunk_buf, chunk)| {
+                let mut builder = LogArrayBufBuilder::new(chunk_buf, width);
+                for &val in chunk {
+                    builder.push(val);
+                }
+                builder.finalize_without_control_word();
+            });
+        buf.put_slice(&control_word(vals.len() as u64, width));
+
+        LogArray::parse(buf.freeze()).unwrap()
+    }
+
     pub fn parse_header_first(mut input_buf: Bytes) -> Result<(LogArray, Bytes), LogArrayError> {
         let input_buf_size = input_buf.len();
         LogArrayError::validate_input_buf_size(input_buf_size)?;
@@ -298,6 +519,24 @@ impl LogArray {
         ))
     }
 
+    /// Parse `count` header-first log arrays which are stored one after the other in `buf`.
+    ///
+    /// Returns an error if `buf` runs out before all arrays are parsed. Anything remaining after
+    /// the last array is ignored.
+    pub fn parse_all_header_first(
+        mut buf: Bytes,
+        count: usize,
+    ) -> Result<Vec<LogArray>, LogArrayError> {
+        let mut result = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (logarray, rest) = Self::parse_header_first(buf)?;
+            result.push(logarray);
+            buf = rest;
+        }
+
+        Ok(result)
+    }
+
     /// Returns the number of elements.
     pub fn len(&self) -> usize {
         // `usize::try_from` succeeds if `std::mem::size_of::<usize>()` >= 4.
@@ -314,22 +553,102 @@ impl LogArray {
         self.width
     }
 
+    /// Returns the index in the original log array of the first element of this one.
+    ///
+    /// This is 0 for a log array that was parsed from a buffer, and the offset into the original
+    /// for a slice, so that `start_offset() + index` maps an index in a slice back to the
+    /// original.
+    pub fn start_offset(&self) -> usize {
+        self.first as usize
+    }
+
+    /// Returns the length in bytes of the underlying buffer, including the control word.
+    ///
+    /// Slices share the buffer of the log array they were created from, so this is the length of
+    /// the whole original buffer rather than just the part covered by the slice.
+    pub fn buffer_len(&self) -> usize {
+        self.input_buf.len()
+    }
+
     /// Reads the data buffer and returns the element at the `index`.
     ///
     /// Panics if `index` is >= the length of the log array.
     pub fn entry(&self, index: usize) -> u64 {
-        debug_assert!(
-            index < self.len(),
-            "expected index ({}) < length ({})",
-            index,
-            self.len
-        );
+        self.get(index)
+            .unwrap_or_else(|| panic!("expected index ({}) < length ({})", index, self.len))
+    }
 
-        // `usize::try_from` succeeds if `std::mem::size_of::<usize>()` >= 4.
-        let bit_index = usize::from(self.width) * (usize::try_from(self.first).unwrap() + index);
+    /// Returns the element at the `index`, or `None` if `index` is >= the length of the log array.
+    pub fn get(&self, index: usize) -> Option<u64> {
+        if index < self.len() {
+            Some(self.decode(index))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the element at the `index`, or `default` if `index` is >= the length of the log
+    /// array.
+    pub fn entry_or(&self, index: usize, default: u64) -> u64 {
+        self.get(index).unwrap_or(default)
+    }
+
+    /// Decodes the elements at all `indices` and appends them to `out`, in the same order.
+    ///
+    /// Consecutive indices whose elements lie in the same 64-bit word share a single read of that
+    /// word. `indices` need not be sorted, but sorted or clustered indices perform better, as more
+    /// reads are shared.
+    ///
+    /// Panics if any index is >= the length of the log array.
+    pub fn entries_into(&self, indices: &[usize], out: &mut Vec<u64>) {
+        out.reserve(indices.len());
+
+        let buf = &self.input_buf;
+        let leading_zeros = 64 - self.width;
+        // The byte index and contents of the most recently read word.
+        let mut cached: Option<(usize, u64)> = None;
+        let mut read_word = |byte_index: usize| match cached {
+            Some((cached_index, word)) if cached_index == byte_index => word,
+            _ => {
+                let word = BigEndian::read_u64(&buf[byte_index..]);
+                cached = Some((byte_index, word));
+                word
+            }
+        };
+
+        for &index in indices {
+            if index >= self.len() {
+                panic!("expected index ({}) < length ({})", index, self.len);
+            }
+
+            // This follows `decode`, but goes through the cached word.
+            let bit_index = u64::from(self.width) * (self.first + index as u64);
+            let byte_index = usize::try_from(bit_index >> 6 << 3).unwrap();
+            let offset = (bit_index & 0b11_1111) as u8;
+            let first_word = read_word(byte_index);
+
+            if offset + self.width <= 64 {
+                out.push(first_word << offset >> leading_zeros);
+                continue;
+            }
 
-        // Calculate the byte index from the bit index.
-        let byte_index = bit_index >> 6 << 3;
+            let second_word = read_word(byte_index + 8);
+            let first_width = 64 - offset;
+            let second_width = self.width - first_width;
+            let first_part = first_word << offset >> offset << second_width;
+            let second_part = second_word >> 64 - second_width;
+            out.push(first_part | second_part);
+        }
+    }
+
+    /// Decodes the element at the `index`, which must be within bounds.
+    fn decode(&self, index: usize) -> u64 {
+        // The bit index may not fit in a 32-bit `usize`, so it is calculated in `u64`.
+        let bit_index = u64::from(self.width) * (self.first + index as u64);
+
+        // Calculate the byte index from the bit index. This is an index into `input_buf`, so it
+        // always fits in a `usize`.
+        let byte_index = usize::try_from(bit_index >> 6 << 3).unwrap();
 
         let buf = &self.input_buf;
 
@@ -371,6 +690,145 @@ impl LogArray {
         first_part | second_part
     }
 
+    /// Reads the data buffer and returns the element at the `index`, together with the bit width
+    /// of this log array.
+    ///
+    /// Panics if `index` is >= the length of the log array.
+    pub fn typed_entry(&self, index: usize) -> Element {
+        Element {
+            value: self.entry(index),
+            width: self.width,
+        }
+    }
+
+    /// Returns an iterator over every `stride`th element, starting with the first.
+    ///
+    /// Skipped elements are not decoded.
+    ///
+    /// Panics if `stride` is 0.
+    pub fn sample(&self, stride: usize) -> impl Iterator<Item = u64> + '_ {
+        assert!(stride != 0, "expected stride to be greater than 0");
+        self.iter().step_by(stride)
+    }
+
+    /// Returns an iterator over the starts of the runs of equal elements, as `(index, value)`.
+    ///
+    /// The first element always starts a run.
+    pub fn transitions(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        let mut previous = None;
+        self.iter().enumerate().filter(move |&(_, value)| {
+            let is_transition = previous != Some(value);
+            previous = Some(value);
+            is_transition
+        })
+    }
+
+    /// Decodes all elements into a `Vec<u64>`, allocated with exactly the capacity needed.
+    ///
+    /// This is the same as `decode_all`.
+    pub fn to_vec(&self) -> Vec<u64> {
+        self.decode_all()
+    }
+
+    /// Decodes all elements into a `Vec<u64>`, reading the buffer a word at a time.
+    ///
+    /// Unlike `iter` or `entry`, which decode every element on its own, this reads every word
+    /// only once. For widths that divide 64, no element is split over two words, so each word is
+    /// unpacked with a fixed set of shifts. Other widths go through a 128-bit accumulator, which
+    /// always holds the next element in full.
+    pub fn decode_all(&self) -> Vec<u64> {
+        let len = self.len();
+        let mut result = Vec::with_capacity(len);
+        if len == 0 {
+            return result;
+        }
+
+        let buf = &self.input_buf;
+        let width = u32::from(self.width);
+        let mask = u64::MAX >> (64 - width);
+        let start_bit = u64::from(self.width) * self.first;
+        let mut byte_index = usize::try_from(start_bit >> 6 << 3).unwrap();
+        // The number of bits at the start of the first word that belong to earlier elements.
+        let skip = (start_bit & 0b11_1111) as u32;
+
+        if 64 % width == 0 {
+            let mut shift = 64 - skip;
+            while result.len() < len {
+                let word = BigEndian::read_u64(&buf[byte_index..]);
+                byte_index += 8;
+                while shift >= width && result.len() < len {
+                    shift -= width;
+                    result.push(word >> shift & mask);
+                }
+                shift = 64;
+            }
+        } else {
+            // The lowest `available` bits of `acc` are the bits that have not been decoded yet.
+            // Bits above those are left over from earlier elements, and masked off on output.
+            let mut acc = u128::from(BigEndian::read_u64(&buf[byte_index..]));
+            byte_index += 8;
+            let mut available = 64 - skip;
+            while result.len() < len {
+                if available < width {
+                    acc = acc << 64 | u128::from(BigEndian::read_u64(&buf[byte_index..]));
+                    byte_index += 8;
+                    available += 64;
+                }
+                available -= width;
+                result.push((acc >> available) as u64 & mask);
+            }
+        }
+
+        result
+    }
+
+    /// Decodes all elements into a `Vec<u32>`, or returns `None` if the width is larger than 32.
+    pub fn to_vec_u32(&self) -> Option<Vec<u32>> {
+        if self.width > 32 {
+            return None;
+        }
+
+        Some(self.iter().map(|v| v as u32).collect())
+    }
+
+    /// Decodes all elements into a `Vec<u16>`, or returns `None` if the width is larger than 16.
+    pub fn to_vec_u16(&self) -> Option<Vec<u16>> {
+        if self.width > 16 {
+            return None;
+        }
+
+        Some(self.iter().map(|v| v as u16).collect())
+    }
+
+    /// Analyzes the deltas between consecutive elements in a single scan.
+    pub fn analyze_delta(&self) -> DeltaReport {
+        let mut is_monotonic = true;
+        let mut max_delta = 0;
+        let mut previous = 0;
+        for value in self.iter() {
+            let delta = if value >= previous {
+                value - previous
+            } else {
+                is_monotonic = false;
+                previous - value
+            };
+            max_delta = max_delta.max(delta);
+            previous = value;
+        }
+
+        let mut delta_width = calculate_width(max_delta);
+        if !is_monotonic {
+            delta_width = (delta_width + 1).min(64);
+        }
+
+        DeltaReport {
+            is_monotonic,
+            delta_width,
+            original_width: self.width,
+            estimated_bytes: logarray_length_from_len_width(self.len, delta_width) + 8,
+        }
+    }
+
     pub fn iter(&self) -> LogArrayIterator {
         LogArrayIterator {
             logarray: self.clone(),
@@ -379,6 +837,217 @@ impl LogArray {
         }
     }
 
+    /// Returns an iterator over the elements from index `start` up to (but not including) `end`.
+    ///
+    /// Panics if `start > end` or `end > self.len()`.
+    pub fn iter_range(&self, start: usize, end: usize) -> LogArrayIterator {
+        assert!(
+            start <= end,
+            "expected range start ({}) <= range end ({})",
+            start,
+            end
+        );
+        assert!(
+            end <= self.len(),
+            "expected range end ({}) <= length ({})",
+            end,
+            self.len
+        );
+
+        LogArrayIterator {
+            logarray: self.clone(),
+            pos: start,
+            end,
+        }
+    }
+
+    /// Returns an iterator over the elements which borrows this array rather than cloning it.
+    pub fn iter_ref(&self) -> LogArrayRefIterator<'_> {
+        LogArrayRefIterator {
+            logarray: self,
+            pos: 0,
+            end: self.len(),
+        }
+    }
+
+    /// Fold over all elements in order, decoding them in place rather than through a
+    /// `LogArrayIterator`.
+    pub fn fold<T, F: FnMut(T, u64) -> T>(&self, init: T, mut f: F) -> T {
+        let mut acc = init;
+        for index in 0..self.len() {
+            acc = f(acc, self.entry(index));
+        }
+
+        acc
+    }
+
+    /// Returns the largest element, or `None` if the array is empty.
+    ///
+    /// This scans the whole array. For a `MonotonicLogArray`, use its constant time `max` instead.
+    pub fn max(&self) -> Option<u64> {
+        self.fold(None, |max, value| {
+            Some(max.map_or(value, |m: u64| m.max(value)))
+        })
+    }
+
+    /// Returns the smallest element, or `None` if the array is empty.
+    ///
+    /// This scans the whole array. For a `MonotonicLogArray`, use its constant time `min` instead.
+    pub fn min(&self) -> Option<u64> {
+        self.fold(None, |min, value| {
+            Some(min.map_or(value, |m: u64| m.min(value)))
+        })
+    }
+
+    /// Builds a log array into `buf` from a list of `(value, count)` runs, each of which is
+    /// expanded into `count` copies of `value`.
+    ///
+    /// The width is the minimal width needed for the values of the non-empty runs.
+    pub fn from_runs<I: IntoIterator<Item = (u64, u64)>, B: BufMut>(runs: I, buf: B) -> B {
+        let runs: Vec<(u64, u64)> = runs.into_iter().filter(|&(_, count)| count != 0).collect();
+        let max = runs.iter().map(|&(value, _)| value).max().unwrap_or(0);
+
+        let mut builder = LogArrayBufBuilder::new(buf, calculate_width(max));
+        for (value, count) in runs {
+            for _ in 0..count {
+                builder.push(value);
+            }
+        }
+
+        builder.finalize()
+    }
+
+    /// Builds a log array into `buf` from values which are all known to be at most `max_allowed`.
+    ///
+    /// The width is derived from `max_allowed` rather than from the values themselves, so arrays
+    /// built with the same bound always have the same width. Returns an error on the first value
+    /// exceeding the bound, in which case the buffer is discarded.
+    pub fn build_bounded<I: Iterator<Item = u64>, B: BufMut>(
+        max_allowed: u64,
+        vals: I,
+        buf: B,
+    ) -> Result<B, LogArrayError> {
+        let mut builder = LogArrayBufBuilder::new(buf, calculate_width(max_allowed));
+        for val in vals {
+            if val > max_allowed {
+                return Err(LogArrayError::ValueExceedsBound(val, max_allowed));
+            }
+            builder.push(val);
+        }
+
+        Ok(builder.finalize())
+    }
+
+    /// Builds a log array of `len` pseudo-random values in the range `0..=max`, generated from
+    /// `seed`.
+    ///
+    /// The output only depends on the arguments, so a failing array found by a randomized test
+    /// can be rebuilt from its seed alone.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn build_from_seed(seed: u64, len: usize, max: u64) -> Bytes {
+        // splitmix64, which is simple and good enough for generating test data
+        let mut state = seed;
+        let mut next = move || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), calculate_width(max));
+        for _ in 0..len {
+            let value = match max.checked_add(1) {
+                Some(bound) => next() % bound,
+                None => next(),
+            };
+            builder.push(value);
+        }
+
+        builder.finalize().freeze()
+    }
+
+    /// Push a bit for every value in `0..universe` to `builder`, which is set if the value occurs
+    /// in this array.
+    ///
+    /// The elements don't have to be sorted or distinct. If an element is not below `universe`,
+    /// an error is returned and nothing is pushed.
+    pub fn to_presence_bitmap<B: BufMut>(
+        &self,
+        universe: u64,
+        builder: &mut BitArrayBufBuilder<B>,
+    ) -> Result<(), LogArrayError> {
+        let mut values = self.to_vec();
+        if let Some(&value) = values.iter().find(|&&value| value >= universe) {
+            return Err(LogArrayError::ValueOutsideUniverse(value, universe));
+        }
+        values.sort_unstable();
+        values.dedup();
+
+        let mut values = values.into_iter().peekable();
+        for candidate in 0..universe {
+            builder.push(values.next_if_eq(&candidate).is_some());
+        }
+
+        Ok(())
+    }
+
+    /// Returns a logical view of the elements at `start`, `start + stride`, `start + 2 * stride`
+    /// and so on.
+    ///
+    /// Panics if `stride` is 0.
+    pub fn strided(&self, start: usize, stride: usize) -> StridedLogArray {
+        assert!(stride != 0, "expected stride to be greater than 0");
+        let len = self.len().saturating_sub(start).div_ceil(stride);
+        StridedLogArray {
+            logarray: self.clone(),
+            start,
+            stride,
+            len,
+        }
+    }
+
+    /// Writes the word-wise XOR of the data of this log array and `base` into `buf`, followed by
+    /// the control word of this log array.
+    ///
+    /// Words which are the same in both arrays come out as zeros, so the result of a near
+    /// duplicate compresses well. As XOR is its own inverse, this log array is reconstructed by
+    /// parsing the result and calling `xor_with` on it with the same `base`.
+    ///
+    /// Panics if the log arrays differ in length or width, or if either of them is a slice.
+    pub fn xor_with<B: BufMut>(&self, base: &LogArray, mut buf: B) -> B {
+        assert!(
+            self.is_unsliced() && base.is_unsliced(),
+            "expected log arrays which are not slices"
+        );
+        assert!(
+            self.len == base.len && self.width == base.width,
+            "expected log arrays of equal length and width, got ({}, {}) and ({}, {})",
+            self.len,
+            self.width,
+            base.len,
+            base.width
+        );
+
+        let data_len = logarray_length_from_len_width(self.len, self.width);
+        let words = self.input_buf[..data_len].chunks_exact(8);
+        let base_words = base.input_buf[..data_len].chunks_exact(8);
+        for (word, base_word) in words.zip(base_words) {
+            buf.put_u64(BigEndian::read_u64(word) ^ BigEndian::read_u64(base_word));
+        }
+        buf.put_slice(&control_word(self.len, self.width));
+
+        buf
+    }
+
+    /// Returns whether this log array covers all of its data, which is either followed by a
+    /// control word or, when parsed header first, by nothing.
+    fn is_unsliced(&self) -> bool {
+        let data_len = logarray_length_from_len_width(self.len, self.width);
+        self.first == 0
+            && (self.input_buf.len() == data_len || self.input_buf.len() == data_len + 8)
+    }
+
     /// Returns a logical slice of the elements in a log array.
     ///
     /// Panics if `index` + `length` is >= the length of the log array.
@@ -404,32 +1073,202 @@ impl LogArray {
     }
 }
 
-/// write a logarray directly to an AsyncWrite
-pub struct LogArrayBufBuilder<B: BufMut> {
-    /// Destination of the log array data
-    buf: B,
-    /// Bit width of an element
-    width: u8,
-    /// Storage for the next word to be written to the buffer
-    current: u64,
-    /// Bit offset in `current` for the msb of the next encoded element
-    offset: u8,
-    /// Number of elements written to the buffer
-    count: u64,
+/// A logical view of every `stride`th element of a log array, starting at `start`.
+///
+/// This is useful when several columns are interleaved in one log array. The view shares the
+/// underlying buffer with the log array it was created from.
+#[derive(Clone, Debug)]
+pub struct StridedLogArray {
+    logarray: LogArray,
+    start: usize,
+    stride: usize,
+    len: usize,
 }
 
-impl<D: std::ops::DerefMut<Target = BytesMut> + BufMut> LogArrayBufBuilder<D> {
-    pub fn reserve(&mut self, additional: usize) {
-        self.buf.reserve(additional * self.width as usize / 8);
+impl StridedLogArray {
+    /// Returns the number of elements in the view.
+    pub fn len(&self) -> usize {
+        self.len
     }
-}
 
-impl<B: BufMut> LogArrayBufBuilder<B> {
-    pub fn new(buf: B, width: u8) -> Self {
-        Self {
-            buf,
-            width,
-            // Zero is needed for bitwise OR-ing new values.
+    /// Returns `true` if there are no elements in the view.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the element at `index` in the view.
+    ///
+    /// Panics if `index` is >= the length of the view.
+    pub fn entry(&self, index: usize) -> u64 {
+        assert!(
+            index < self.len,
+            "expected index ({}) < length ({})",
+            index,
+            self.len
+        );
+        self.logarray.entry(self.start + index * self.stride)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.len).map(move |index| self.entry(index))
+    }
+}
+
+/// A view of a log array whose elements are wrapped in an id type.
+///
+/// The encoding is the same as that of a plain `LogArray`. Only the type of the elements differs,
+/// so that ids from different arrays can't be mixed up by accident. Id types can be declared with
+/// `log_array_newtype!`.
+pub struct TypedLogArray<T> {
+    logarray: LogArray,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for TypedLogArray<T> {
+    fn clone(&self) -> Self {
+        Self {
+            logarray: self.logarray.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: From<u64>> TypedLogArray<T> {
+    pub fn new(logarray: LogArray) -> Self {
+        Self {
+            logarray,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn parse(input_buf: Bytes) -> Result<Self, LogArrayError> {
+        Ok(Self::new(LogArray::parse(input_buf)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.logarray.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logarray.is_empty()
+    }
+
+    /// Returns the element at the `index`.
+    ///
+    /// Panics if `index` is >= the length of the log array.
+    pub fn entry(&self, index: usize) -> T {
+        T::from(self.logarray.entry(index))
+    }
+
+    pub fn get(&self, index: usize) -> Option<T> {
+        self.logarray.get(index).map(T::from)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.logarray.iter_ref().map(T::from)
+    }
+
+    /// Returns the underlying untyped log array.
+    pub fn logarray(&self) -> &LogArray {
+        &self.logarray
+    }
+
+    pub fn into_logarray(self) -> LogArray {
+        self.logarray
+    }
+}
+
+/// Declare an id type wrapping a `u64`, for use as the element type of a `TypedLogArray`.
+///
+/// ```
+/// use tdb_succinct::{log_array_newtype, LogArray, TypedLogArray};
+///
+/// log_array_newtype!(NodeId);
+/// log_array_newtype!(PredicateId);
+///
+/// let nodes: TypedLogArray<NodeId> = TypedLogArray::new(LogArray::default());
+/// let node: NodeId = NodeId(3);
+/// let predicate: PredicateId = PredicateId(3);
+/// assert_eq!(u64::from(node), u64::from(predicate));
+/// assert!(nodes.get(0).is_none());
+/// ```
+///
+/// Ids of different types can't be mixed:
+///
+/// ```compile_fail
+/// use tdb_succinct::{log_array_newtype, LogArray, TypedLogArray};
+///
+/// log_array_newtype!(NodeId);
+/// log_array_newtype!(PredicateId);
+///
+/// let nodes: TypedLogArray<NodeId> = TypedLogArray::new(LogArray::default());
+/// let node: NodeId = PredicateId(3);
+/// ```
+#[macro_export]
+macro_rules! log_array_newtype {
+    ($ty:ident) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $ty(pub u64);
+
+        impl From<u64> for $ty {
+            fn from(id: u64) -> Self {
+                $ty(id)
+            }
+        }
+
+        impl From<$ty> for u64 {
+            fn from(id: $ty) -> u64 {
+                id.0
+            }
+        }
+    };
+}
+
+/// Returns an iterator over the rows of a set of equally long log arrays.
+///
+/// Each row is a vector containing the element at the same index in each column, in column order.
+///
+/// Panics if the columns are not all of the same length.
+pub fn zip_columns<'a>(columns: &'a [LogArray]) -> impl Iterator<Item = Vec<u64>> + 'a {
+    let len = columns.first().map(|c| c.len()).unwrap_or(0);
+    for (index, column) in columns.iter().enumerate() {
+        assert_eq!(
+            len,
+            column.len(),
+            "expected column {} to have the same length as column 0",
+            index
+        );
+    }
+
+    (0..len).map(move |row| columns.iter().map(|c| c.entry(row)).collect())
+}
+
+/// write a logarray directly to an AsyncWrite
+pub struct LogArrayBufBuilder<B: BufMut> {
+    /// Destination of the log array data
+    buf: B,
+    /// Bit width of an element
+    width: u8,
+    /// Storage for the next word to be written to the buffer
+    current: u64,
+    /// Bit offset in `current` for the msb of the next encoded element
+    offset: u8,
+    /// Number of elements written to the buffer
+    count: u64,
+}
+
+impl<D: std::ops::DerefMut<Target = BytesMut> + BufMut> LogArrayBufBuilder<D> {
+    pub fn reserve(&mut self, additional: usize) {
+        self.buf.reserve(additional * self.width as usize / 8);
+    }
+}
+
+impl<B: BufMut> LogArrayBufBuilder<B> {
+    pub fn new(buf: B, width: u8) -> Self {
+        Self {
+            buf,
+            width,
+            // Zero is needed for bitwise OR-ing new values.
             current: 0,
             // Start at the beginning of `current`.
             offset: 0,
@@ -442,13 +1281,21 @@ impl<B: BufMut> LogArrayBufBuilder<B> {
         self.count
     }
 
+    /// Push a value, panicking if it does not fit in the width of this builder.
     pub fn push(&mut self, val: u64) {
+        if let Err(e) = self.try_push(val) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Push a value, returning an error if it does not fit in the width of this builder.
+    pub fn try_push(&mut self, val: u64) -> Result<(), LogArrayError> {
         // This is the minimum number of leading zeros that a decoded value should have.
         let leading_zeros = u64::BITS - self.width as u32;
 
         // If `val` does not fit in the `width`, return an error.
-        if val.leading_zeros() < u32::from(leading_zeros) {
-            panic!("expected value ({}) to fit in {} bits", val, self.width);
+        if val.leading_zeros() < leading_zeros {
+            return Err(LogArrayError::ValueTooWide(val, self.width));
         }
 
         // Otherwise, push `val` onto the log array.
@@ -479,6 +1326,8 @@ impl<B: BufMut> LogArrayBufBuilder<B> {
                 val << 64 - self.offset
             };
         }
+
+        Ok(())
     }
 
     pub fn push_vec(&mut self, vals: Vec<u64>) {
@@ -565,6 +1414,13 @@ impl<B: BufMut> LateLogArrayBufBuilder<B> {
         }
     }
 
+    /// Push all elements of `other`.
+    pub fn extend_from_logarray(&mut self, other: &LogArray) {
+        for val in other.iter_ref() {
+            self.push(val);
+        }
+    }
+
     pub fn last(&mut self) -> Option<u64> {
         self.vals.last().copied()
     }
@@ -590,6 +1446,57 @@ impl<B: BufMut> LateLogArrayBufBuilder<B> {
     }
 }
 
+/// Builds a log array of record lengths together with a monotonic log array of the offsets at
+/// which those records start.
+///
+/// The offset of record `i` is the sum of the lengths of all records before it, so the first
+/// offset is always 0.
+pub struct OffsetIndexBuilder<B: BufMut> {
+    lengths: LateLogArrayBufBuilder<B>,
+    offsets: LateLogArrayBufBuilder<B>,
+    total: u64,
+}
+
+impl<B: BufMut> OffsetIndexBuilder<B> {
+    pub fn new(lengths_buf: B, offsets_buf: B) -> Self {
+        Self {
+            lengths: LateLogArrayBufBuilder::new(lengths_buf),
+            offsets: LateLogArrayBufBuilder::new(offsets_buf),
+            total: 0,
+        }
+    }
+
+    /// Returns the number of records pushed so far.
+    pub fn count(&self) -> u64 {
+        self.lengths.count()
+    }
+
+    /// Returns the sum of all lengths pushed so far, which is the offset of the next record.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn push_length(&mut self, len: u64) {
+        self.lengths.push(len);
+        self.offsets.push(self.total);
+        self.total = self
+            .total
+            .checked_add(len)
+            .expect("sum of record lengths overflows u64");
+    }
+
+    pub fn push_lengths<I: IntoIterator<Item = u64>>(&mut self, lens: I) {
+        for len in lens {
+            self.push_length(len);
+        }
+    }
+
+    /// Finish both arrays, returning the buffers for the lengths and the offsets, in that order.
+    pub fn finalize(self) -> (B, B) {
+        (self.lengths.finalize(), self.offsets.finalize())
+    }
+}
+
 /// write a logarray directly to an AsyncWrite
 pub struct LogArrayFileBuilder<W: SyncableFile> {
     /// Destination of the log array data
@@ -684,6 +1591,15 @@ impl<W: SyncableFile> LogArrayFileBuilder<W> {
         Ok(())
     }
 
+    /// Push all elements of `other`, which have to fit in the width of this builder.
+    pub async fn push_logarray(&mut self, other: &LogArray) -> io::Result<()> {
+        for val in other.iter_ref() {
+            self.push(val).await?;
+        }
+
+        Ok(())
+    }
+
     async fn finalize_data(&mut self) -> io::Result<()> {
         if self.count * u64::from(self.width) & 0b11_1111 != 0 {
             util::write_u64(&mut self.file, self.current).await?;
@@ -692,7 +1608,20 @@ impl<W: SyncableFile> LogArrayFileBuilder<W> {
         Ok(())
     }
 
-    pub async fn finalize(mut self) -> io::Result<()> {
+    pub async fn finalize(self) -> io::Result<()> {
+        let file = self.finalize_no_sync().await?;
+        file.sync_all().await?;
+
+        Ok(())
+    }
+
+    /// Write the remaining data and the control word, and flush them, but don't sync the file.
+    ///
+    /// This trades durability for throughput: the log array is not guaranteed to survive a crash
+    /// until the returned file is synced, and some stores, like `MemoryBackedStore`, don't make
+    /// the file visible at all before that. It is meant for writing many files which are then
+    /// made durable in one go. Use `finalize` unless you take care of that yourself.
+    pub async fn finalize_no_sync(mut self) -> io::Result<W> {
         let len = self.count;
         let width = self.width;
 
@@ -704,9 +1633,8 @@ impl<W: SyncableFile> LogArrayFileBuilder<W> {
         self.file.write_all(&buf).await?;
 
         self.file.flush().await?;
-        self.file.sync_all().await?;
 
-        Ok(())
+        Ok(self.file)
     }
 }
 
@@ -837,14 +1765,11 @@ impl Decoder for LogArrayDecoder {
 }
 
 pub async fn logarray_file_get_length_and_width<F: FileLoad>(f: F) -> io::Result<(u64, u8)> {
-    LogArrayError::validate_input_buf_size(f.size().await?)?;
+    let size = f.size().await?;
+    LogArrayError::validate_input_buf_size(size)?;
 
-    let mut buf = [0; 8];
-    f.open_read_from(f.size().await? - 8)
-        .await?
-        .read_exact(&mut buf)
-        .await?;
-    Ok(read_control_word(&buf, f.size().await?)?)
+    let control_word = f.map_range(size - 8, 8).await?;
+    Ok(read_control_word(&control_word, size)?)
 }
 
 pub async fn logarray_stream_entries<F: 'static + FileLoad>(
@@ -857,34 +1782,203 @@ pub async fn logarray_stream_entries<F: 'static + FileLoad>(
     ))
 }
 
-#[derive(Clone)]
+/// Stream the positions of the entries in a logarray file for which `pred` returns `true`.
+///
+/// Only the positions are yielded, so a selective scan over a large file does not need to
+/// keep the matching values around.
+pub async fn logarray_stream_matching<F: 'static + FileLoad, P: Fn(u64) -> bool>(
+    f: F,
+    pred: P,
+) -> io::Result<impl Stream<Item = io::Result<usize>> + Unpin> {
+    Ok(logarray_stream_entries(f)
+        .await?
+        .enumerate()
+        .filter_map(move |(index, entry)| {
+            let result = match entry {
+                Ok(value) if pred(value) => Some(Ok(index)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            };
+            future::ready(result)
+        }))
+}
+
+/// A reader which feeds everything it reads into a shared checksum.
+struct Crc32Reader<R> {
+    inner: R,
+    crc: Arc<Mutex<Crc32>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Crc32Reader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            this.crc.lock().unwrap().update(&buf.filled()[filled..]);
+        }
+
+        result
+    }
+}
+
+/// Stream the entries of a logarray file, while calculating the CRC-32 of the entire file.
+///
+/// If the checksum does not match `expected_crc` once the file has been read, the stream ends
+/// with an error of kind `InvalidData`. All entries are yielded before that, whether or not the
+/// checksum matches.
+pub async fn logarray_stream_entries_verified<F: 'static + FileLoad>(
+    f: F,
+    expected_crc: u32,
+) -> io::Result<impl Stream<Item = io::Result<u64>> + Unpin + Send> {
+    let (len, width) = logarray_file_get_length_and_width(f.clone()).await?;
+    let crc = Arc::new(Mutex::new(Crc32::new()));
+    let reader = Crc32Reader {
+        inner: f.open_read().await?,
+        crc: crc.clone(),
+    };
+    let entries = FramedRead::new(reader, LogArrayDecoder::new_unchecked(width, len));
+
+    let verification = stream::once(future::lazy(move |_| {
+        let actual_crc = crc.lock().unwrap().finalize();
+        if actual_crc == expected_crc {
+            None
+        } else {
+            Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "logarray checksum mismatch: expected {:#010x}, got {:#010x}",
+                    expected_crc, actual_crc
+                ),
+            )))
+        }
+    }))
+    .filter_map(future::ready);
+
+    Ok(entries.chain(verification))
+}
+
+/// Serializes a log array as its buffer, which ends in the control word.
+#[cfg(feature = "serde")]
+impl serde::Serialize for LogArray {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data_len = logarray_length_from_len_width(self.len, self.width);
+        let is_whole_buf = self.first == 0
+            && self.input_buf.len() == data_len + 8
+            && parse_control_word(&self.input_buf[data_len..]).0 == self.len;
+        if is_whole_buf {
+            serializer.serialize_bytes(&self.input_buf)
+        } else {
+            // slices and header first log arrays don't have a buffer of their own
+            let mut builder = LogArrayBufBuilder::new(BytesMut::new(), self.width);
+            for val in self.iter_ref() {
+                builder.push(val);
+            }
+            serializer.serialize_bytes(&builder.finalize())
+        }
+    }
+}
+
+/// Accepts a log array buffer either as bytes or as a sequence of bytes, as not every format
+/// has a separate representation for bytes.
+#[cfg(feature = "serde")]
+struct LogArrayBufVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for LogArrayBufVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the buffer of a log array")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut buf = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            buf.push(byte);
+        }
+
+        Ok(buf)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for LogArray {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let buf = deserializer.deserialize_byte_buf(LogArrayBufVisitor)?;
+
+        LogArray::parse(Bytes::from(buf)).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
 pub struct MonotonicLogArray(LogArray);
 
 impl std::fmt::Debug for MonotonicLogArray {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "MonotonicLogArray([{}])", self.iter().format(", "))
+        fmt_logarray_debug("MonotonicLogArray", &self.0, f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for MonotonicLogArray {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&self.0, serializer)
+    }
+}
+
+/// Deserializes a monotonic log array, which in debug builds is validated to be monotonic.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MonotonicLogArray {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let logarray = <LogArray as serde::Deserialize>::deserialize(deserializer)?;
+        if cfg!(debug_assertions) {
+            Self::validate(&logarray).map_err(serde::de::Error::custom)?;
+        }
+
+        Ok(MonotonicLogArray(logarray))
     }
 }
 
 impl MonotonicLogArray {
     pub fn from_logarray(logarray: LogArray) -> MonotonicLogArray {
         if cfg!(debug_assertions) {
-            // Validate that the elements are monotonically increasing.
-            let mut iter = logarray.iter();
-            if let Some(mut pred) = iter.next() {
-                for succ in iter {
-                    assert!(
-                        pred <= succ,
+            if let Err(e) = Self::validate(&logarray) {
+                panic!("{}", e);
+            }
+        }
+
+        MonotonicLogArray(logarray)
+    }
+
+    /// Validate that the elements are monotonically increasing.
+    fn validate(logarray: &LogArray) -> Result<(), String> {
+        let mut iter = logarray.iter();
+        if let Some(mut pred) = iter.next() {
+            for succ in iter {
+                if pred > succ {
+                    return Err(format!(
                         "not monotonic: expected predecessor ({}) <= successor ({})",
-                        pred,
-                        succ
-                    );
-                    pred = succ;
+                        pred, succ
+                    ));
                 }
+                pred = succ;
             }
         }
 
-        MonotonicLogArray(logarray)
+        Ok(())
     }
 
     pub fn parse(bytes: Bytes) -> Result<MonotonicLogArray, LogArrayError> {
@@ -907,14 +2001,37 @@ impl MonotonicLogArray {
         self.0.is_empty()
     }
 
+    pub fn width(&self) -> u8 {
+        self.0.width()
+    }
+
     pub fn entry(&self, index: usize) -> u64 {
         self.0.entry(index)
     }
 
+    pub fn get(&self, index: usize) -> Option<u64> {
+        self.0.get(index)
+    }
+
     pub fn iter(&self) -> LogArrayIterator {
         self.0.iter()
     }
 
+    /// Decodes all elements into a `Vec<u64>`. See `LogArray::to_vec`.
+    pub fn to_vec(&self) -> Vec<u64> {
+        self.0.to_vec()
+    }
+
+    /// Returns the first and therefore smallest element, or `None` if the array is empty.
+    pub fn min(&self) -> Option<u64> {
+        self.get(0)
+    }
+
+    /// Returns the last and therefore largest element, or `None` if the array is empty.
+    pub fn max(&self) -> Option<u64> {
+        self.len().checked_sub(1).map(|last| self.entry(last))
+    }
+
     pub fn index_of(&self, element: u64) -> Option<usize> {
         let index = self.nearest_index_of(element);
         if index >= self.len() || self.entry(index) != element {
@@ -924,6 +2041,24 @@ impl MonotonicLogArray {
         }
     }
 
+    /// Returns the index in the original log array of the first element of this one. See
+    /// `LogArray::start_offset`.
+    pub fn start_offset(&self) -> usize {
+        self.0.start_offset()
+    }
+
+    /// Like `index_of`, but returns the index in the original log array rather than in this
+    /// slice of it.
+    pub fn global_index_of(&self, element: u64) -> Option<usize> {
+        self.index_of(element)
+            .map(|index| self.start_offset() + index)
+    }
+
+    /// Returns the index of an occurrence of `element`, or `lower_bound(element)` if it does not
+    /// occur.
+    ///
+    /// If `element` occurs more than once, the index may be that of any of the occurrences. Use
+    /// `lower_bound` or `upper_bound` to find the first or last.
     pub fn nearest_index_of(&self, element: u64) -> usize {
         if self.is_empty() {
             return 0;
@@ -948,9 +2083,105 @@ impl MonotonicLogArray {
         (min + max) / 2 + 1
     }
 
+    /// Searches for `element`, like `slice::binary_search`.
+    ///
+    /// Returns `Ok` with the index of the element if it was found, and `Err` with the index at
+    /// which it could be inserted to keep the array sorted otherwise.
+    pub fn binary_search(&self, element: u64) -> Result<usize, usize> {
+        let index = self.nearest_index_of(element);
+        if index < self.len() && self.entry(index) == element {
+            Ok(index)
+        } else {
+            Err(index)
+        }
+    }
+
     pub fn slice(&self, offset: usize, len: usize) -> MonotonicLogArray {
         Self(self.0.slice(offset, len))
     }
+
+    /// Returns the distinct values that occur in exactly one of the two arrays, in increasing order.
+    pub fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a MonotonicLogArray,
+    ) -> impl Iterator<Item = u64> + 'a {
+        self.0
+            .iter_ref()
+            .dedup()
+            .merge_join_by(other.0.iter_ref().dedup(), |a, b| a.cmp(b))
+            .filter_map(|either| match either {
+                EitherOrBoth::Left(value) | EitherOrBoth::Right(value) => Some(value),
+                EitherOrBoth::Both(_, _) => None,
+            })
+    }
+
+    /// Returns the index of the first element >= `element`, or the length if there is none.
+    pub fn lower_bound(&self, element: u64) -> usize {
+        self.partition_point(|entry| entry < element)
+    }
+
+    /// Returns the index of the first element > `element`, or the length if there is none.
+    pub fn upper_bound(&self, element: u64) -> usize {
+        self.partition_point(|entry| entry <= element)
+    }
+
+    /// Returns the slice of all elements `v` with `min <= v <= max`.
+    ///
+    /// If there are no such elements, the slice is empty.
+    pub fn range_slice(&self, min: u64, max: u64) -> MonotonicLogArray {
+        let start = self.lower_bound(min);
+        // when `max < min`, the upper bound may come before the lower bound
+        let end = self.upper_bound(max).max(start);
+
+        self.slice(start, end - start)
+    }
+
+    /// Returns the index of the first element for which `pred` is false, assuming `pred` is true
+    /// for a prefix of the array and false for the rest.
+    fn partition_point<P: Fn(u64) -> bool>(&self, pred: P) -> usize {
+        let mut min = 0;
+        let mut max = self.len();
+        while min < max {
+            let mid = (min + max) / 2;
+            if pred(self.entry(mid)) {
+                min = mid + 1;
+            } else {
+                max = mid;
+            }
+        }
+
+        min
+    }
+
+    /// Returns an iterator over all elements >= `x`, in order.
+    pub fn iter_from_value(&self, x: u64) -> LogArrayIterator {
+        LogArrayIterator {
+            logarray: self.0.clone(),
+            pos: self.lower_bound(x),
+            end: self.len(),
+        }
+    }
+
+    /// Push a bit for every probe to `builder`, which is set if the probe occurs in this array.
+    ///
+    /// The probes have to be sorted, so that all membership tests can be done in a single merge
+    /// pass over the array.
+    pub fn contains_batch<B: BufMut>(
+        &self,
+        sorted_probes: &[u64],
+        builder: &mut BitArrayBufBuilder<B>,
+    ) {
+        debug_assert!(
+            sorted_probes.windows(2).all(|w| w[0] <= w[1]),
+            "expected sorted probes"
+        );
+
+        let mut entries = self.iter().peekable();
+        for &probe in sorted_probes {
+            while entries.next_if(|&entry| entry < probe).is_some() {}
+            builder.push(entries.peek() == Some(&probe));
+        }
+    }
 }
 
 impl From<LogArray> for MonotonicLogArray {
@@ -959,14 +2190,167 @@ impl From<LogArray> for MonotonicLogArray {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::storage::memory::MemoryBackedStore;
-    use crate::storage::FileStore;
-    use crate::util::stream_iter_ok;
-    use futures::executor::block_on;
-    use futures::stream::TryStreamExt;
+/// A sorted set of integers supporting the standard succinct set queries.
+///
+/// Ranks and selects in this trait are 0-based, so that for an element `x` of the set,
+/// `select(rank(x)) == Some(x)`.
+pub trait SuccinctSet {
+    /// Returns the amount of elements smaller than `x`.
+    fn rank(&self, x: u64) -> u64;
+    /// Returns the `n`th smallest element, starting at 0.
+    fn select(&self, n: u64) -> Option<u64>;
+    /// Returns `true` if `x` is an element of the set.
+    fn contains(&self, x: u64) -> bool;
+}
+
+impl SuccinctSet for MonotonicLogArray {
+    fn rank(&self, x: u64) -> u64 {
+        self.lower_bound(x) as u64
+    }
+
+    fn select(&self, n: u64) -> Option<u64> {
+        if n < self.len() as u64 {
+            Some(self.entry(n as usize))
+        } else {
+            None
+        }
+    }
+
+    fn contains(&self, x: u64) -> bool {
+        self.index_of(x).is_some()
+    }
+}
+
+/// Returns the number of distinct values in the union of two monotonic log arrays, without
+/// materializing that union.
+pub fn union_len(a: &MonotonicLogArray, b: &MonotonicLogArray) -> usize {
+    let mut a_iter = a.iter().dedup().peekable();
+    let mut b_iter = b.iter().dedup().peekable();
+    let mut count = 0;
+    loop {
+        match (a_iter.peek(), b_iter.peek()) {
+            (None, None) => break,
+            (Some(_), None) => {
+                a_iter.next();
+            }
+            (None, Some(_)) => {
+                b_iter.next();
+            }
+            (Some(x), Some(y)) => match x.cmp(y) {
+                Ordering::Less => {
+                    a_iter.next();
+                }
+                Ordering::Greater => {
+                    b_iter.next();
+                }
+                Ordering::Equal => {
+                    a_iter.next();
+                    b_iter.next();
+                }
+            },
+        }
+        count += 1;
+    }
+
+    count
+}
+
+/// Look up the value for every probe key, given a sorted key column and a parallel value column.
+///
+/// The value of a key is the entry of `values` at the index of that key in `keys`. Probes that
+/// don't occur in `keys` get `None`. As both `keys` and `probes` are sorted, this is done with a
+/// single merge pass over both.
+pub fn lookup_values(
+    keys: &MonotonicLogArray,
+    values: &LogArray,
+    probes: &MonotonicLogArray,
+) -> Vec<Option<u64>> {
+    assert_eq!(
+        keys.len(),
+        values.len(),
+        "expected key and value columns of equal length"
+    );
+
+    let mut keys_iter = keys.iter().enumerate().peekable();
+    probes
+        .iter()
+        .map(|probe| {
+            while keys_iter.next_if(|&(_, key)| key < probe).is_some() {}
+            match keys_iter.peek() {
+                Some(&(index, key)) if key == probe => Some(values.entry(index)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Count how many of the given values need each bit width, as calculated by `calculate_width`.
+///
+/// Entry `w` of the result is the number of values needing exactly `w` bits. As `calculate_width`
+/// represents 0 with a single bit, entry 0 is always 0.
+pub fn width_histogram(vals: impl Iterator<Item = u64>) -> [u64; 65] {
+    let mut histogram = [0; 65];
+    for val in vals {
+        histogram[calculate_width(val) as usize] += 1;
+    }
+
+    histogram
+}
+
+/// Build a log array from `vals` in memory and assert that it reads back exactly as `vals`.
+///
+/// The array is built with a `LateLogArrayBufBuilder`, and the width it chooses has to be the
+/// smallest one all values fit in. After parsing, `entry`, `get`, `iter`, `to_vec` and slices at
+/// several offsets all have to agree with `vals`.
+#[cfg(any(test, feature = "test-util"))]
+pub fn assert_logarray_roundtrip(vals: &[u64]) {
+    let mut builder = LateLogArrayBufBuilder::new(BytesMut::new());
+    builder.push_vec(vals.to_vec());
+    let logarray = LogArray::parse(builder.finalize().freeze())
+        .unwrap_or_else(|e| panic!("expected the built log array to parse, but got: {}", e));
+
+    let needed = vals.iter().map(|&val| calculate_width(val)).max();
+    if let Some(needed) = needed {
+        assert_eq!(needed, logarray.width(), "expected the minimal width");
+    }
+
+    assert_eq!(vals.len(), logarray.len(), "length");
+    for (index, &val) in vals.iter().enumerate() {
+        assert_eq!(val, logarray.entry(index), "entry at index {}", index);
+    }
+    assert_eq!(None, logarray.get(vals.len()), "get past the end");
+    assert_eq!(vals, &logarray.iter().collect::<Vec<_>>()[..], "iter");
+    assert_eq!(vals, &logarray.to_vec()[..], "to_vec");
+
+    let step = std::cmp::max(1, vals.len() / 8);
+    for offset in (0..=vals.len()).step_by(step) {
+        let len = std::cmp::min(vals.len() - offset, step + 1);
+        assert_eq!(
+            &vals[offset..offset + len],
+            &logarray.slice(offset, len).to_vec()[..],
+            "slice of length {} at offset {}",
+            len,
+            offset
+        );
+        assert_eq!(
+            &vals[offset..],
+            &logarray.slice(offset, vals.len() - offset).to_vec()[..],
+            "slice to the end at offset {}",
+            offset
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitarray::BitArray;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::profiled::ProfiledStore;
+    use crate::storage::FileStore;
+    use crate::util::stream_iter_ok;
+    use futures::executor::block_on;
+    use futures::stream::TryStreamExt;
 
     #[test]
     fn log_array_error() {
@@ -1066,6 +2450,47 @@ mod tests {
         assert_eq!(logarray.entry(0_usize), 0_u64);
     }
 
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn from_slice_parallel_matches_sequential_builder() {
+        for (len, max) in [
+            (0, 1),
+            (1, 0),
+            (1000, 1),
+            (64 * 1024, 1000),
+            (200_003, 1 << 30),
+        ] {
+            let vals = LogArray::parse(LogArray::build_from_seed(len as u64, len, max))
+                .unwrap()
+                .to_vec();
+
+            let mut builder = LateLogArrayBufBuilder::new(BytesMut::new());
+            builder.push_vec(vals.clone());
+            let sequential = builder.finalize().freeze();
+            let parallel = LogArray::from_slice_parallel(&vals);
+
+            assert_eq!(sequential, parallel.input_buf);
+            assert_eq!(vals, parallel.to_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn finalize_no_sync_leaves_syncing_to_the_caller() {
+        let synced = MemoryBackedStore::new();
+        let mut builder = LogArrayFileBuilder::new(synced.open_write().await.unwrap(), 9);
+        builder.push_vec((0..300).collect()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let unsynced = MemoryBackedStore::new();
+        let mut builder = LogArrayFileBuilder::new(unsynced.open_write().await.unwrap(), 9);
+        builder.push_vec((0..300).collect()).await.unwrap();
+        let file = builder.finalize_no_sync().await.unwrap();
+        assert!(!unsynced.exists().await.unwrap());
+
+        file.sync_all().await.unwrap();
+        assert_eq!(synced.map().await.unwrap(), unsynced.map().await.unwrap());
+    }
+
     #[tokio::test]
     #[should_panic(expected = "expected value (8) to fit in 3 bits")]
     async fn log_array_file_builder_panic() {
@@ -1074,6 +2499,26 @@ mod tests {
         block_on(builder.push(8)).unwrap();
     }
 
+    #[test]
+    fn log_array_buf_builder_try_push_rejects_too_wide_values() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 3);
+        builder.try_push(7).unwrap();
+        assert_eq!(Err(LogArrayError::ValueTooWide(8, 3)), builder.try_push(8));
+        builder.try_push(5).unwrap();
+        // a rejected value leaves the builder untouched
+        assert_eq!(2, builder.count());
+
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+        assert_eq!(vec![7, 5], logarray.to_vec());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected value (8) to fit in 3 bits")]
+    fn log_array_buf_builder_panic() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 3);
+        builder.push(8);
+    }
+
     #[tokio::test]
     async fn generate_then_parse_works() {
         let store = MemoryBackedStore::new();
@@ -1130,6 +2575,61 @@ mod tests {
         LogArray::parse(Bytes::from(content)).unwrap()
     }
 
+    #[test]
+    fn typed_entry_carries_width() {
+        let logarray = test0_logarray();
+        let element = logarray.typed_entry(1);
+        assert_eq!(
+            Element {
+                value: 2,
+                width: 17
+            },
+            element
+        );
+        assert_eq!("2 (width 17)", element.to_string());
+        assert_eq!("Element { value: 2, width: 17 }", format!("{:?}", element));
+        assert_eq!(17, logarray.slice(1, 2).typed_entry(1).width);
+    }
+
+    #[test]
+    fn analyze_delta_reports_narrow_width_for_monotonic_arrays() {
+        let values: Vec<u64> = (0..1000).map(|i| 1_000_000 + i * 3).collect();
+        let logarray = logarray_from_vec(20, values);
+        let report = logarray.analyze_delta();
+
+        assert!(report.is_monotonic);
+        assert_eq!(20, report.original_width);
+        // the first delta is the first value itself
+        assert_eq!(20, report.delta_width);
+
+        let values: Vec<u64> = (0..1000).map(|i| i * 3).collect();
+        let logarray = logarray_from_vec(12, values);
+        let report = logarray.analyze_delta();
+        assert_eq!(
+            DeltaReport {
+                is_monotonic: true,
+                delta_width: 2,
+                original_width: 12,
+                estimated_bytes: 256 + 8,
+            },
+            report
+        );
+    }
+
+    #[test]
+    fn analyze_delta_reports_wide_width_for_unordered_arrays() {
+        let values = LogArray::parse(LogArray::build_from_seed(7, 1000, (1 << 20) - 1))
+            .unwrap()
+            .to_vec();
+        let logarray = logarray_from_vec(20, values);
+        let report = logarray.analyze_delta();
+
+        assert!(!report.is_monotonic);
+        assert_eq!(20, report.original_width);
+        assert_eq!(21, report.delta_width);
+        assert!(report.estimated_bytes > logarray_length_from_len_width(1000, 20));
+    }
+
     #[test]
     #[should_panic(expected = "expected index (3) < length (3)")]
     fn entry_panic() {
@@ -1149,6 +2649,102 @@ mod tests {
         let _ = test0_logarray().slice(usize::try_from(u32::max_value()).unwrap() + 1, 2);
     }
 
+    #[test]
+    #[should_panic(expected = "overflow from slice offset")]
+    #[cfg(target_pointer_width = "64")]
+    fn slice_panic_usize_max() {
+        let _ = test0_logarray().slice(usize::MAX, 1);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn data_length_at_maximum_len_and_width() {
+        assert_eq!(
+            (u32::MAX as usize) * 8,
+            logarray_length_from_len_width(u32::MAX as u64, 64)
+        );
+        assert_eq!(
+            (u32::MAX as usize).div_ceil(8) * 8,
+            logarray_length_from_len_width(u32::MAX as u64, 8)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows usize")]
+    #[cfg(target_pointer_width = "32")]
+    fn data_length_at_maximum_len_and_width() {
+        let _ = logarray_length_from_len_width(u32::MAX as u64, 64);
+    }
+
+    #[test]
+    fn entry_in_slice_of_slice() {
+        let values: Vec<u64> = (0..200)
+            .map(|i| (i * 0x1_0000_0001) ^ 0xdead_beef)
+            .collect();
+        let logarray = logarray_from_vec(64, values.clone());
+        let slice = logarray.slice(37, 150).slice(11, 100);
+        for i in 0..100 {
+            assert_eq!(values[48 + i], slice.entry(i));
+        }
+    }
+
+    #[test]
+    fn typed_logarray_yields_newtype() {
+        log_array_newtype!(NodeId);
+        log_array_newtype!(PredicateId);
+
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 5);
+        builder.push_vec(vec![3, 1, 4, 1, 5]);
+        let bytes = builder.finalize().freeze();
+        let nodes: TypedLogArray<NodeId> = TypedLogArray::parse(bytes.clone()).unwrap();
+        let predicates: TypedLogArray<PredicateId> = TypedLogArray::parse(bytes).unwrap();
+
+        assert_eq!(5, nodes.len());
+        assert_eq!(NodeId(4), nodes.entry(2));
+        assert_eq!(None, nodes.get(5));
+        assert_eq!(
+            vec![NodeId(3), NodeId(1), NodeId(4), NodeId(1), NodeId(5)],
+            nodes.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(PredicateId(5), predicates.entry(4));
+        assert_eq!(u64::from(nodes.entry(0)), u64::from(predicates.entry(0)));
+        assert_eq!(
+            nodes.logarray().to_vec(),
+            predicates.into_logarray().to_vec()
+        );
+    }
+
+    #[test]
+    fn get_returns_none_out_of_bounds() {
+        let logarray = test0_logarray();
+        assert_eq!(Some(1), logarray.get(0));
+        assert_eq!(Some(3), logarray.get(2));
+        assert_eq!(None, logarray.get(3));
+        assert_eq!(None, logarray.get(usize::MAX));
+
+        let slice = logarray.slice(1, 1);
+        assert_eq!(Some(2), slice.get(0));
+        assert_eq!(None, slice.get(1));
+
+        let monotonic = MonotonicLogArray::from_logarray(logarray);
+        assert_eq!(Some(2), monotonic.get(1));
+        assert_eq!(None, monotonic.get(3));
+    }
+
+    #[test]
+    fn entry_or_returns_default_out_of_bounds() {
+        let logarray = test0_logarray();
+        assert_eq!(1, logarray.entry_or(0, 42));
+        assert_eq!(3, logarray.entry_or(2, 42));
+        assert_eq!(42, logarray.entry_or(3, 42));
+        assert_eq!(42, logarray.entry_or(4, 42));
+        assert_eq!(42, logarray.entry_or(usize::MAX, 42));
+
+        let slice = logarray.slice(1, 1);
+        assert_eq!(2, slice.entry_or(0, 0));
+        assert_eq!(7, slice.entry_or(1, 7));
+    }
+
     #[test]
     #[should_panic(expected = "expected index (2) < length (2)")]
     fn slice_entry_panic() {
@@ -1225,6 +2821,25 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn logarray_file_get_length_and_width_reads_only_the_control_word() {
+        let store = ProfiledStore::new(MemoryBackedStore::new());
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 5);
+        builder.push_vec(vec![1, 2, 3, 4]).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        assert_eq!(
+            (4, 5),
+            logarray_file_get_length_and_width(store.clone())
+                .await
+                .unwrap()
+        );
+        let latencies = store.latencies();
+        assert_eq!(1, latencies.map_range.count());
+        assert_eq!(0, latencies.map.count());
+        assert_eq!(0, latencies.open_read.count());
+    }
+
     #[tokio::test]
     async fn generate_then_stream_works() {
         let store = MemoryBackedStore::new();
@@ -1248,6 +2863,265 @@ mod tests {
         assert_eq!(expected, entries);
     }
 
+    #[tokio::test]
+    async fn stream_matching_yields_positions_of_matching_entries() {
+        let store = MemoryBackedStore::new();
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 6);
+        let original: Vec<u64> = (0..200).map(|i| (i * 37) % 61).collect();
+        builder.push_vec(original.clone()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let positions: Vec<usize> = logarray_stream_matching(store, |v| v % 3 == 0)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let expected: Vec<usize> = original
+            .iter()
+            .enumerate()
+            .filter(|(_, &v)| v % 3 == 0)
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(expected, positions);
+    }
+
+    #[tokio::test]
+    async fn verified_stream_detects_tampering() {
+        let values: Vec<u64> = (0..100).map(|i| i * 3).collect();
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 9);
+        builder.push_vec(values.clone());
+        let bytes = builder.finalize().freeze();
+        let crc = util::crc32(&bytes);
+
+        let store = MemoryBackedStore::new();
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(&bytes).await.unwrap();
+        writer.sync_all().await.unwrap();
+        let entries: Vec<u64> = logarray_stream_entries_verified(store, crc)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(values, entries);
+
+        let mut tampered = bytes.to_vec();
+        tampered[5] ^= 0x10;
+        let store = MemoryBackedStore::new();
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(&tampered).await.unwrap();
+        writer.sync_all().await.unwrap();
+        let results: Vec<io::Result<u64>> = logarray_stream_entries_verified(store, crc)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(values.len() + 1, results.len());
+        let entries: Vec<u64> = results[..values.len()]
+            .iter()
+            .map(|r| *r.as_ref().unwrap())
+            .collect();
+        assert_ne!(values, entries);
+        let err = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn fold_matches_iterator() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 17);
+        builder.push_vec((0..1000).map(|i| (i * 7919) % 100_000).collect());
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+
+        assert_eq!(
+            logarray.iter().sum::<u64>(),
+            logarray.fold(0, |sum, x| sum + x)
+        );
+        assert_eq!(
+            logarray.iter().max(),
+            logarray.fold(None, |max: Option<u64>, x| max.max(Some(x)))
+        );
+
+        let slice = logarray.slice(13, 100);
+        assert_eq!(slice.iter().sum::<u64>(), slice.fold(0, |sum, x| sum + x));
+        assert_eq!(0, LogArray::default().fold(0, |count, _| count + 1));
+    }
+
+    #[test]
+    fn parse_all_header_first_parses_concatenated_arrays() {
+        let contents: Vec<Vec<u64>> =
+            vec![vec![1, 2, 3], vec![], (0..100).map(|i| i * 1000).collect()];
+        let mut buf = BytesMut::new();
+        for values in contents.iter() {
+            let mut builder = LateLogArrayBufBuilder::new(&mut buf);
+            builder.push_vec(values.clone());
+            builder.finalize_header_first();
+        }
+        let buf = buf.freeze();
+
+        let parsed = LogArray::parse_all_header_first(buf.clone(), 3).unwrap();
+        assert_eq!(3, parsed.len());
+        for (values, logarray) in contents.iter().zip(parsed.iter()) {
+            assert_eq!(values, &logarray.to_vec());
+        }
+
+        assert!(LogArray::parse_all_header_first(buf.clone(), 4).is_err());
+        assert!(LogArray::parse_all_header_first(buf.slice(..buf.len() - 8), 3).is_err());
+        assert!(LogArray::parse_all_header_first(buf, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn to_vec_allocates_exact_capacity() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 10);
+        builder.push_vec((0..1000).collect());
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+
+        let vec = logarray.to_vec();
+        assert_eq!(1000, vec.capacity());
+        assert_eq!(logarray.iter().collect::<Vec<_>>(), vec);
+        assert_eq!(vec![5, 6, 7], logarray.slice(5, 3).to_vec());
+    }
+
+    #[test]
+    fn late_builder_extends_from_logarray() {
+        let first = logarray_from_vec(5, vec![1, 20, 3]);
+        let second = logarray_from_vec(12, vec![4000, 5]);
+
+        let mut builder = LateLogArrayBufBuilder::new(BytesMut::new());
+        builder.extend_from_logarray(&first);
+        assert_eq!(5, builder.width);
+        builder.extend_from_logarray(&LogArray::default());
+        assert_eq!(3, builder.count());
+        assert_eq!(5, builder.width);
+        builder.extend_from_logarray(&second);
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+
+        assert_eq!(12, logarray.width());
+        assert_eq!(vec![1, 20, 3, 4000, 5], logarray.to_vec());
+    }
+
+    #[tokio::test]
+    async fn file_builder_pushes_logarray() {
+        let store = MemoryBackedStore::new();
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 12);
+        builder
+            .push_logarray(&logarray_from_vec(5, vec![1, 20, 3]))
+            .await
+            .unwrap();
+        builder.push_logarray(&LogArray::default()).await.unwrap();
+        builder
+            .push_logarray(&logarray_from_vec(12, vec![4000, 5]))
+            .await
+            .unwrap();
+        assert!(builder
+            .push_logarray(&logarray_from_vec(13, vec![8000]))
+            .await
+            .is_err());
+        builder.finalize().await.unwrap();
+
+        let logarray = LogArray::parse(store.map().await.unwrap()).unwrap();
+        assert_eq!(vec![1, 20, 3, 4000, 5], logarray.to_vec());
+    }
+
+    #[test]
+    fn build_bounded_uses_bound_width() {
+        let buf = LogArray::build_bounded(1000, [1, 2, 3].into_iter(), BytesMut::new()).unwrap();
+        let logarray = LogArray::parse(buf.freeze()).unwrap();
+        assert_eq!(10, logarray.width());
+        assert_eq!(vec![1, 2, 3], logarray.to_vec());
+
+        let buf = LogArray::build_bounded(1000, [0, 1000].into_iter(), BytesMut::new()).unwrap();
+        assert_eq!(10, LogArray::parse(buf.freeze()).unwrap().width());
+
+        let err = LogArray::build_bounded(1000, [5, 1001, 3].into_iter(), BytesMut::new())
+            .err()
+            .unwrap();
+        assert_eq!(LogArrayError::ValueExceedsBound(1001, 1000), err);
+        assert_eq!("expected value (1001) <= bound (1000)", err.to_string());
+    }
+
+    #[test]
+    fn iter_range_yields_window() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 10);
+        builder.push_vec((0..100).map(|i| i * 7).collect());
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+
+        assert_eq!(
+            logarray.slice(10, 20).iter().collect::<Vec<_>>(),
+            logarray.iter_range(10, 30).collect::<Vec<_>>()
+        );
+        assert_eq!(0, logarray.iter_range(50, 50).count());
+        assert_eq!(100, logarray.iter_range(0, 100).count());
+
+        let chunks: Vec<u64> = (0..4)
+            .flat_map(|chunk| logarray.iter_range(chunk * 25, (chunk + 1) * 25))
+            .collect();
+        assert_eq!(logarray.iter().collect::<Vec<_>>(), chunks);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected range end (4) <= length (3)")]
+    fn iter_range_panics_beyond_end() {
+        let _ = test0_logarray().iter_range(1, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected range start (2) <= range end (1)")]
+    fn iter_range_panics_on_reversed_range() {
+        let _ = test0_logarray().iter_range(2, 1);
+    }
+
+    #[test]
+    fn xor_with_twice_restores_the_array() {
+        let base = LogArray::parse(LogArray::build_from_seed(1, 300, 1000)).unwrap();
+        let mut values = base.to_vec();
+        values[3] = 7;
+        values[200] = 999;
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), base.width());
+        builder.push_vec(values.clone());
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+
+        let delta = LogArray::parse(logarray.xor_with(&base, BytesMut::new()).freeze()).unwrap();
+        // only the words holding a changed value differ
+        let nonzero = delta.input_buf[..delta.input_buf.len() - 8]
+            .chunks_exact(8)
+            .filter(|word| word.iter().any(|&b| b != 0))
+            .count();
+        assert_eq!(2, nonzero);
+
+        let restored = delta.xor_with(&base, BytesMut::new()).freeze();
+        assert_eq!(logarray.input_buf, restored);
+        assert_eq!(values, LogArray::parse(restored).unwrap().to_vec());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected log arrays which are not slices")]
+    fn xor_with_panics_on_slice() {
+        let logarray = LogArray::parse(LogArray::build_from_seed(1, 300, 1000)).unwrap();
+        logarray.xor_with(&logarray.slice(1, 299), BytesMut::new());
+    }
+
+    #[test]
+    fn iter_ref_matches_iter() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 11);
+        builder.push_vec((0..500).map(|i| (i * 31) % 2000).collect());
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+
+        for logarray in [
+            logarray.clone(),
+            logarray.slice(7, 300),
+            LogArray::default(),
+        ] {
+            assert_eq!(
+                logarray.iter().collect::<Vec<_>>(),
+                logarray.iter_ref().collect::<Vec<_>>()
+            );
+            assert_eq!(logarray.iter().nth(5), logarray.iter_ref().nth(5));
+            assert_eq!(logarray.len(), logarray.iter_ref().size_hint().0);
+        }
+    }
+
     #[tokio::test]
     async fn iterate_over_logarray() {
         let store = MemoryBackedStore::new();
@@ -1368,6 +3242,711 @@ mod tests {
         assert_eq!(4, logarray.width());
     }
 
+    fn logarray_from_vec(width: u8, vals: Vec<u64>) -> LogArray {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), width);
+        builder.push_vec(vals);
+        LogArray::parse(builder.finalize().freeze()).unwrap()
+    }
+
+    fn monotonic_from_vec(vals: Vec<u64>) -> MonotonicLogArray {
+        let width = vals.last().map(|&v| calculate_width(v)).unwrap_or(1);
+        MonotonicLogArray::from_logarray(logarray_from_vec(width, vals))
+    }
+
+    #[test]
+    fn entries_into_matches_entry() {
+        for width in [1, 7, 13, 32, 61, 64] {
+            let max = u64::MAX >> (64 - width);
+            let logarray =
+                LogArray::parse(LogArray::build_from_seed(width as u64, 500, max)).unwrap();
+            let slice = logarray.slice(37, 400);
+
+            let sorted: Vec<usize> = (0..400).collect();
+            let scattered: Vec<usize> = (0..400).map(|i| (i * 173) % 400).collect();
+            let repeated = vec![5, 5, 6, 5, 399, 0, 0];
+            for indices in [sorted, scattered, repeated, Vec::new()] {
+                let mut out = vec![42];
+                slice.entries_into(&indices, &mut out);
+
+                let mut expected = vec![42];
+                expected.extend(indices.iter().map(|&i| slice.entry(i)));
+                assert_eq!(expected, out, "width {}", width);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "expected index (3) < length (3)")]
+    fn entries_into_panics_out_of_bounds() {
+        test0_logarray().entries_into(&[0, 3], &mut Vec::new());
+    }
+
+    #[test]
+    fn decode_all_matches_iter() {
+        for width in 1..=64 {
+            let max = u64::MAX >> (64 - width);
+            let logarray =
+                LogArray::parse(LogArray::build_from_seed(width as u64, 300, max)).unwrap();
+            assert_eq!(width, logarray.width());
+            assert_eq!(
+                logarray.iter().collect::<Vec<_>>(),
+                logarray.decode_all(),
+                "width {}",
+                width
+            );
+
+            for (offset, len) in [(1, 299), (5, 7), (63, 200), (299, 1), (150, 0)] {
+                let slice = logarray.slice(offset, len);
+                assert_eq!(
+                    slice.iter().collect::<Vec<_>>(),
+                    slice.decode_all(),
+                    "width {}, slice {} + {}",
+                    width,
+                    offset,
+                    len
+                );
+            }
+        }
+
+        assert!(LogArray::default().decode_all().is_empty());
+    }
+
+    #[test]
+    fn collect_into_logarray() {
+        let logarray: LogArray = (0..100).map(|x| x * 3).collect();
+        assert_eq!(100, logarray.len());
+        assert_eq!(9, logarray.width());
+        assert_eq!(
+            (0..100).map(|x| x * 3).collect::<Vec<_>>(),
+            logarray.to_vec()
+        );
+
+        let empty: LogArray = std::iter::empty().collect();
+        assert!(empty.is_empty());
+
+        let from_slice = LogArray::try_from_slice(&[5, 0, u64::MAX]).unwrap();
+        assert_eq!(64, from_slice.width());
+        assert_eq!(vec![5, 0, u64::MAX], from_slice.to_vec());
+    }
+
+    #[test]
+    fn buffer_len_includes_control_word() {
+        let logarray = logarray_from_vec(5, (0..10).collect());
+        // 50 bits of data round up to a single word
+        assert_eq!(16, logarray.buffer_len());
+        assert_eq!(16, logarray.slice(2, 3).buffer_len());
+        assert_eq!(
+            8,
+            LogArray::parse(Bytes::from([0u8; 8].as_ref()))
+                .unwrap()
+                .buffer_len()
+        );
+    }
+
+    #[test]
+    fn global_index_of_maps_back_to_original() {
+        let values = vec![1, 4, 6, 9, 12, 15, 20, 21, 30];
+        let logarray = monotonic_from_vec(values.clone());
+        assert_eq!(0, logarray.start_offset());
+        assert_eq!(Some(4), logarray.global_index_of(12));
+
+        let slice = logarray.slice(3, 5);
+        assert_eq!(3, slice.start_offset());
+        assert_eq!(Some(1), slice.index_of(12));
+        assert_eq!(Some(4), slice.global_index_of(12));
+        assert_eq!(None, slice.global_index_of(4));
+
+        let nested = slice.slice(2, 3);
+        assert_eq!(5, nested.start_offset());
+        assert_eq!(logarray.0.buffer_len(), nested.0.buffer_len());
+        for &value in &[15, 20, 21] {
+            let index = nested.global_index_of(value).unwrap();
+            assert_eq!(value, values[index]);
+        }
+    }
+
+    #[test]
+    fn min_and_max() {
+        let logarray = logarray_from_vec(5, vec![7, 3, 19, 0, 12]);
+        assert_eq!(Some(0), logarray.min());
+        assert_eq!(Some(19), logarray.max());
+        assert_eq!(Some(3), logarray.slice(1, 1).min());
+        assert_eq!(None, logarray.slice(2, 0).max());
+
+        let monotonic = monotonic_from_vec(vec![2, 3, 3, 8, 13]);
+        assert_eq!(Some(2), monotonic.min());
+        assert_eq!(Some(13), monotonic.max());
+        assert_eq!(Some(8), monotonic.slice(1, 3).max());
+
+        assert_eq!(vec![3, 3, 8], monotonic.slice(1, 3).to_vec());
+
+        let empty = monotonic_from_vec(vec![]);
+        assert!(empty.to_vec().is_empty());
+        assert_eq!(None, empty.min());
+        assert_eq!(None, empty.max());
+        assert_eq!(None, LogArray::default().min());
+    }
+
+    #[test]
+    fn union_len_counts_distinct_values() {
+        let overlapping = (vec![1, 3, 3, 5, 7, 9, 12], vec![2, 3, 5, 6, 9, 9, 12, 20]);
+        let disjoint = (vec![1, 2, 3], vec![10, 11, 12, 13]);
+        let empty = (vec![], vec![4, 5]);
+
+        for (a, b) in [overlapping, disjoint, empty] {
+            let mut union: Vec<u64> = a.iter().chain(b.iter()).copied().collect();
+            union.sort();
+            union.dedup();
+
+            let a = monotonic_from_vec(a);
+            let b = monotonic_from_vec(b);
+            assert_eq!(union.len(), union_len(&a, &b));
+            assert_eq!(union.len(), union_len(&b, &a));
+        }
+    }
+
+    #[test]
+    fn offset_index_builder_builds_prefix_sums() {
+        let lengths = vec![5, 0, 12, 1, 300, 7, 0, 0, 42];
+        let mut builder = OffsetIndexBuilder::new(BytesMut::new(), BytesMut::new());
+        builder.push_lengths(lengths.clone());
+        assert_eq!(lengths.len() as u64, builder.count());
+        assert_eq!(lengths.iter().sum::<u64>(), builder.total());
+
+        let (lengths_buf, offsets_buf) = builder.finalize();
+        let parsed_lengths = LogArray::parse(lengths_buf.freeze()).unwrap();
+        let offsets = MonotonicLogArray::parse(offsets_buf.freeze()).unwrap();
+
+        assert_eq!(lengths, parsed_lengths.iter().collect::<Vec<_>>());
+        assert_eq!(lengths.len(), offsets.len());
+        for i in 0..lengths.len() {
+            assert_eq!(lengths[..i].iter().sum::<u64>(), offsets.entry(i));
+        }
+    }
+
+    #[test]
+    fn width_histogram_counts_needed_widths() {
+        let vals: Vec<u64> = (0..1000)
+            .map(|i| match i % 10 {
+                0 => 1 << 20,
+                9 => u64::MAX,
+                _ => 128 + i % 100,
+            })
+            .collect();
+        let histogram = width_histogram(vals.iter().copied());
+
+        assert_eq!(vals.len() as u64, histogram.iter().sum::<u64>());
+        assert_eq!(0, histogram[0]);
+        assert_eq!(800, histogram[8]);
+        assert_eq!(100, histogram[21]);
+        assert_eq!(100, histogram[64]);
+        let peak = (0..65).max_by_key(|&w| histogram[w]).unwrap();
+        assert_eq!(8, peak);
+
+        let histogram = width_histogram([0, 1, 2, 3].into_iter());
+        assert_eq!(2, histogram[1]);
+        assert_eq!(2, histogram[2]);
+    }
+
+    #[test]
+    fn lookup_values_joins_probes_on_keys() {
+        let keys = monotonic_from_vec(vec![2, 5, 6, 10, 15, 16, 30]);
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 8);
+        builder.push_vec(vec![20, 50, 60, 100, 150, 160, 255]);
+        let values = LogArray::parse(builder.finalize().freeze()).unwrap();
+        let probes = monotonic_from_vec(vec![0, 2, 3, 6, 6, 11, 15, 30, 31]);
+
+        assert_eq!(
+            vec![
+                None,
+                Some(20),
+                None,
+                Some(60),
+                Some(60),
+                None,
+                Some(150),
+                Some(255),
+                None
+            ],
+            lookup_values(&keys, &values, &probes)
+        );
+        assert!(lookup_values(&keys, &values, &monotonic_from_vec(vec![])).is_empty());
+    }
+
+    #[test]
+    fn succinct_set_queries_on_monotonic_logarray() {
+        let values = vec![3, 4, 10, 11, 12, 40, 41, 100];
+        let set = monotonic_from_vec(values.clone());
+
+        for x in 0..110 {
+            let expected_rank = values.iter().filter(|&&v| v < x).count() as u64;
+            assert_eq!(expected_rank, SuccinctSet::rank(&set, x), "rank({})", x);
+            assert_eq!(values.contains(&x), SuccinctSet::contains(&set, x));
+        }
+        for (n, &v) in values.iter().enumerate() {
+            assert_eq!(Some(v), SuccinctSet::select(&set, n as u64));
+            assert_eq!(n as u64, SuccinctSet::rank(&set, v));
+        }
+        assert_eq!(None, SuccinctSet::select(&set, values.len() as u64));
+    }
+
+    #[test]
+    fn binary_search_matches_slice_binary_search() {
+        let values = vec![2, 3, 5, 8, 13, 21, 34, 55];
+        let logarray = monotonic_from_vec(values.clone());
+        for element in 0..60 {
+            assert_eq!(
+                values.binary_search(&element),
+                logarray.binary_search(element),
+                "element {}",
+                element
+            );
+        }
+
+        let empty = monotonic_from_vec(vec![]);
+        assert_eq!(Err(0), empty.binary_search(7));
+    }
+
+    #[test]
+    fn symmetric_difference_matches_sets() {
+        use std::collections::BTreeSet;
+
+        let cases: Vec<(Vec<u64>, Vec<u64>)> = vec![
+            (vec![1, 3, 3, 5, 7, 9, 12], vec![2, 3, 5, 6, 9, 9, 12, 20]),
+            (vec![1, 2, 3], vec![10, 11, 12, 13]),
+            (vec![], vec![4, 5]),
+            (vec![4, 4, 5], vec![4, 5]),
+            (vec![], vec![]),
+        ];
+
+        for (a, b) in cases {
+            let a_set: BTreeSet<u64> = a.iter().copied().collect();
+            let b_set: BTreeSet<u64> = b.iter().copied().collect();
+            let expected: Vec<u64> = a_set.symmetric_difference(&b_set).copied().collect();
+
+            let a = monotonic_from_vec(a);
+            let b = monotonic_from_vec(b);
+            assert_eq!(expected, a.symmetric_difference(&b).collect::<Vec<_>>());
+            assert_eq!(expected, b.symmetric_difference(&a).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn lower_and_upper_bound() {
+        let values = vec![2, 3, 3, 3, 5, 8, 10, 10, 13];
+        let logarray = monotonic_from_vec(values.clone());
+
+        for x in 0..16 {
+            assert_eq!(
+                values.partition_point(|&v| v < x),
+                logarray.lower_bound(x),
+                "lower bound of {}",
+                x
+            );
+            assert_eq!(
+                values.partition_point(|&v| v <= x),
+                logarray.upper_bound(x),
+                "upper bound of {}",
+                x
+            );
+        }
+
+        assert_eq!(3, logarray.upper_bound(3) - logarray.lower_bound(3));
+        assert_eq!(0, logarray.upper_bound(4) - logarray.lower_bound(4));
+        // the values in [3, 10]
+        let range = logarray.slice(
+            logarray.lower_bound(3),
+            logarray.upper_bound(10) - logarray.lower_bound(3),
+        );
+        assert_eq!(
+            vec![3, 3, 3, 5, 8, 10, 10],
+            range.iter().collect::<Vec<_>>()
+        );
+
+        let empty = monotonic_from_vec(vec![]);
+        assert_eq!(0, empty.lower_bound(5));
+        assert_eq!(0, empty.upper_bound(5));
+    }
+
+    #[test]
+    fn range_slice_contains_values_in_range() {
+        let values = vec![2, 3, 3, 3, 5, 8, 10, 10, 13];
+        let logarray = monotonic_from_vec(values.clone());
+
+        assert_eq!(
+            vec![3, 3, 3, 5, 8, 10, 10],
+            logarray.range_slice(3, 10).iter().collect::<Vec<_>>()
+        );
+        for min in 0..16 {
+            for max in 0..16 {
+                let expected: Vec<u64> = values
+                    .iter()
+                    .copied()
+                    .filter(|&v| min <= v && v <= max)
+                    .collect();
+                assert_eq!(
+                    expected,
+                    logarray.range_slice(min, max).iter().collect::<Vec<_>>(),
+                    "range [{}, {}]",
+                    min,
+                    max
+                );
+            }
+        }
+
+        assert!(logarray.range_slice(6, 7).is_empty());
+        assert!(logarray.range_slice(10, 3).is_empty());
+        assert!(logarray.range_slice(14, u64::MAX).is_empty());
+        assert!(monotonic_from_vec(vec![]).range_slice(0, 5).is_empty());
+    }
+
+    #[test]
+    fn iter_from_value_starts_at_lower_bound() {
+        let values = vec![2, 3, 3, 5, 8, 10, 10, 13, 21, 34];
+        let logarray = monotonic_from_vec(values.clone());
+
+        assert_eq!(
+            vec![10, 10, 13, 21, 34],
+            logarray.iter_from_value(10).collect::<Vec<_>>()
+        );
+        for x in 0..40 {
+            let expected: Vec<u64> = values.iter().copied().filter(|&v| v >= x).collect();
+            assert_eq!(expected, logarray.iter_from_value(x).collect::<Vec<_>>());
+        }
+        assert_eq!(0, logarray.iter_from_value(35).count());
+        assert_eq!(0, monotonic_from_vec(vec![]).iter_from_value(0).count());
+    }
+
+    #[test]
+    fn contains_batch_matches_contains() {
+        let logarray = monotonic_from_vec(vec![2, 3, 3, 5, 8, 13, 21, 34, 55]);
+        let probes: Vec<u64> = vec![0, 1, 2, 3, 3, 4, 8, 9, 21, 22, 55, 56, 100];
+
+        let mut builder = BitArrayBufBuilder::new(BytesMut::new());
+        logarray.contains_batch(&probes, &mut builder);
+        let bits = crate::bitarray::BitArray::from_bits(builder.finalize().freeze()).unwrap();
+
+        assert_eq!(probes.len(), bits.len());
+        for (i, &probe) in probes.iter().enumerate() {
+            assert_eq!(
+                SuccinctSet::contains(&logarray, probe),
+                bits.get(i),
+                "probe {}",
+                probe
+            );
+        }
+    }
+
+    #[test]
+    fn debug_small_logarray_is_complete() {
+        let logarray = test0_logarray();
+        assert_eq!("LogArray([1, 2, 3])", format!("{:?}", logarray));
+        assert_eq!(
+            "MonotonicLogArray([1, 2, 3])",
+            format!("{:?}", MonotonicLogArray::from_logarray(logarray))
+        );
+    }
+
+    #[test]
+    fn debug_large_logarray_is_elided() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 10);
+        builder.push_vec((1..=1000).collect());
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+
+        assert_eq!(
+            "LogArray([1, 2, 3, 4, 5, 6, 7, 8, ..., 993, 994, 995, 996, 997, 998, 999, 1000] \
+             len=1000 width=10)",
+            format!("{:?}", logarray)
+        );
+        assert_eq!(
+            "LogArray([1, 2, 3, ..., 998, 999, 1000] len=1000 width=10)",
+            format!("{:.3?}", logarray)
+        );
+        assert_eq!(
+            "LogArray([3, 4, ..., 9, 10] len=8 width=10)",
+            format!("{:.2?}", logarray.slice(2, 8))
+        );
+    }
+
+    #[test]
+    fn strided_deinterleaves_columns() {
+        let first: Vec<u64> = (0..50).collect();
+        let second: Vec<u64> = (0..49).map(|i| 1000 - i).collect();
+        let interleaved: Vec<u64> = first.iter().interleave(second.iter()).copied().collect();
+        let logarray = logarray_from_vec(10, interleaved);
+
+        let column0 = logarray.strided(0, 2);
+        let column1 = logarray.strided(1, 2);
+        assert_eq!(50, column0.len());
+        assert_eq!(49, column1.len());
+        assert_eq!(first, column0.iter().collect::<Vec<_>>());
+        assert_eq!(second, column1.iter().collect::<Vec<_>>());
+        assert_eq!(second[3], column1.entry(3));
+
+        assert!(logarray.strided(99, 3).is_empty());
+        assert_eq!(
+            vec![0, 999],
+            logarray
+                .slice(0, 6)
+                .strided(0, 3)
+                .iter()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn roundtrip_of_various_value_sets() {
+        assert_logarray_roundtrip(&[]);
+        assert_logarray_roundtrip(&[0; 100]);
+        assert_logarray_roundtrip(&[u64::MAX; 10]);
+        assert_logarray_roundtrip(&[0, u64::MAX, 1, u64::MAX - 1, 1 << 63]);
+        assert_logarray_roundtrip(&(0..1000).collect::<Vec<_>>());
+        assert_logarray_roundtrip(
+            &LogArray::parse(LogArray::build_from_seed(7, 777, 1 << 37))
+                .unwrap()
+                .to_vec(),
+        );
+        for width in 1..=64 {
+            assert_logarray_roundtrip(&[u64::MAX >> (64 - width), 1, 0]);
+        }
+    }
+
+    #[test]
+    fn build_from_seed_is_deterministic() {
+        let first = LogArray::build_from_seed(1234, 1000, 100_000);
+        let second = LogArray::build_from_seed(1234, 1000, 100_000);
+        assert_eq!(first, second);
+
+        let other = LogArray::build_from_seed(1235, 1000, 100_000);
+        assert_ne!(first, other);
+
+        let logarray = LogArray::parse(first).unwrap();
+        assert_eq!(1000, logarray.len());
+        assert_eq!(17, logarray.width());
+        assert!(logarray.iter().all(|v| v <= 100_000));
+
+        let full_range = LogArray::parse(LogArray::build_from_seed(1, 10, u64::MAX)).unwrap();
+        assert_eq!(64, full_range.width());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrips_through_the_raw_buffer() {
+        let buf = LogArray::build_from_seed(3, 100, 5000);
+        let logarray = LogArray::parse(buf.clone()).unwrap();
+
+        let json = serde_json::to_vec(&logarray).unwrap();
+        assert_eq!(serde_json::to_vec(&buf.to_vec()).unwrap(), json);
+        let deserialized: LogArray = serde_json::from_slice(&json).unwrap();
+        assert_eq!(buf, deserialized.input_buf);
+
+        // slices are serialized as a log array of their own
+        let slice = logarray.slice(10, 50);
+        let json = serde_json::to_vec(&slice).unwrap();
+        let deserialized: LogArray = serde_json::from_slice(&json).unwrap();
+        assert_eq!(slice.to_vec(), deserialized.to_vec());
+
+        assert!(serde_json::from_slice::<LogArray>(b"[1, 2, 3]").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_validates_monotonic_logarrays() {
+        let monotonic = monotonic_from_vec(vec![1, 3, 3, 7, 12]);
+        let json = serde_json::to_vec(&monotonic).unwrap();
+        let deserialized: MonotonicLogArray = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            vec![1, 3, 3, 7, 12],
+            deserialized.iter().collect::<Vec<_>>()
+        );
+
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 4);
+        builder.push_vec(vec![1, 3, 2]);
+        let json = serde_json::to_vec(&builder.finalize().to_vec()).unwrap();
+        let result = serde_json::from_slice::<MonotonicLogArray>(&json);
+        if cfg!(debug_assertions) {
+            let err = result.err().unwrap();
+            assert!(err.to_string().contains("not monotonic"));
+        }
+    }
+
+    #[test]
+    fn logarrays_compare_by_width_and_elements() {
+        let buf = LogArray::build_from_seed(5, 30, 100);
+        let logarray = LogArray::parse(buf.clone()).unwrap();
+
+        // the same elements behind garbage padding bits and in a larger buffer
+        let mut padded = BytesMut::from(&buf[..]);
+        padded[buf.len() - 9] |= 1;
+        let padded = LogArray::parse(padded.freeze()).unwrap();
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), logarray.width());
+        builder.push_vec(logarray.to_vec());
+        builder.push(0);
+        let sliced = LogArray::parse(builder.finalize().freeze())
+            .unwrap()
+            .slice(0, 30);
+        assert_eq!(logarray, padded);
+        assert_eq!(logarray, sliced);
+
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), logarray.width() + 1);
+        builder.push_vec(logarray.to_vec());
+        let wider = LogArray::parse(builder.finalize().freeze()).unwrap();
+        assert_ne!(logarray, wider);
+        assert_ne!(logarray, logarray.slice(0, 29));
+        assert_eq!(logarray.slice(3, 0), logarray.slice(10, 0));
+
+        let mut columns = std::collections::HashMap::new();
+        for (ix, column) in [logarray.clone(), padded, wider, sliced]
+            .into_iter()
+            .enumerate()
+        {
+            columns.entry(column).or_insert(ix);
+        }
+        assert_eq!(2, columns.len());
+        assert_eq!(Some(&0), columns.get(&logarray));
+
+        assert_eq!(
+            monotonic_from_vec(vec![1, 2, 3]),
+            monotonic_from_vec(vec![1, 2, 3])
+        );
+        assert_ne!(
+            monotonic_from_vec(vec![1, 2, 3]),
+            monotonic_from_vec(vec![1, 2, 4])
+        );
+    }
+
+    #[test]
+    fn presence_bitmap_sets_distinct_values() {
+        let values = vec![9, 2, 14, 2, 0, 9, 9, 31];
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 5);
+        builder.push_vec(values.clone());
+        let logarray = LogArray::parse(builder.finalize().freeze()).unwrap();
+
+        let mut bits = BitArrayBufBuilder::new(BytesMut::new());
+        logarray.to_presence_bitmap(40, &mut bits).unwrap();
+        let bitarray = BitArray::from_bits(bits.finalize().freeze()).unwrap();
+        assert_eq!(40, bitarray.len());
+        let set: Vec<u64> = (0..40).filter(|&v| bitarray.get(v as usize)).collect();
+        assert_eq!(vec![0, 2, 9, 14, 31], set);
+
+        let mut bits = BitArrayBufBuilder::new(BytesMut::new());
+        assert_eq!(
+            Err(LogArrayError::ValueOutsideUniverse(31, 31)),
+            logarray.to_presence_bitmap(31, &mut bits)
+        );
+        assert_eq!(0, bits.count());
+    }
+
+    #[test]
+    fn default_logarrays_are_empty() {
+        let logarray = LogArray::default();
+        assert_eq!(0, logarray.len());
+        assert!(logarray.is_empty());
+        assert_eq!(0, logarray.iter().count());
+
+        let monotonic = MonotonicLogArray::default();
+        assert_eq!(0, monotonic.len());
+        assert!(monotonic.is_empty());
+        assert_eq!(0, monotonic.iter().count());
+        assert_eq!(None, monotonic.index_of(0));
+    }
+
+    #[test]
+    fn sample_yields_every_stride_element() {
+        let values: Vec<u64> = vec![10, 11, 12, 13, 14, 15, 16, 17, 18, 19];
+        let logarray = logarray_from_vec(5, values);
+        assert_eq!(vec![10, 13, 16, 19], logarray.sample(3).collect::<Vec<_>>());
+        assert_eq!(vec![10], logarray.sample(10).collect::<Vec<_>>());
+        assert_eq!(10, logarray.sample(1).count());
+        assert_eq!(
+            vec![12, 14],
+            logarray.slice(2, 4).sample(2).collect::<Vec<_>>()
+        );
+
+        let mut iter = logarray.iter();
+        assert_eq!(Some(19), iter.nth(9));
+        assert_eq!(None, iter.next());
+        assert_eq!(None, logarray.iter().nth(usize::MAX));
+    }
+
+    #[test]
+    fn from_runs_equals_expanded_sequence() {
+        let runs = vec![(5, 3), (0, 1), (9, 0), (300, 4), (5, 2)];
+        let expanded: Vec<u64> = runs
+            .iter()
+            .flat_map(|&(value, count)| std::iter::repeat_n(value, count as usize))
+            .collect();
+
+        let from_runs = LogArray::from_runs(runs, BytesMut::new()).freeze();
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), calculate_width(300));
+        builder.push_vec(expanded);
+        assert_eq!(builder.finalize().freeze(), from_runs);
+
+        let empty = LogArray::parse(LogArray::from_runs(vec![], BytesMut::new()).freeze()).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn transitions_yield_run_starts() {
+        let logarray = logarray_from_vec(4, vec![3, 3, 3, 7, 7, 3, 0, 0, 0, 0, 9]);
+        assert_eq!(
+            vec![(0, 3), (3, 7), (5, 3), (6, 0), (10, 9)],
+            logarray.transitions().collect::<Vec<_>>()
+        );
+
+        let constant = logarray_from_vec(4, vec![0; 20]);
+        assert_eq!(vec![(0, 0)], constant.transitions().collect::<Vec<_>>());
+
+        let empty = logarray_from_vec(4, vec![]);
+        assert_eq!(0, empty.transitions().count());
+    }
+
+    #[test]
+    fn narrow_decode_respects_width() {
+        let values: Vec<u64> = (0..300).map(|i| (i * 37) % 1024).collect();
+        let logarray = logarray_from_vec(10, values.clone());
+        let expected32: Vec<u32> = values.iter().map(|&v| v as u32).collect();
+        let expected16: Vec<u16> = values.iter().map(|&v| v as u16).collect();
+        assert_eq!(Some(expected32), logarray.to_vec_u32());
+        assert_eq!(Some(expected16), logarray.to_vec_u16());
+
+        let wide = logarray_from_vec(20, vec![1 << 19, 5, 70_000]);
+        assert_eq!(None, wide.to_vec_u16());
+        assert_eq!(Some(vec![1 << 19, 5, 70_000]), wide.to_vec_u32());
+        assert_eq!(None, logarray_from_vec(33, vec![1 << 32]).to_vec_u32());
+    }
+
+    #[test]
+    fn zip_columns_assembles_rows() {
+        let columns = [
+            logarray_from_vec(3, vec![1, 2, 3, 4]),
+            logarray_from_vec(10, vec![100, 200, 300, 400]),
+            logarray_from_vec(64, vec![u64::MAX, 0, 7, 8]),
+        ];
+
+        let rows: Vec<_> = zip_columns(&columns).collect();
+        assert_eq!(
+            vec![
+                vec![1, 100, u64::MAX],
+                vec![2, 200, 0],
+                vec![3, 300, 7],
+                vec![4, 400, 8],
+            ],
+            rows
+        );
+        assert_eq!(0, zip_columns(&[]).count());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected column 1 to have the same length as column 0")]
+    fn zip_columns_panics_on_unequal_lengths() {
+        let columns = [
+            logarray_from_vec(3, vec![1, 2, 3]),
+            logarray_from_vec(3, vec![1, 2]),
+        ];
+        let _ = zip_columns(&columns);
+    }
+
     #[test]
     fn large_control_word() {
         let num: u64 = 0xFF_FFFF_FFFF_FFFF;
@@ -0,0 +1,212 @@
+//! Batching of writes to many small files.
+//!
+//! Building a layer writes a lot of small files, and on networked storage the overhead of opening,
+//! flushing and syncing each of them one after the other dominates. A `BatchWriter` collects the
+//! files in memory instead. On `commit`, all files are written out concurrently, and only once
+//! every write has completed are they all synced.
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::future;
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
+
+use super::{FileStore, SyncableFile};
+
+/// Collects writes to named files in memory, to be written to their stores in one go.
+///
+/// Stores are looked up by name through the function the writer was constructed with. A file is
+/// only part of the batch once its writer has been synced, just like a file in a store only
+/// exists once it has been synced.
+pub struct BatchWriter<F> {
+    resolve: Arc<dyn Fn(&str) -> F + Send + Sync>,
+    pending: Arc<Mutex<BTreeMap<String, Bytes>>>,
+}
+
+impl<F> Clone for BatchWriter<F> {
+    fn clone(&self) -> Self {
+        Self {
+            resolve: self.resolve.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<F: FileStore> BatchWriter<F> {
+    pub fn new<R: Fn(&str) -> F + Send + Sync + 'static>(resolve: R) -> Self {
+        Self {
+            resolve: Arc::new(resolve),
+            pending: Default::default(),
+        }
+    }
+
+    /// Open the file with the given name for writing as part of this batch.
+    pub fn open_write(&self, name: &str) -> BatchFile {
+        BatchFile {
+            name: name.to_string(),
+            bytes: BytesMut::new(),
+            pending: self.pending.clone(),
+        }
+    }
+
+    /// Returns the names of the files that will be written on commit.
+    pub fn pending_files(&self) -> Vec<String> {
+        self.pending.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Write all files in the batch to their stores, and sync them once everything is written.
+    ///
+    /// The batch is empty afterwards. If writing or syncing fails, the files are put back into the
+    /// batch instead, so that the commit can be retried. A file that was synced into the batch
+    /// again while committing keeps its newer contents.
+    pub async fn commit(&self) -> io::Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+
+        let result = self.write_and_sync(&pending).await;
+        if result.is_err() {
+            let mut current = self.pending.lock().unwrap();
+            for (name, bytes) in pending {
+                current.entry(name).or_insert(bytes);
+            }
+        }
+
+        result
+    }
+
+    async fn write_and_sync(&self, pending: &BTreeMap<String, Bytes>) -> io::Result<()> {
+        let writers = future::try_join_all(pending.iter().map(|(name, bytes)| {
+            let store = (self.resolve)(name);
+            async move {
+                let mut writer = store.open_write_sized(bytes.len()).await?;
+                writer.write_all(bytes).await?;
+                writer.flush().await?;
+
+                Ok::<_, io::Error>(writer)
+            }
+        }))
+        .await?;
+
+        future::try_join_all(writers.into_iter().map(|writer| writer.sync_all())).await?;
+
+        Ok(())
+    }
+}
+
+/// A file in a `BatchWriter`, which is added to the batch when synced.
+pub struct BatchFile {
+    name: String,
+    bytes: BytesMut,
+    pending: Arc<Mutex<BTreeMap<String, Bytes>>>,
+}
+
+impl AsyncWrite for BatchFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.get_mut().bytes.extend_from_slice(buf);
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
+impl SyncableFile for BatchFile {
+    async fn sync_all(self) -> io::Result<()> {
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(self.name, self.bytes.freeze());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::faulty::FaultyStore;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::FileLoad;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn files_exist_only_after_commit() {
+        let stores: HashMap<String, MemoryBackedStore> = ["a", "b", "c"]
+            .iter()
+            .map(|name| (name.to_string(), MemoryBackedStore::new()))
+            .collect();
+        let resolve_stores = stores.clone();
+        let batch = BatchWriter::new(move |name: &str| resolve_stores[name].clone());
+
+        for (i, name) in ["a", "b", "c"].iter().enumerate() {
+            let mut builder = LogArrayFileBuilder::new(batch.open_write(name), 8);
+            builder
+                .push_vec((0..10).map(|x| x * (i as u64 + 1)).collect())
+                .await
+                .unwrap();
+            builder.finalize().await.unwrap();
+        }
+        // a file that is never synced is not part of the batch
+        batch.open_write("c").write_all(b"garbage").await.unwrap();
+
+        assert_eq!(vec!["a", "b", "c"], batch.pending_files());
+        for store in stores.values() {
+            assert!(!store.exists().await.unwrap());
+        }
+
+        batch.commit().await.unwrap();
+
+        assert!(batch.pending_files().is_empty());
+        for (i, name) in ["a", "b", "c"].iter().enumerate() {
+            let logarray = LogArray::parse(stores[*name].map().await.unwrap()).unwrap();
+            assert_eq!(
+                (0..10).map(|x| x * (i as u64 + 1)).collect::<Vec<_>>(),
+                logarray.to_vec()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_commit_keeps_files_for_a_retry() {
+        let stores: HashMap<String, FaultyStore<MemoryBackedStore>> = ["a", "b"]
+            .iter()
+            .map(|name| (name.to_string(), FaultyStore::new(MemoryBackedStore::new())))
+            .collect();
+        let resolve_stores = stores.clone();
+        let batch = BatchWriter::new(move |name: &str| resolve_stores[name].clone());
+
+        for name in ["a", "b"] {
+            let mut file = batch.open_write(name);
+            file.write_all(name.as_bytes()).await.unwrap();
+            file.sync_all().await.unwrap();
+        }
+
+        stores["b"].fail_sync(io::ErrorKind::WriteZero);
+        let err = batch.commit().await.unwrap_err();
+        assert_eq!(io::ErrorKind::WriteZero, err.kind());
+        assert_eq!(vec!["a", "b"], batch.pending_files());
+
+        stores["b"].clear_faults();
+        batch.commit().await.unwrap();
+
+        assert!(batch.pending_files().is_empty());
+        for name in ["a", "b"] {
+            assert_eq!(name.as_bytes(), &stores[name].map().await.unwrap()[..]);
+        }
+    }
+}
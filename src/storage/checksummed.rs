@@ -0,0 +1,316 @@
+//! A store wrapper which appends a checksum to every file, and verifies it when mapping.
+//!
+//! Corruption on disk otherwise only shows up once a parser trips over it, as a bogus control
+//! word or a panic deep inside a data structure. Verifying a checksum on `map` turns it into an
+//! `InvalidData` error at the storage boundary instead.
+//!
+//! Unlike `SidecarChecksumStore`, the checksum is part of the file itself, so the inner store no
+//! longer contains a file in its usual format. Readers opened with `open_read_from` stop before
+//! the checksum, but are not verified, as they may start anywhere in the file.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::ready;
+use tokio::io::{self, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "xxhash")]
+use xxhash_rust::xxh64::Xxh64;
+
+use super::{FileLoad, FileStore, SyncableFile};
+use crate::util::Crc32;
+
+/// The hash function used for the checksum of a `ChecksummedStore`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// CRC-32, stored as 4 big-endian bytes.
+    Crc32,
+    /// xxHash64 with a seed of 0, stored as 8 big-endian bytes. Needs the `xxhash` feature.
+    #[cfg(feature = "xxhash")]
+    XxHash64,
+}
+
+impl ChecksumAlgorithm {
+    /// Returns the number of bytes the checksum takes at the end of a file.
+    pub fn checksum_len(self) -> usize {
+        match self {
+            ChecksumAlgorithm::Crc32 => 4,
+            #[cfg(feature = "xxhash")]
+            ChecksumAlgorithm::XxHash64 => 8,
+        }
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            ChecksumAlgorithm::Crc32 => Hasher::Crc32(Crc32::new()),
+            #[cfg(feature = "xxhash")]
+            ChecksumAlgorithm::XxHash64 => Hasher::XxHash64(Xxh64::new(0)),
+        }
+    }
+
+    /// Returns the checksum of `bytes`, encoded as it is stored.
+    pub fn checksum(self, bytes: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(bytes);
+
+        hasher.finalize()
+    }
+}
+
+enum Hasher {
+    Crc32(Crc32),
+    #[cfg(feature = "xxhash")]
+    XxHash64(Xxh64),
+}
+
+impl Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Crc32(crc) => crc.update(bytes),
+            #[cfg(feature = "xxhash")]
+            Hasher::XxHash64(xxh) => xxh.update(bytes),
+        }
+    }
+
+    fn finalize(&self) -> Vec<u8> {
+        match self {
+            Hasher::Crc32(crc) => crc.finalize().to_be_bytes().to_vec(),
+            #[cfg(feature = "xxhash")]
+            Hasher::XxHash64(xxh) => xxh.digest().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+/// A store whose files end in a checksum of their contents, which is verified when mapped.
+#[derive(Clone, Debug)]
+pub struct ChecksummedStore<F> {
+    inner: F,
+    algorithm: ChecksumAlgorithm,
+}
+
+impl<F> ChecksummedStore<F> {
+    pub fn new(inner: F, algorithm: ChecksumAlgorithm) -> Self {
+        Self { inner, algorithm }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn algorithm(&self) -> ChecksumAlgorithm {
+        self.algorithm
+    }
+}
+
+impl<F: FileLoad> ChecksummedStore<F> {
+    fn too_small(&self, size: usize) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "expected a file of at least {} bytes for the checksum, got {} bytes",
+                self.algorithm.checksum_len(),
+                size
+            ),
+        )
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for ChecksummedStore<F> {
+    type Read = io::Take<F::Read>;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.inner.exists().await
+    }
+
+    /// Returns the size of the contents, not counting the checksum.
+    async fn size(&self) -> io::Result<usize> {
+        let size = self.inner.size().await?;
+        size.checked_sub(self.algorithm.checksum_len())
+            .ok_or_else(|| self.too_small(size))
+    }
+
+    /// Open the file for reading, up to the checksum. Unlike `map`, this does not verify it.
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        let size = self.size().await?;
+        let reader = self.inner.open_read_from(offset).await?;
+
+        Ok(reader.take(size.saturating_sub(offset) as u64))
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        let mut contents = self.inner.map().await?;
+        let size = contents
+            .len()
+            .checked_sub(self.algorithm.checksum_len())
+            .ok_or_else(|| self.too_small(contents.len()))?;
+        let expected = contents.split_off(size);
+
+        let actual = self.algorithm.checksum(&contents);
+        if actual != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "checksum mismatch: file has {}, contents have {}",
+                    hex::encode(&expected),
+                    hex::encode(&actual)
+                ),
+            ));
+        }
+
+        Ok(contents)
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for ChecksummedStore<F> {
+    type Write = ChecksummedFile<F::Write>;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        Ok(ChecksummedFile {
+            inner: self.inner.open_write().await?,
+            hasher: self.algorithm.hasher(),
+        })
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<Self::Write> {
+        let size = size + self.algorithm.checksum_len();
+        Ok(ChecksummedFile {
+            inner: self.inner.open_write_sized(size).await?,
+            hasher: self.algorithm.hasher(),
+        })
+    }
+}
+
+/// A writer returned by `ChecksummedStore`, which appends the checksum of everything written to
+/// it on `sync_all`.
+pub struct ChecksummedFile<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: SyncableFile> AsyncWrite for ChecksummedFile<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.hasher.update(&buf[..written]);
+
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl<W: SyncableFile> SyncableFile for ChecksummedFile<W> {
+    async fn sync_all(mut self) -> io::Result<()> {
+        let checksum = self.hasher.finalize();
+        self.inner.write_all(&checksum).await?;
+        self.inner.flush().await?;
+
+        self.inner.sync_all().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::memory::MemoryBackedStore;
+
+    const ALGORITHMS: &[ChecksumAlgorithm] = &[
+        ChecksumAlgorithm::Crc32,
+        #[cfg(feature = "xxhash")]
+        ChecksumAlgorithm::XxHash64,
+    ];
+
+    async fn build_logarray<F: FileStore>(store: &F) -> io::Result<()> {
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await?, 10);
+        builder.push_vec((0..700).collect()).await?;
+        builder.finalize().await
+    }
+
+    #[tokio::test]
+    async fn checksummed_logarray_roundtrips() {
+        for &algorithm in ALGORITHMS {
+            let store = ChecksummedStore::new(MemoryBackedStore::new(), algorithm);
+            build_logarray(&store).await.unwrap();
+
+            let inner_size = store.inner().size().await.unwrap();
+            assert_eq!(
+                inner_size - algorithm.checksum_len(),
+                store.size().await.unwrap()
+            );
+
+            let logarray = LogArray::parse(store.map().await.unwrap()).unwrap();
+            assert_eq!((0..700).collect::<Vec<u64>>(), logarray.to_vec());
+
+            let mut tail = Vec::new();
+            store
+                .open_read_from(100)
+                .await
+                .unwrap()
+                .read_to_end(&mut tail)
+                .await
+                .unwrap();
+            assert_eq!(&store.map().await.unwrap()[100..], &tail[..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn corruption_fails_map() {
+        for &algorithm in ALGORITHMS {
+            let store = ChecksummedStore::new(MemoryBackedStore::new(), algorithm);
+            build_logarray(&store).await.unwrap();
+
+            let mut contents = store.inner().map().await.unwrap().to_vec();
+            contents[17] ^= 0x10;
+            let mut writer = store.inner().open_write().await.unwrap();
+            writer.write_all(&contents).await.unwrap();
+            writer.sync_all().await.unwrap();
+
+            let err = store.map().await.unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        }
+    }
+
+    #[tokio::test]
+    async fn file_shorter_than_checksum_fails_map() {
+        let inner = MemoryBackedStore::new();
+        let mut writer = inner.open_write().await.unwrap();
+        writer.write_all(&[1, 2, 3]).await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        let store = ChecksummedStore::new(inner, ChecksumAlgorithm::Crc32);
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            store.map().await.unwrap_err().kind()
+        );
+        assert!(store.size().await.is_err());
+    }
+
+    #[test]
+    fn checksum_check_values() {
+        assert_eq!(
+            0xcbf4_3926_u32.to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Crc32.checksum(b"123456789")
+        );
+        #[cfg(feature = "xxhash")]
+        assert_eq!(
+            0xef46_db37_51d8_e999_u64.to_be_bytes().to_vec(),
+            ChecksumAlgorithm::XxHash64.checksum(b"")
+        );
+    }
+}
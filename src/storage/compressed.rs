@@ -0,0 +1,335 @@
+//! A store wrapper which keeps files zstd compressed in the inner store.
+//!
+//! This is meant for rarely accessed layers, which are better off taking less space on disk than
+//! being fast to load.
+//!
+//! Writes are buffered uncompressed in memory, and compressed in one go on `sync_all`. A zstd
+//! stream can't be read from an arbitrary offset, so the first `map` or `open_read_from`
+//! decompresses the whole file into memory. The decompressed contents are cached, and shared
+//! between clones of the store, so that later reads don't have to decompress again. This means a
+//! compressed file that is in use takes as much memory as an uncompressed one.
+//!
+//! Not every file is worth that tradeoff. Dictionary blocks compress well, but offsets and log
+//! arrays hardly do. A store can therefore also be constructed in passthrough mode, where it
+//! leaves the contents as they are, and `for_file` picks between the two using a policy on the
+//! name of the file.
+
+use std::io::{Cursor, SeekFrom};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use tokio::io::{self, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::either::Either;
+
+use super::{FileLoad, FileStore, SyncableFile};
+
+/// The compression level used by `CompressedStore::new`, which is zstd's own default.
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+/// A store that compresses file contents before passing them on to the inner store.
+///
+/// In passthrough mode, contents are passed on uncompressed instead.
+#[derive(Clone, Debug)]
+pub struct CompressedStore<F> {
+    inner: F,
+    /// The zstd compression level, or `None` in passthrough mode.
+    level: Option<i32>,
+    decompressed: Arc<Mutex<Option<Bytes>>>,
+}
+
+impl<F> CompressedStore<F> {
+    pub fn new(inner: F) -> Self {
+        Self::with_level(inner, DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    /// Construct a store compressing at the given zstd compression level.
+    pub fn with_level(inner: F, level: i32) -> Self {
+        Self {
+            inner,
+            level: Some(level),
+            decompressed: Default::default(),
+        }
+    }
+
+    /// Construct a store which doesn't compress, reading and writing the inner store as is.
+    pub fn passthrough(inner: F) -> Self {
+        Self {
+            inner,
+            level: None,
+            decompressed: Default::default(),
+        }
+    }
+
+    /// Construct a store for the file called `name`, which is compressed at the default level if
+    /// `policy` returns `true` for that name, and passed through otherwise.
+    ///
+    /// The policy has to give the same answer for a file when it is written and when it is read
+    /// back.
+    pub fn for_file<P: Fn(&str) -> bool + ?Sized>(inner: F, name: &str, policy: &P) -> Self {
+        if policy(name) {
+            Self::new(inner)
+        } else {
+            Self::passthrough(inner)
+        }
+    }
+
+    /// Returns `true` if this store compresses, and `false` in passthrough mode.
+    pub fn is_compressed(&self) -> bool {
+        self.level.is_some()
+    }
+
+    /// Returns a reference to the wrapped store, which holds the compressed contents.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Drop the cached decompressed contents, so they are decompressed again on the next read.
+    pub fn clear_cache(&self) {
+        *self.decompressed.lock().unwrap() = None;
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for CompressedStore<F> {
+    type Read = Either<Cursor<Bytes>, F::Read>;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.inner.exists().await
+    }
+
+    /// Returns the size of the decompressed contents, which requires decompressing them.
+    async fn size(&self) -> io::Result<usize> {
+        if !self.is_compressed() {
+            return self.inner.size().await;
+        }
+
+        Ok(self.map().await?.len())
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        if !self.is_compressed() {
+            return Ok(Either::Right(self.inner.open_read_from(offset).await?));
+        }
+
+        let mut reader = Cursor::new(self.map().await?);
+        reader.seek(SeekFrom::Start(offset as u64)).await?;
+
+        Ok(Either::Left(reader))
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        if !self.is_compressed() {
+            return self.inner.map().await;
+        }
+
+        if let Some(contents) = self.decompressed.lock().unwrap().clone() {
+            return Ok(contents);
+        }
+
+        let compressed = self.inner.map().await?;
+        let contents = Bytes::from(zstd::stream::decode_all(&compressed[..])?);
+        *self.decompressed.lock().unwrap() = Some(contents.clone());
+
+        Ok(contents)
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for CompressedStore<F> {
+    type Write = CompressedFile<F::Write>;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        Ok(CompressedFile {
+            inner: self.inner.open_write().await?,
+            level: self.level,
+            buf: BytesMut::new(),
+            decompressed: self.decompressed.clone(),
+        })
+    }
+}
+
+/// A writer returned by `CompressedStore`, which compresses everything written to it on
+/// `sync_all`, or writes it straight to the inner file in passthrough mode.
+pub struct CompressedFile<W> {
+    inner: W,
+    level: Option<i32>,
+    buf: BytesMut,
+    decompressed: Arc<Mutex<Option<Bytes>>>,
+}
+
+impl<W: SyncableFile> AsyncWrite for CompressedFile<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+        if this.level.is_none() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        this.buf.extend_from_slice(buf);
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+        if this.level.is_none() {
+            return Pin::new(&mut this.inner).poll_flush(cx);
+        }
+
+        // nothing is written to the inner store before `sync_all`
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+        if this.level.is_none() {
+            return Pin::new(&mut this.inner).poll_shutdown(cx);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
+impl<W: SyncableFile> SyncableFile for CompressedFile<W> {
+    async fn sync_all(mut self) -> io::Result<()> {
+        let Some(level) = self.level else {
+            return self.inner.sync_all().await;
+        };
+
+        let compressed = zstd::bulk::compress(&self.buf, level)?;
+        self.inner.write_all(&compressed).await?;
+        self.inner.flush().await?;
+        self.inner.sync_all().await?;
+
+        // the file has been replaced, so the cache now holds the new contents
+        *self.decompressed.lock().unwrap() = Some(self.buf.freeze());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use tokio::io::AsyncReadExt;
+
+    async fn build_logarray<F: FileStore>(store: &F) -> io::Result<()> {
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await?, 16);
+        builder
+            .push_vec((0..2000).map(|i| i % 10).collect())
+            .await?;
+        builder.finalize().await
+    }
+
+    #[tokio::test]
+    async fn compressed_logarray_roundtrips() {
+        let inner = MemoryBackedStore::new();
+        let store = CompressedStore::new(inner.clone());
+        build_logarray(&store).await.unwrap();
+
+        let logarray = LogArray::parse(store.map().await.unwrap()).unwrap();
+        assert_eq!(
+            (0..2000).map(|i| i % 10).collect::<Vec<u64>>(),
+            logarray.to_vec()
+        );
+        // a repetitive array compresses well
+        assert!(inner.size().await.unwrap() * 10 < store.size().await.unwrap());
+
+        // a fresh store has to decompress the inner file itself
+        let reopened = CompressedStore::new(inner);
+        assert_eq!(store.map().await.unwrap(), reopened.map().await.unwrap());
+
+        let mut tail = Vec::new();
+        reopened
+            .open_read_from(4000)
+            .await
+            .unwrap()
+            .read_to_end(&mut tail)
+            .await
+            .unwrap();
+        assert_eq!(&store.map().await.unwrap()[4000..], &tail[..]);
+    }
+
+    #[tokio::test]
+    async fn rewriting_replaces_cached_contents() {
+        let store = CompressedStore::with_level(MemoryBackedStore::new(), 3);
+        build_logarray(&store).await.unwrap();
+        assert_eq!(
+            2000,
+            LogArray::parse(store.map().await.unwrap()).unwrap().len()
+        );
+
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 4);
+        builder.push_vec(vec![1, 2, 3]).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        assert_eq!(
+            vec![1, 2, 3],
+            LogArray::parse(store.map().await.unwrap())
+                .unwrap()
+                .to_vec()
+        );
+        store.clear_cache();
+        assert_eq!(
+            vec![1, 2, 3],
+            LogArray::parse(store.map().await.unwrap())
+                .unwrap()
+                .to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn policy_compresses_only_marked_files() {
+        let policy = |name: &str| name.ends_with("_blocks");
+        let blocks_inner = MemoryBackedStore::new();
+        let offsets_inner = MemoryBackedStore::new();
+        let blocks = CompressedStore::for_file(blocks_inner.clone(), "node_blocks", &policy);
+        let offsets = CompressedStore::for_file(offsets_inner.clone(), "node_offsets", &policy);
+        assert!(blocks.is_compressed());
+        assert!(!offsets.is_compressed());
+
+        build_logarray(&blocks).await.unwrap();
+        build_logarray(&offsets).await.unwrap();
+
+        // the unmarked file is stored as raw bytes
+        let raw = offsets_inner.map().await.unwrap();
+        assert_eq!(raw, offsets.map().await.unwrap());
+        assert_eq!(raw.len(), offsets.size().await.unwrap());
+        let mut tail = Vec::new();
+        offsets
+            .open_read_from(4000)
+            .await
+            .unwrap()
+            .read_to_end(&mut tail)
+            .await
+            .unwrap();
+        assert_eq!(&raw[4000..], &tail[..]);
+
+        // the marked file is stored compressed, but reads back the same contents
+        assert_eq!(raw, blocks.map().await.unwrap());
+        assert!(blocks_inner.size().await.unwrap() * 10 < raw.len());
+        assert_eq!(
+            raw,
+            zstd::stream::decode_all(&blocks_inner.map().await.unwrap()[..]).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn corrupt_compressed_file_fails_map() {
+        let inner = MemoryBackedStore::new();
+        let mut writer = inner.open_write().await.unwrap();
+        writer.write_all(b"not a zstd frame").await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        assert!(CompressedStore::new(inner).map().await.is_err());
+    }
+}
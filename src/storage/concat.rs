@@ -0,0 +1,181 @@
+//! A read-only view of a list of files as a single contiguous file.
+//!
+//! Large structures are sometimes written out in several segments. Wrapping the segments in a
+//! `ConcatStore` allows them to be loaded as if they had been written to one file.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::ready;
+use tokio::io::{self, AsyncRead, ReadBuf};
+
+use super::FileLoad;
+
+/// A store presenting an ordered list of segments as one file.
+#[derive(Clone, Debug)]
+pub struct ConcatStore<F> {
+    segments: Vec<F>,
+}
+
+impl<F> ConcatStore<F> {
+    pub fn new(segments: Vec<F>) -> Self {
+        Self { segments }
+    }
+
+    /// Returns the segments, in order.
+    pub fn segments(&self) -> &[F] {
+        &self.segments
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for ConcatStore<F> {
+    type Read = ConcatReader<F::Read>;
+
+    async fn exists(&self) -> io::Result<bool> {
+        for segment in self.segments.iter() {
+            if !segment.exists().await? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        let mut size = 0;
+        for segment in self.segments.iter() {
+            size += segment.size().await?;
+        }
+
+        Ok(size)
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        let mut readers = VecDeque::new();
+        let mut segment_start = 0;
+        for segment in self.segments.iter() {
+            let segment_size = segment.size().await?;
+            let segment_end = segment_start + segment_size;
+            if offset < segment_end {
+                let reader = if offset > segment_start {
+                    segment.open_read_from(offset - segment_start).await?
+                } else {
+                    segment.open_read().await?
+                };
+                readers.push_back(reader);
+            }
+            segment_start = segment_end;
+        }
+
+        Ok(ConcatReader { readers })
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        if self.segments.len() == 1 {
+            return self.segments[0].map().await;
+        }
+
+        let mut result = BytesMut::with_capacity(self.size().await?);
+        for segment in self.segments.iter() {
+            result.extend_from_slice(&segment.map().await?);
+        }
+
+        Ok(result.freeze())
+    }
+}
+
+/// A reader over the segments of a `ConcatStore`, which moves on to the next segment once the
+/// current one is exhausted.
+pub struct ConcatReader<R> {
+    readers: VecDeque<R>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ConcatReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        while let Some(reader) = self.readers.front_mut() {
+            let filled = buf.filled().len();
+            ready!(Pin::new(reader).poll_read(cx, buf))?;
+            if buf.filled().len() != filled || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            // this segment is exhausted
+            self.readers.pop_front();
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::{FileStore, SyncableFile};
+    use futures::TryStreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn store_with(bytes: &[u8]) -> MemoryBackedStore {
+        let store = MemoryBackedStore::new();
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(bytes).await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        store
+    }
+
+    #[tokio::test]
+    async fn split_logarray_parses_like_the_original() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 13);
+        builder.push_vec((0..500).map(|i| (i * 7919) % 8000).collect());
+        let whole = builder.finalize().freeze();
+
+        let split_at = 301;
+        let concat = ConcatStore::new(vec![
+            store_with(&whole[..split_at]).await,
+            store_with(&whole[split_at..]).await,
+        ]);
+
+        assert!(concat.exists().await.unwrap());
+        assert_eq!(whole.len(), concat.size().await.unwrap());
+        assert_eq!(whole, concat.map().await.unwrap());
+
+        let expected: Vec<u64> = LogArray::parse(whole.clone()).unwrap().iter().collect();
+        let parsed = LogArray::parse(concat.map().await.unwrap()).unwrap();
+        assert_eq!(expected, parsed.iter().collect::<Vec<_>>());
+
+        let streamed: Vec<u64> = logarray_stream_entries(concat.clone())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(expected, streamed);
+
+        let mut tail = Vec::new();
+        concat
+            .open_read_from(250)
+            .await
+            .unwrap()
+            .read_to_end(&mut tail)
+            .await
+            .unwrap();
+        assert_eq!(&whole[250..], &tail[..]);
+    }
+
+    #[tokio::test]
+    async fn missing_segment_means_nonexistent() {
+        let concat = ConcatStore::new(vec![store_with(b"abc").await, MemoryBackedStore::new()]);
+
+        assert!(!concat.exists().await.unwrap());
+    }
+}
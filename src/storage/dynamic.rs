@@ -0,0 +1,151 @@
+//! Type erasure for file backends.
+//!
+//! `FileLoad` has an associated reader type, so it can't be used as a trait object. `DynFileLoad`
+//! is an object-safe counterpart which is implemented for every `FileLoad`. A boxed
+//! `DynFileLoad` implements `FileLoad` again, so a backend chosen at runtime can be passed to
+//! anything that expects a `FileLoad`.
+//!
+//! The methods of `DynFileLoad` are prefixed with `dyn_`, so that they don't clash with the
+//! `FileLoad` methods when both traits are in scope.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use tokio::io::{self, AsyncRead};
+
+use super::FileLoad;
+
+/// A reader with its concrete type erased.
+pub type DynRead = Box<dyn AsyncRead + Unpin + Send>;
+
+/// An object-safe version of `FileLoad`.
+pub trait DynFileLoad: Send + Sync {
+    fn dyn_exists(&self) -> BoxFuture<'_, io::Result<bool>>;
+    fn dyn_size(&self) -> BoxFuture<'_, io::Result<usize>>;
+    fn dyn_open_read_from(&self, offset: usize) -> BoxFuture<'_, io::Result<DynRead>>;
+    fn dyn_map(&self) -> BoxFuture<'_, io::Result<Bytes>>;
+    fn dyn_map_range(&self, offset: usize, len: usize) -> BoxFuture<'_, io::Result<Bytes>>;
+
+    /// Clone this backend into a new box.
+    fn dyn_clone(&self) -> Box<dyn DynFileLoad>;
+}
+
+impl<F: 'static + FileLoad> DynFileLoad for F
+where
+    F::Read: 'static,
+{
+    fn dyn_exists(&self) -> BoxFuture<'_, io::Result<bool>> {
+        self.exists()
+    }
+
+    fn dyn_size(&self) -> BoxFuture<'_, io::Result<usize>> {
+        self.size()
+    }
+
+    fn dyn_open_read_from(&self, offset: usize) -> BoxFuture<'_, io::Result<DynRead>> {
+        Box::pin(async move {
+            let reader = self.open_read_from(offset).await?;
+            Ok(Box::new(reader) as DynRead)
+        })
+    }
+
+    fn dyn_map(&self) -> BoxFuture<'_, io::Result<Bytes>> {
+        self.map()
+    }
+
+    fn dyn_map_range(&self, offset: usize, len: usize) -> BoxFuture<'_, io::Result<Bytes>> {
+        self.map_range(offset, len)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn DynFileLoad> {
+        Box::new(self.clone())
+    }
+}
+
+// A boxed backend is itself a `FileLoad`, and therefore also a `DynFileLoad`. The methods below
+// explicitly go through the box, as calling them on the box itself would recurse forever.
+impl Clone for Box<dyn DynFileLoad> {
+    fn clone(&self) -> Self {
+        (**self).dyn_clone()
+    }
+}
+
+#[async_trait]
+impl FileLoad for Box<dyn DynFileLoad> {
+    type Read = DynRead;
+
+    async fn exists(&self) -> io::Result<bool> {
+        (**self).dyn_exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        (**self).dyn_size().await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<DynRead> {
+        (**self).dyn_open_read_from(offset).await
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        (**self).dyn_map().await
+    }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        (**self).dyn_map_range(offset, len).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::concat::ConcatStore;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::{FileStore, SyncableFile};
+    use bytes::BytesMut;
+    use futures::TryStreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    async fn store_with(bytes: &[u8]) -> MemoryBackedStore {
+        let store = MemoryBackedStore::new();
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(bytes).await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        store
+    }
+
+    #[tokio::test]
+    async fn erased_stores_can_be_mapped_and_streamed() {
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 7);
+        builder.push_vec((0..100).collect());
+        let bytes = builder.finalize().freeze();
+
+        let stores: Vec<Box<dyn DynFileLoad>> = vec![
+            Box::new(store_with(&bytes).await),
+            Box::new(ConcatStore::new(vec![
+                store_with(&bytes[..10]).await,
+                store_with(&bytes[10..]).await,
+            ])),
+        ];
+
+        for store in stores {
+            assert!(store.dyn_exists().await.unwrap());
+            assert_eq!(bytes.len(), store.dyn_size().await.unwrap());
+
+            let logarray = LogArray::parse(store.dyn_map().await.unwrap()).unwrap();
+            assert_eq!(
+                (0..100).collect::<Vec<u64>>(),
+                logarray.iter().collect::<Vec<_>>()
+            );
+
+            let streamed: Vec<u64> = logarray_stream_entries(store.clone())
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!((0..100).collect::<Vec<u64>>(), streamed);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! A store wrapper which injects I/O errors, for testing error handling.
+//!
+//! Code built on the storage traits should surface storage errors rather than panic or silently
+//! produce truncated results. A `FaultyStore` makes it possible to test this by failing specific
+//! operations on demand. Faults are shared between clones of the store, so they can be configured
+//! after the store has been handed to the code under test.
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::ready;
+use tokio::io::{self, AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{FileLoad, FileStore, SyncableFile};
+
+fn injected_error(kind: io::ErrorKind, operation: &str) -> io::Error {
+    io::Error::new(kind, format!("injected {} failure", operation))
+}
+
+#[derive(Default, Debug)]
+struct Faults {
+    next_map: Option<io::ErrorKind>,
+    reads_after: Option<(usize, io::ErrorKind)>,
+    sync: Option<io::ErrorKind>,
+}
+
+/// A store that passes operations through to the inner store, unless a fault has been configured
+/// for them.
+#[derive(Clone, Debug)]
+pub struct FaultyStore<F> {
+    inner: F,
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl<F> FaultyStore<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            faults: Default::default(),
+        }
+    }
+
+    /// Fail the next call to `map` with an error of the given kind.
+    pub fn fail_next_map(&self, kind: io::ErrorKind) {
+        self.faults.lock().unwrap().next_map = Some(kind);
+    }
+
+    /// Fail reads on readers opened from now on once they have returned `n_bytes` bytes.
+    pub fn fail_reads_after(&self, n_bytes: usize, kind: io::ErrorKind) {
+        self.faults.lock().unwrap().reads_after = Some((n_bytes, kind));
+    }
+
+    /// Fail every `sync_all` on writers of this store from now on.
+    pub fn fail_sync(&self, kind: io::ErrorKind) {
+        self.faults.lock().unwrap().sync = Some(kind);
+    }
+
+    /// Remove all configured faults.
+    pub fn clear_faults(&self) {
+        *self.faults.lock().unwrap() = Faults::default();
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for FaultyStore<F> {
+    type Read = FaultyReader<F::Read>;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.inner.exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.inner.size().await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        let fail_after = self.faults.lock().unwrap().reads_after;
+
+        Ok(FaultyReader {
+            inner: self.inner.open_read_from(offset).await?,
+            read: 0,
+            fail_after,
+        })
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        let fault = self.faults.lock().unwrap().next_map.take();
+        match fault {
+            Some(kind) => Err(injected_error(kind, "map")),
+            None => self.inner.map().await,
+        }
+    }
+
+    /// Read a range of the file, which fails like a reader would if reads are configured to fail
+    /// before `len` bytes.
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        let fault = self.faults.lock().unwrap().reads_after;
+        match fault {
+            Some((n_bytes, kind)) if n_bytes < len => Err(injected_error(kind, "read")),
+            _ => self.inner.map_range(offset, len).await,
+        }
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for FaultyStore<F> {
+    type Write = FaultyFile<F::Write>;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        Ok(FaultyFile {
+            inner: self.inner.open_write().await?,
+            faults: self.faults.clone(),
+        })
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<Self::Write> {
+        Ok(FaultyFile {
+            inner: self.inner.open_write_sized(size).await?,
+            faults: self.faults.clone(),
+        })
+    }
+}
+
+/// A reader returned by `FaultyStore`, which fails once it has returned a configured amount of
+/// bytes.
+pub struct FaultyReader<R> {
+    inner: R,
+    read: usize,
+    fail_after: Option<(usize, io::ErrorKind)>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for FaultyReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let (limit, kind) = match this.fail_after {
+            None => return Pin::new(&mut this.inner).poll_read(cx, buf),
+            Some(fault) => fault,
+        };
+        if this.read >= limit {
+            return Poll::Ready(Err(injected_error(kind, "read")));
+        }
+
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        // anything beyond the limit is dropped, as the next read is going to fail anyway
+        let allowed = std::cmp::min(buf.filled().len() - filled, limit - this.read);
+        buf.set_filled(filled + allowed);
+        this.read += allowed;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A writer returned by `FaultyStore`, which fails `sync_all` if the store is configured to.
+pub struct FaultyFile<W> {
+    inner: W,
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl<W: SyncableFile> AsyncWrite for FaultyFile<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl<W: SyncableFile> SyncableFile for FaultyFile<W> {
+    async fn sync_all(self) -> io::Result<()> {
+        let fault = self.faults.lock().unwrap().sync;
+        match fault {
+            Some(kind) => Err(injected_error(kind, "sync")),
+            None => self.inner.sync_all().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use futures::TryStreamExt;
+
+    async fn build_logarray<F: FileStore>(store: &F) -> io::Result<()> {
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await?, 9);
+        builder.push_vec((0..300).collect()).await?;
+        builder.finalize().await
+    }
+
+    async fn load_logarray<F: FileLoad>(store: &F) -> io::Result<LogArray> {
+        Ok(LogArray::parse(store.map().await?)?)
+    }
+
+    #[tokio::test]
+    async fn injected_map_error_propagates_through_parse() {
+        let store = FaultyStore::new(MemoryBackedStore::new());
+        build_logarray(&store).await.unwrap();
+
+        store.fail_next_map(io::ErrorKind::Other);
+        let err = load_logarray(&store).await.err().unwrap();
+        assert_eq!(io::ErrorKind::Other, err.kind());
+
+        // the fault only applies to a single map
+        assert_eq!(300, load_logarray(&store).await.unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn injected_read_error_ends_stream() {
+        let store = FaultyStore::new(MemoryBackedStore::new());
+        build_logarray(&store).await.unwrap();
+
+        store.fail_reads_after(100, io::ErrorKind::UnexpectedEof);
+        let err = logarray_stream_entries(store.clone())
+            .await
+            .unwrap()
+            .try_collect::<Vec<u64>>()
+            .await
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+        assert_eq!(
+            io::ErrorKind::UnexpectedEof,
+            store.map_range(0, 101).await.unwrap_err().kind()
+        );
+        assert_eq!(100, store.map_range(0, 100).await.unwrap().len());
+
+        store.clear_faults();
+        let entries: Vec<u64> = logarray_stream_entries(store)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!((0..300).collect::<Vec<_>>(), entries);
+    }
+
+    #[tokio::test]
+    async fn injected_sync_error_fails_finalize() {
+        let store = FaultyStore::new(MemoryBackedStore::new());
+        store.fail_sync(io::ErrorKind::WriteZero);
+
+        let err = build_logarray(&store).await.unwrap_err();
+        assert_eq!(io::ErrorKind::WriteZero, err.kind());
+    }
+}
@@ -33,6 +33,19 @@ impl FileBackedStore {
     pub fn new<P: Into<PathBuf>>(path: P) -> FileBackedStore {
         FileBackedStore { path: path.into() }
     }
+
+    /// Open the file for writing without wrapping it in a `BufWriter`.
+    ///
+    /// This is meant for writers that hand over large chunks at a time, such as a buffer built up
+    /// in memory and written with a single `write_all`, where the extra buffer only adds a copy.
+    /// Every write goes straight to the file, so for writers doing many small writes, like
+    /// `LogArrayFileBuilder`, `open_write` is the better choice.
+    pub async fn open_write_unbuffered(&self) -> io::Result<File> {
+        let mut options = tokio::fs::OpenOptions::new();
+        options.read(true).write(true).create(true);
+
+        options.open(&self.path).await
+    }
 }
 
 #[async_trait]
@@ -79,6 +92,17 @@ impl FileLoad for FileBackedStore {
             Ok(b.freeze())
         }
     }
+
+    /// Read `len` bytes at `offset` with a single seek and read.
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        let mut file = File::open(&self.path).await?;
+        file.seek(SeekFrom::Start(offset as u64)).await?;
+
+        let mut buf = BytesMut::zeroed(len);
+        file.read_exact(&mut buf[..]).await?;
+
+        Ok(buf.freeze())
+    }
 }
 
 #[async_trait]
@@ -86,10 +110,131 @@ impl FileStore for FileBackedStore {
     type Write = BufWriter<File>;
 
     async fn open_write(&self) -> io::Result<BufWriter<File>> {
-        let mut options = tokio::fs::OpenOptions::new();
-        options.read(true).write(true).create(true);
-        let file = options.open(&self.path).await?;
+        let file = self.open_write_unbuffered().await?;
 
         Ok(BufWriter::new(file))
     }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<BufWriter<File>> {
+        let writer = self.open_write().await?;
+        preallocate(writer.get_ref(), size)?;
+
+        Ok(writer)
+    }
+}
+
+/// Reserve disk space for `size` bytes without changing the reported file size, so that a file
+/// that is written incrementally does not end up fragmented.
+#[cfg(target_os = "linux")]
+fn preallocate(file: &File, size: usize) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if size == 0 {
+        return Ok(());
+    }
+
+    // unsafe justification: the file descriptor stays valid for the
+    // duration of the call, as we are borrowing the file it belongs to.
+    let result = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            0,
+            size as libc::off_t,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        // preallocation is only a hint, so file systems that can't do it are fine
+        Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => Ok(()),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preallocate(_file: &File, _size: usize) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::temp::TempFileStore;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn sized_write_produces_exact_content() {
+        let store = TempFileStore::new();
+
+        let values: Vec<u64> = (0..1000).map(|i| i % 100).collect();
+        let size = logarray_length_from_len_width(values.len() as u64, 7) + 8;
+        let mut builder = LogArrayFileBuilder::new(store.open_write_sized(size).await.unwrap(), 7);
+        builder.push_vec(values.clone()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let mapped = store.map().await.unwrap();
+        assert_eq!(size, mapped.len());
+        let logarray = LogArray::parse(mapped).unwrap();
+        assert_eq!(values, logarray.iter().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn oversized_hint_does_not_change_file_size() {
+        let store = TempFileStore::new();
+
+        let mut writer = store.open_write_sized(1 << 20).await.unwrap();
+        writer.write_all(b"hello").await.unwrap();
+        writer.flush().await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        assert_eq!(&b"hello"[..], &store.map().await.unwrap()[..]);
+    }
+
+    #[tokio::test]
+    async fn unbuffered_write_matches_buffered_write() {
+        let buffered = TempFileStore::new();
+        let temp = TempFileStore::new();
+        let unbuffered = FileBackedStore::new(temp.path());
+
+        let values: Vec<u64> = (0..5000).map(|i| i * 7 % 1000).collect();
+        let mut builder = LogArrayFileBuilder::new(buffered.open_write().await.unwrap(), 10);
+        builder.push_vec(values.clone()).await.unwrap();
+        builder.finalize().await.unwrap();
+        let mut builder = LogArrayBufBuilder::new(BytesMut::new(), 10);
+        builder.push_vec(values.clone());
+        let mut file = unbuffered.open_write_unbuffered().await.unwrap();
+        file.write_all(&builder.finalize()).await.unwrap();
+        file.sync_all().await.unwrap();
+
+        let unbuffered_contents = unbuffered.map().await.unwrap();
+        assert_eq!(buffered.map().await.unwrap(), unbuffered_contents);
+        assert_eq!(
+            values,
+            LogArray::parse(unbuffered_contents).unwrap().to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn map_range_reads_control_word() {
+        let temp = TempFileStore::new();
+        let store = FileBackedStore::new(temp.path());
+
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 13);
+        builder.push_vec((0..300).collect()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let size = store.size().await.unwrap();
+        let control_word = store.map_range(size - 8, 8).await.unwrap();
+        let length_and_width = logarray_file_get_length_and_width(store.clone()).await;
+        let past_end = store.map_range(size - 4, 8).await;
+
+        assert_eq!((300, 13), parse_control_word(&control_word));
+        assert_eq!((300, 13), length_and_width.unwrap());
+        assert_eq!(io::ErrorKind::UnexpectedEof, past_end.unwrap_err().kind());
+    }
 }
@@ -143,18 +143,20 @@ impl FileLoad for MemoryBackedStore {
 
     async fn size(&self) -> io::Result<usize> {
         match &*self.contents.read().unwrap() {
-            MemoryBackedStoreContents::Nonexistent => {
-                panic!("tried to retrieve size of nonexistent memory file")
-            }
+            MemoryBackedStoreContents::Nonexistent => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "tried to retrieve size of a nonexistent memory file",
+            )),
             MemoryBackedStoreContents::Existent(bytes) => Ok(bytes.len()),
         }
     }
 
     async fn open_read_from(&self, offset: usize) -> io::Result<MemoryBackedStoreReader> {
         match &*self.contents.read().unwrap() {
-            MemoryBackedStoreContents::Nonexistent => {
-                panic!("tried to open nonexistent memory file for reading")
-            }
+            MemoryBackedStoreContents::Nonexistent => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "tried to open a nonexistent memory file for reading",
+            )),
             MemoryBackedStoreContents::Existent(bytes) => Ok(MemoryBackedStoreReader {
                 bytes: bytes.clone(),
                 pos: offset,
@@ -171,4 +173,83 @@ impl FileLoad for MemoryBackedStore {
             MemoryBackedStoreContents::Existent(bytes) => Ok(bytes.clone()),
         }
     }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        let bytes = self.map().await?;
+        match offset.checked_add(len) {
+            Some(end) if end <= bytes.len() => Ok(bytes.slice(offset..end)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "tried to read {} bytes at offset {} from a memory file of {} bytes",
+                    len,
+                    offset,
+                    bytes.len()
+                ),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn nonexistent_file_errors_until_synced() {
+        let store = MemoryBackedStore::new();
+        assert!(!store.exists().await.unwrap());
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            store.size().await.unwrap_err().kind()
+        );
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            store.open_read_from(0).await.err().unwrap().kind()
+        );
+
+        // a writer that is still open doesn't make the file exist yet
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(b"abc").await.unwrap();
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            store.open_read().await.err().unwrap().kind()
+        );
+
+        writer.sync_all().await.unwrap();
+        assert_eq!(3, store.size().await.unwrap());
+        let mut contents = Vec::new();
+        store
+            .open_read_from(1)
+            .await
+            .unwrap()
+            .read_to_end(&mut contents)
+            .await
+            .unwrap();
+        assert_eq!(b"bc", &contents[..]);
+    }
+
+    #[tokio::test]
+    async fn map_range_slices_contents() {
+        let store = MemoryBackedStore::new();
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(b"0123456789").await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        assert_eq!(&b"345"[..], store.map_range(3, 3).await.unwrap());
+        assert_eq!(&b""[..], store.map_range(10, 0).await.unwrap());
+        assert_eq!(
+            io::ErrorKind::UnexpectedEof,
+            store.map_range(8, 3).await.unwrap_err().kind()
+        );
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            MemoryBackedStore::new()
+                .map_range(0, 1)
+                .await
+                .unwrap_err()
+                .kind()
+        );
+    }
 }
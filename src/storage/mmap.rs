@@ -0,0 +1,153 @@
+//! A file backed store which memory maps files rather than reading them into memory.
+//!
+//! `FileBackedStore::map` reads the whole file into a buffer, which for large layers doubles the
+//! memory use and makes loading slow. `MmapBackedStore::map` returns a `Bytes` backed by a memory
+//! mapping instead, so the contents are paged in lazily through the OS page cache.
+//!
+//! A mapped file must not be modified while any of its mapped buffers is alive. Layer files are
+//! never modified once written, but this store can't enforce that.
+
+use std::io::{Cursor, SeekFrom};
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use memmap2::Mmap;
+use tokio::fs::File;
+use tokio::io::{self, AsyncSeekExt, BufWriter};
+
+use super::file::FileBackedStore;
+use super::{FileLoad, FileStore};
+
+/// A store for a file on disk, which is memory mapped when mapped.
+#[derive(Clone, Debug)]
+pub struct MmapBackedStore {
+    path: PathBuf,
+    store: FileBackedStore,
+}
+
+impl MmapBackedStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> MmapBackedStore {
+        let path = path.into();
+
+        MmapBackedStore {
+            store: FileBackedStore::new(path.clone()),
+            path,
+        }
+    }
+}
+
+#[async_trait]
+impl FileLoad for MmapBackedStore {
+    type Read = Cursor<Bytes>;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.store.exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.store.size().await
+    }
+
+    /// Open a reader over the mapped file, starting at `offset`.
+    async fn open_read_from(&self, offset: usize) -> io::Result<Cursor<Bytes>> {
+        let mut reader = Cursor::new(self.map().await?);
+        reader.seek(SeekFrom::Start(offset as u64)).await?;
+
+        Ok(reader)
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        let file = File::open(&self.path).await?.into_std().await;
+        if file.metadata()?.len() == 0 {
+            // mapping an empty file fails on some platforms
+            return Ok(Bytes::new());
+        }
+
+        // unsafe justification: the mapping is only undefined behavior if
+        // the file is modified while it is mapped, which is documented to
+        // be unsupported for this store.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Ok(Bytes::from_owner(mmap))
+    }
+
+    /// Read a range of the file directly, as mapping it just for a few bytes isn't worth it.
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        self.store.map_range(offset, len).await
+    }
+}
+
+#[async_trait]
+impl FileStore for MmapBackedStore {
+    type Write = BufWriter<File>;
+
+    async fn open_write(&self) -> io::Result<BufWriter<File>> {
+        self.store.open_write().await
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<BufWriter<File>> {
+        self.store.open_write_sized(size).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::temp::TempFileStore;
+    use crate::storage::SyncableFile;
+    use futures::TryStreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn mapped_logarray_matches_written_one() {
+        let temp = TempFileStore::new();
+        let store = MmapBackedStore::new(temp.path());
+        assert!(!store.exists().await.unwrap());
+
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 11);
+        builder.push_vec((0..1000).collect()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let mapped = store.map().await.unwrap();
+        assert_eq!(temp.map().await.unwrap(), mapped);
+        let logarray = LogArray::parse(mapped).unwrap();
+        assert_eq!((0..1000).collect::<Vec<u64>>(), logarray.to_vec());
+
+        let streamed: Vec<u64> = logarray_stream_entries(store)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!((0..1000).collect::<Vec<u64>>(), streamed);
+    }
+
+    #[tokio::test]
+    async fn read_from_offset_and_empty_file() {
+        let temp = TempFileStore::new();
+        let store = MmapBackedStore::new(temp.path());
+        assert!(store.map().await.is_err());
+
+        let mut writer = store.open_write().await.unwrap();
+        writer.flush().await.unwrap();
+        writer.sync_all().await.unwrap();
+        assert!(store.map().await.unwrap().is_empty());
+
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(b"hello, mapped world").await.unwrap();
+        writer.flush().await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        let mut contents = String::new();
+        store
+            .open_read_from(7)
+            .await
+            .unwrap()
+            .read_to_string(&mut contents)
+            .await
+            .unwrap();
+        assert_eq!("mapped world", contents);
+    }
+}
@@ -1,5 +1,23 @@
+pub mod batch;
+pub mod checksummed;
+#[cfg(feature = "zstd")]
+pub mod compressed;
+pub mod concat;
+pub mod dynamic;
+pub mod faulty;
 pub mod file;
 pub mod memory;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "object-store")]
+pub mod object;
+pub mod overlay;
+pub mod profiled;
+pub mod readonly;
+pub mod retry;
+pub mod sidecar;
+pub mod temp;
+pub mod timeout;
 pub mod types;
 
 pub use types::*;
@@ -0,0 +1,265 @@
+//! A store which keeps each file as an object in an `object_store::ObjectStore`, such as S3.
+//!
+//! Every operation is a request to the object store, so reads avoid fetching more than they need.
+//! `exists` and `size` only do a HEAD request, and `open_read_from` and `map_range` do a ranged
+//! GET. This matters for the succinct structures, whose readers start out by reading a control
+//! word at the end of the file.
+//!
+//! Objects can't be appended to, so writes are buffered in memory and stored with a single PUT
+//! on `sync_all`.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use object_store::path::Path;
+use object_store::{GetOptions, GetRange, ObjectStore, PutPayload};
+use tokio::io::{self, AsyncWrite};
+use tokio_util::io::StreamReader;
+
+use super::{FileLoad, FileStore, SyncableFile};
+
+/// A store for a single object in an object store.
+#[derive(Clone, Debug)]
+pub struct ObjectStoreBackedStore {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+}
+
+impl ObjectStoreBackedStore {
+    pub fn new(store: Arc<dyn ObjectStore>, path: Path) -> Self {
+        Self { store, path }
+    }
+
+    /// Returns a reference to the object store this file is kept in.
+    pub fn store(&self) -> &Arc<dyn ObjectStore> {
+        &self.store
+    }
+
+    /// Returns the location of the object in the object store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[async_trait]
+impl FileLoad for ObjectStoreBackedStore {
+    type Read = StreamReader<BoxStream<'static, io::Result<Bytes>>, Bytes>;
+
+    async fn exists(&self) -> io::Result<bool> {
+        match self.store.head(&self.path).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        Ok(self.store.head(&self.path).await?.size as usize)
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        let options = GetOptions {
+            range: (offset != 0).then_some(GetRange::Offset(offset as u64)),
+            ..Default::default()
+        };
+        let stream = match self.store.get_opts(&self.path, options).await {
+            Ok(result) => result.into_stream().map_err(io::Error::from).boxed(),
+            // object stores refuse ranges starting at the end, which is just an empty read
+            Err(e) => {
+                if offset == 0 || offset < self.size().await? {
+                    return Err(e.into());
+                }
+                stream::empty().boxed()
+            }
+        };
+
+        Ok(StreamReader::new(stream))
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        Ok(self.store.get(&self.path).await?.bytes().await?)
+    }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        if len == 0 {
+            return Ok(Bytes::new());
+        }
+
+        let range = offset as u64..(offset + len) as u64;
+        let bytes = self.store.get_range(&self.path, range).await?;
+        // a range past the end of the object is cut short rather than refused
+        if bytes.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "expected {} bytes at offset {}, but only {} were available",
+                    len,
+                    offset,
+                    bytes.len()
+                ),
+            ));
+        }
+
+        Ok(bytes)
+    }
+}
+
+#[async_trait]
+impl FileStore for ObjectStoreBackedStore {
+    type Write = ObjectStoreFile;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        Ok(ObjectStoreFile {
+            store: self.store.clone(),
+            path: self.path.clone(),
+            buf: BytesMut::new(),
+        })
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<Self::Write> {
+        Ok(ObjectStoreFile {
+            store: self.store.clone(),
+            path: self.path.clone(),
+            buf: BytesMut::with_capacity(size),
+        })
+    }
+}
+
+/// A writer returned by `ObjectStoreBackedStore`, which stores everything written to it as the
+/// object on `sync_all`.
+pub struct ObjectStoreFile {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    buf: BytesMut,
+}
+
+impl AsyncWrite for ObjectStoreFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.get_mut().buf.extend_from_slice(buf);
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        // nothing is sent to the object store before `sync_all`
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
+impl SyncableFile for ObjectStoreFile {
+    async fn sync_all(self) -> io::Result<()> {
+        self.store
+            .put(&self.path, PutPayload::from(self.buf.freeze()))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use object_store::memory::InMemory;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn store(name: &str) -> ObjectStoreBackedStore {
+        ObjectStoreBackedStore::new(Arc::new(InMemory::new()), Path::from(name))
+    }
+
+    async fn read_from(store: &ObjectStoreBackedStore, offset: usize) -> Vec<u8> {
+        let mut contents = Vec::new();
+        store
+            .open_read_from(offset)
+            .await
+            .unwrap()
+            .read_to_end(&mut contents)
+            .await
+            .unwrap();
+
+        contents
+    }
+
+    #[tokio::test]
+    async fn object_logarray_roundtrips() {
+        let store = store("layer/logarray");
+        assert!(!store.exists().await.unwrap());
+
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 10);
+        builder.push_vec((0..1000).collect()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        assert!(store.exists().await.unwrap());
+        let contents = store.map().await.unwrap();
+        assert_eq!(contents.len(), store.size().await.unwrap());
+        assert_eq!(
+            (0..1000).collect::<Vec<u64>>(),
+            LogArray::parse(contents.clone()).unwrap().to_vec()
+        );
+
+        assert_eq!(
+            (1000, 10),
+            logarray_file_get_length_and_width(store.clone())
+                .await
+                .unwrap()
+        );
+        assert_eq!(&contents[100..], &read_from(&store, 100).await[..]);
+        assert_eq!(&contents[..], &read_from(&store, 0).await[..]);
+        assert!(read_from(&store, contents.len()).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn map_range_past_the_end_fails() {
+        let store = store("file");
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(b"0123456789").await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        assert_eq!(&b"345"[..], &store.map_range(3, 3).await.unwrap()[..]);
+        assert!(store.map_range(10, 0).await.unwrap().is_empty());
+        assert_eq!(
+            io::ErrorKind::UnexpectedEof,
+            store.map_range(8, 3).await.unwrap_err().kind()
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_object_is_not_found() {
+        let store = store("missing");
+
+        assert!(!store.exists().await.unwrap());
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            store.size().await.unwrap_err().kind()
+        );
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            store.map().await.unwrap_err().kind()
+        );
+        assert!(store.open_read().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn empty_object_reads_empty() {
+        let store = store("empty");
+        store.open_write().await.unwrap().sync_all().await.unwrap();
+
+        assert!(store.exists().await.unwrap());
+        assert_eq!(0, store.size().await.unwrap());
+        assert!(store.map().await.unwrap().is_empty());
+        assert!(read_from(&store, 0).await.is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+//! A store wrapper which keeps writes apart from the store it wraps.
+//!
+//! Speculative layer builds should not touch the files they were built from. An `OverlayStore`
+//! sends every write to a separate overlay store, such as a `MemoryBackedStore`, while reads are
+//! served from the overlay once it has been written to, and from the base store before that.
+//! Throwing away the overlay leaves the base store as it was.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io;
+use tokio_util::either::Either;
+
+use super::{FileLoad, FileStore};
+
+/// A store that writes to `overlay`, and reads from `overlay` if it exists and from `base`
+/// otherwise.
+#[derive(Clone, Debug)]
+pub struct OverlayStore<Base, Over> {
+    base: Base,
+    overlay: Over,
+}
+
+impl<Base, Over> OverlayStore<Base, Over> {
+    pub fn new(base: Base, overlay: Over) -> Self {
+        Self { base, overlay }
+    }
+
+    /// Returns a reference to the store that reads fall through to.
+    pub fn base(&self) -> &Base {
+        &self.base
+    }
+
+    /// Returns a reference to the store that receives the writes.
+    pub fn overlay(&self) -> &Over {
+        &self.overlay
+    }
+
+    pub fn into_parts(self) -> (Base, Over) {
+        (self.base, self.overlay)
+    }
+}
+
+#[async_trait]
+impl<Base: FileLoad, Over: FileLoad> FileLoad for OverlayStore<Base, Over> {
+    type Read = Either<Over::Read, Base::Read>;
+
+    async fn exists(&self) -> io::Result<bool> {
+        Ok(self.overlay.exists().await? || self.base.exists().await?)
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        if self.overlay.exists().await? {
+            self.overlay.size().await
+        } else {
+            self.base.size().await
+        }
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        if self.overlay.exists().await? {
+            Ok(Either::Left(self.overlay.open_read_from(offset).await?))
+        } else {
+            Ok(Either::Right(self.base.open_read_from(offset).await?))
+        }
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        if self.overlay.exists().await? {
+            self.overlay.map().await
+        } else {
+            self.base.map().await
+        }
+    }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        if self.overlay.exists().await? {
+            self.overlay.map_range(offset, len).await
+        } else {
+            self.base.map_range(offset, len).await
+        }
+    }
+}
+
+#[async_trait]
+impl<Base: FileLoad, Over: FileStore> FileStore for OverlayStore<Base, Over> {
+    type Write = Over::Write;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        self.overlay.open_write().await
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<Self::Write> {
+        self.overlay.open_write_sized(size).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use tokio::io::AsyncReadExt;
+
+    async fn build_logarray<F: FileStore>(store: &F, len: u64) -> io::Result<()> {
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await?, 10);
+        builder.push_vec((0..len).collect()).await?;
+        builder.finalize().await
+    }
+
+    #[tokio::test]
+    async fn reads_fall_through_to_base() {
+        let base = MemoryBackedStore::new();
+        build_logarray(&base, 100).await.unwrap();
+        let store = OverlayStore::new(base.clone(), MemoryBackedStore::new());
+
+        assert!(store.exists().await.unwrap());
+        assert_eq!(base.size().await.unwrap(), store.size().await.unwrap());
+        assert_eq!(base.map().await.unwrap(), store.map().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn writes_shadow_base_without_changing_it() {
+        let base = MemoryBackedStore::new();
+        build_logarray(&base, 100).await.unwrap();
+        let base_contents = base.map().await.unwrap();
+        let store = OverlayStore::new(base.clone(), MemoryBackedStore::new());
+
+        build_logarray(&store, 200).await.unwrap();
+
+        let logarray = LogArray::parse(store.map().await.unwrap()).unwrap();
+        assert_eq!((0..200).collect::<Vec<_>>(), logarray.to_vec());
+        let mut read = Vec::new();
+        store
+            .open_read()
+            .await
+            .unwrap()
+            .read_to_end(&mut read)
+            .await
+            .unwrap();
+        assert_eq!(store.map().await.unwrap(), read);
+        assert_eq!(read.len(), store.size().await.unwrap());
+
+        assert_eq!(base_contents, base.map().await.unwrap());
+        assert_eq!(base_contents, store.into_parts().0.map().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn missing_in_both_does_not_exist() {
+        let store = OverlayStore::new(MemoryBackedStore::new(), MemoryBackedStore::new());
+
+        assert!(!store.exists().await.unwrap());
+        assert!(store.map().await.is_err());
+    }
+}
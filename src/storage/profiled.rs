@@ -0,0 +1,300 @@
+//! A store wrapper which records how long storage operations take.
+//!
+//! Byte counts alone don't tell whether a slow layer build is waiting on reads or on fsyncs. A
+//! `ProfiledStore` keeps a latency histogram for each kind of operation, so the two can be told
+//! apart. The histograms are shared between clones of the store and its writers.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io::{self, AsyncWrite};
+
+use super::{FileLoad, FileStore, SyncableFile};
+
+/// The number of buckets in a `LatencyHistogram`.
+pub const LATENCY_BUCKETS: usize = 32;
+
+/// A histogram of latencies, with buckets that double in size.
+///
+/// Bucket 0 counts latencies below 1µs, and bucket `i` counts latencies from `2^(i-1)`µs up to
+/// `2^i`µs. The last bucket also counts everything longer than that.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    /// Returns the index of the bucket which counts `latency`.
+    pub fn bucket_index(latency: Duration) -> usize {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let index = (u64::BITS - micros.leading_zeros()) as usize;
+
+        index.min(LATENCY_BUCKETS - 1)
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        self.buckets[Self::bucket_index(latency)] += 1;
+    }
+
+    /// Returns the number of latencies counted in each bucket.
+    pub fn buckets(&self) -> &[u64; LATENCY_BUCKETS] {
+        &self.buckets
+    }
+
+    /// Returns the total number of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+}
+
+/// The latency histograms of each profiled operation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpLatencies {
+    pub map: LatencyHistogram,
+    pub map_range: LatencyHistogram,
+    pub open_read: LatencyHistogram,
+    pub open_write: LatencyHistogram,
+    pub sync_all: LatencyHistogram,
+}
+
+async fn timed<T, Fut: Future<Output = io::Result<T>>>(
+    latencies: &Mutex<OpLatencies>,
+    histogram: fn(&mut OpLatencies) -> &mut LatencyHistogram,
+    future: Fut,
+) -> io::Result<T> {
+    let start = Instant::now();
+    let result = future.await;
+    histogram(&mut latencies.lock().unwrap()).record(start.elapsed());
+
+    result
+}
+
+/// A store that passes operations through to the inner store, recording the latency of `map`,
+/// `map_range`, `open_read`, `open_write` and `sync_all`.
+///
+/// Failed operations are recorded too.
+#[derive(Clone, Debug)]
+pub struct ProfiledStore<F> {
+    inner: F,
+    latencies: Arc<Mutex<OpLatencies>>,
+}
+
+impl<F> ProfiledStore<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            latencies: Default::default(),
+        }
+    }
+
+    /// Returns a snapshot of the latencies recorded so far.
+    pub fn latencies(&self) -> OpLatencies {
+        self.latencies.lock().unwrap().clone()
+    }
+
+    /// Clear all recorded latencies.
+    pub fn reset_latencies(&self) {
+        *self.latencies.lock().unwrap() = OpLatencies::default();
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for ProfiledStore<F> {
+    type Read = F::Read;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.inner.exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.inner.size().await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        timed(
+            &self.latencies,
+            |l| &mut l.open_read,
+            self.inner.open_read_from(offset),
+        )
+        .await
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        timed(&self.latencies, |l| &mut l.map, self.inner.map()).await
+    }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        timed(
+            &self.latencies,
+            |l| &mut l.map_range,
+            self.inner.map_range(offset, len),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for ProfiledStore<F> {
+    type Write = ProfiledFile<F::Write>;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        let inner = timed(
+            &self.latencies,
+            |l| &mut l.open_write,
+            self.inner.open_write(),
+        )
+        .await?;
+
+        Ok(ProfiledFile {
+            inner,
+            latencies: self.latencies.clone(),
+        })
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<Self::Write> {
+        let inner = timed(
+            &self.latencies,
+            |l| &mut l.open_write,
+            self.inner.open_write_sized(size),
+        )
+        .await?;
+
+        Ok(ProfiledFile {
+            inner,
+            latencies: self.latencies.clone(),
+        })
+    }
+}
+
+/// A writer returned by `ProfiledStore`, which records the latency of `sync_all`.
+pub struct ProfiledFile<W> {
+    inner: W,
+    latencies: Arc<Mutex<OpLatencies>>,
+}
+
+impl<W: SyncableFile> AsyncWrite for ProfiledFile<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl<W: SyncableFile> SyncableFile for ProfiledFile<W> {
+    async fn sync_all(self) -> io::Result<()> {
+        let latencies = self.latencies;
+        timed(&latencies, |l| &mut l.sync_all, self.inner.sync_all()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::dynamic::DynFileLoad;
+    use crate::storage::faulty::FaultyStore;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::overlay::OverlayStore;
+    use crate::storage::readonly::ReadOnlyStore;
+    use crate::storage::retry::RetryStore;
+    use crate::storage::sidecar::SidecarChecksumStore;
+    use crate::storage::timeout::TimeoutStore;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn latencies_are_bucketed_by_powers_of_two() {
+        assert_eq!(0, LatencyHistogram::bucket_index(Duration::from_nanos(999)));
+        assert_eq!(1, LatencyHistogram::bucket_index(Duration::from_micros(1)));
+        assert_eq!(2, LatencyHistogram::bucket_index(Duration::from_micros(3)));
+        assert_eq!(10, LatencyHistogram::bucket_index(Duration::from_millis(1)));
+        assert_eq!(
+            LATENCY_BUCKETS - 1,
+            LatencyHistogram::bucket_index(Duration::from_secs(1 << 20))
+        );
+    }
+
+    #[tokio::test]
+    async fn operations_populate_their_histograms() {
+        let store = ProfiledStore::new(MemoryBackedStore::new());
+        assert_eq!(OpLatencies::default(), store.latencies());
+
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 9);
+        builder.push_vec((0..300).collect()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let latencies = store.latencies();
+        assert_eq!(1, latencies.open_write.count());
+        assert_eq!(1, latencies.sync_all.count());
+        assert_eq!(0, latencies.map.count());
+
+        let mapped = store.map().await.unwrap();
+        store.map().await.unwrap();
+        let mut read = Vec::new();
+        store
+            .open_read()
+            .await
+            .unwrap()
+            .read_to_end(&mut read)
+            .await
+            .unwrap();
+        assert_eq!(mapped, read);
+        assert_eq!(mapped.slice(8..16), store.map_range(8, 8).await.unwrap());
+
+        let latencies = store.latencies();
+        assert_eq!(2, latencies.map.count());
+        assert_eq!(1, latencies.map_range.count());
+        assert_eq!(1, latencies.open_read.count());
+        assert!(latencies.map.buckets().iter().any(|&count| count != 0));
+
+        store.reset_latencies();
+        assert_eq!(OpLatencies::default(), store.latencies());
+    }
+
+    #[tokio::test]
+    async fn wrappers_forward_map_range() {
+        let store = ProfiledStore::new(MemoryBackedStore::new());
+        let mut writer = store.open_write().await.unwrap();
+        writer.write_all(b"0123456789").await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        let sidecar =
+            SidecarChecksumStore::new(store.clone(), ProfiledStore::new(MemoryBackedStore::new()));
+        let overlay = OverlayStore::new(sidecar, MemoryBackedStore::new());
+        let faulty = FaultyStore::new(ReadOnlyStore::new(overlay));
+        let timeout = TimeoutStore::new(faulty, Duration::from_secs(10));
+        let retry = RetryStore::new(timeout, 3, Duration::from_millis(1));
+        let wrapped: Box<dyn DynFileLoad> = Box::new(retry);
+
+        assert_eq!(&b"345"[..], wrapped.map_range(3, 3).await.unwrap());
+
+        // the ranged read reaches the inner store, rather than going through a reader
+        let latencies = store.latencies();
+        assert_eq!(1, latencies.map_range.count());
+        assert_eq!(0, latencies.open_read.count());
+    }
+}
@@ -0,0 +1,110 @@
+//! A store wrapper which refuses all writes.
+//!
+//! Read replicas should never modify the data they serve. Wrapping their stores in a
+//! `ReadOnlyStore` turns any attempt to open a file for writing into a `PermissionDenied` error,
+//! rather than letting it go through.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io;
+
+use super::{FileLoad, FileStore};
+
+/// A store that passes reads through to the inner store, but fails every write.
+#[derive(Clone, Debug)]
+pub struct ReadOnlyStore<F> {
+    inner: F,
+}
+
+impl<F> ReadOnlyStore<F> {
+    pub fn new(inner: F) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for ReadOnlyStore<F> {
+    type Read = F::Read;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.inner.exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.inner.size().await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        self.inner.open_read_from(offset).await
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        self.inner.map().await
+    }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        self.inner.map_range(offset, len).await
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for ReadOnlyStore<F> {
+    type Write = F::Write;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "attempted to write to a read-only store",
+        ))
+    }
+
+    async fn open_write_sized(&self, _size: usize) -> io::Result<Self::Write> {
+        self.open_write().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn reads_work_but_writes_fail() {
+        let inner = MemoryBackedStore::new();
+        let mut builder = LogArrayFileBuilder::new(inner.open_write().await.unwrap(), 6);
+        builder.push_vec(vec![1, 2, 3, 40]).await.unwrap();
+        builder.finalize().await.unwrap();
+        let store = ReadOnlyStore::new(inner.clone());
+
+        let logarray = LogArray::parse(store.map().await.unwrap()).unwrap();
+        assert_eq!(vec![1, 2, 3, 40], logarray.iter().collect::<Vec<_>>());
+        let mut contents = Vec::new();
+        store
+            .open_read()
+            .await
+            .unwrap()
+            .read_to_end(&mut contents)
+            .await
+            .unwrap();
+        assert_eq!(inner.map().await.unwrap(), contents);
+
+        let err = store.open_write().await.err().unwrap();
+        assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+        let err = store.open_write_sized(100).await.err().unwrap();
+        assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+
+        // the inner store is left untouched
+        assert_eq!(inner.map().await.unwrap(), store.map().await.unwrap());
+    }
+}
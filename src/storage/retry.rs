@@ -0,0 +1,242 @@
+//! A store wrapper which retries operations that fail with a transient error.
+//!
+//! Remote backends can fail a request with an error that goes away when the request is simply
+//! repeated. Wrapping such a store in a `RetryStore` retries these operations with exponential
+//! backoff, while errors like `NotFound` or `InvalidData` are returned immediately.
+//!
+//! Only opening, inspecting and mapping files is retried. Data written to a writer can't be
+//! replayed, so writes and `sync_all` go straight to the inner store.
+
+use std::future::Future;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io;
+
+use super::{FileLoad, FileStore};
+
+/// The error kinds that are retried by default.
+pub const DEFAULT_RETRYABLE_KINDS: [io::ErrorKind; 6] = [
+    io::ErrorKind::Interrupted,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::ConnectionRefused,
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::BrokenPipe,
+];
+
+/// A store that retries failed operations on the inner store if the error is of a retryable kind.
+#[derive(Clone, Debug)]
+pub struct RetryStore<F> {
+    inner: F,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    retryable_kinds: Vec<io::ErrorKind>,
+}
+
+impl<F> RetryStore<F> {
+    /// Wrap `inner`, trying every operation at most `max_attempts` times.
+    ///
+    /// The first retry waits for `initial_backoff`, and every subsequent retry waits twice as long
+    /// as the one before.
+    pub fn new(inner: F, max_attempts: u32, initial_backoff: Duration) -> Self {
+        assert!(max_attempts != 0, "expected at least one attempt");
+        Self {
+            inner,
+            max_attempts,
+            initial_backoff,
+            retryable_kinds: DEFAULT_RETRYABLE_KINDS.to_vec(),
+        }
+    }
+
+    /// Replace the error kinds that are retried.
+    pub fn retry_on(mut self, kinds: Vec<io::ErrorKind>) -> Self {
+        self.retryable_kinds = kinds;
+        self
+    }
+
+    /// Returns the maximum number of times an operation is tried.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn is_retryable(&self, kind: io::ErrorKind) -> bool {
+        self.retryable_kinds.contains(&kind)
+    }
+
+    async fn retry<T, Fut: Future<Output = io::Result<T>>, Op: Fn() -> Fut>(
+        &self,
+        op: Op,
+    ) -> io::Result<T> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(e) if attempt < self.max_attempts && self.is_retryable(e.kind()) => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for RetryStore<F> {
+    type Read = F::Read;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.retry(|| self.inner.exists()).await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.retry(|| self.inner.size()).await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        self.retry(|| self.inner.open_read_from(offset)).await
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        self.retry(|| self.inner.map()).await
+    }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        self.retry(|| self.inner.map_range(offset, len)).await
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for RetryStore<F> {
+    type Write = F::Write;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        self.retry(|| self.inner.open_write()).await
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<Self::Write> {
+        self.retry(|| self.inner.open_write_sized(size)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::SyncableFile;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+
+    /// A store whose `map` fails with the given error kind a number of times before succeeding.
+    #[derive(Clone)]
+    struct FlakyStore {
+        inner: MemoryBackedStore,
+        failures_left: Arc<AtomicUsize>,
+        attempts: Arc<AtomicUsize>,
+        kind: io::ErrorKind,
+    }
+
+    impl FlakyStore {
+        async fn new(failures: usize, kind: io::ErrorKind) -> Self {
+            let inner = MemoryBackedStore::new();
+            let mut writer = inner.open_write().await.unwrap();
+            writer.write_all(&[1, 2, 3]).await.unwrap();
+            writer.sync_all().await.unwrap();
+
+            Self {
+                inner,
+                failures_left: Arc::new(AtomicUsize::new(failures)),
+                attempts: Arc::new(AtomicUsize::new(0)),
+                kind,
+            }
+        }
+
+        fn attempts(&self) -> usize {
+            self.attempts.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl FileLoad for FlakyStore {
+        type Read = <MemoryBackedStore as FileLoad>::Read;
+
+        async fn exists(&self) -> io::Result<bool> {
+            self.inner.exists().await
+        }
+
+        async fn size(&self) -> io::Result<usize> {
+            self.inner.size().await
+        }
+
+        async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+            self.inner.open_read_from(offset).await
+        }
+
+        async fn map(&self) -> io::Result<Bytes> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let failed = self
+                .failures_left
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if failed {
+                Err(io::Error::new(self.kind, "flaky failure"))
+            } else {
+                self.inner.map().await
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let flaky = FlakyStore::new(2, io::ErrorKind::ConnectionReset).await;
+        let store = RetryStore::new(flaky.clone(), 3, Duration::from_millis(1));
+
+        assert_eq!(&[1, 2, 3][..], &store.map().await.unwrap()[..]);
+        assert_eq!(3, flaky.attempts());
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let flaky = FlakyStore::new(5, io::ErrorKind::TimedOut).await;
+        let store = RetryStore::new(flaky.clone(), 3, Duration::from_millis(1));
+
+        let err = store.map().await.unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        assert_eq!(3, flaky.attempts());
+    }
+
+    #[tokio::test]
+    async fn logical_errors_are_not_retried() {
+        for kind in [io::ErrorKind::NotFound, io::ErrorKind::InvalidData] {
+            let flaky = FlakyStore::new(1, kind).await;
+            let store = RetryStore::new(flaky.clone(), 5, Duration::from_millis(1));
+
+            let err = store.map().await.unwrap_err();
+            assert_eq!(kind, err.kind());
+            assert_eq!(1, flaky.attempts());
+        }
+    }
+
+    #[tokio::test]
+    async fn retryable_kinds_can_be_configured() {
+        let flaky = FlakyStore::new(1, io::ErrorKind::Other).await;
+        let store = RetryStore::new(flaky.clone(), 2, Duration::from_millis(1))
+            .retry_on(vec![io::ErrorKind::Other]);
+
+        assert_eq!(&[1, 2, 3][..], &store.map().await.unwrap()[..]);
+        assert_eq!(2, flaky.attempts());
+    }
+}
@@ -0,0 +1,214 @@
+//! A store wrapper which keeps a CRC-32 checksum of a file in a separate sidecar file.
+//!
+//! Storing the checksum next to the file rather than in it means the file itself stays in its
+//! usual format, and can still be read by anything that doesn't know about the sidecar.
+//!
+//! The sidecar contains the checksum as 4 big-endian bytes. It is written after the file itself
+//! has been synced, so a crash in between leaves a file that fails verification rather than one
+//! that silently passes it.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::ready;
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
+
+use super::{FileLoad, FileStore, SyncableFile};
+use crate::util::{crc32, Crc32};
+
+/// A store whose contents are verified against a checksum in a sidecar store when mapped.
+#[derive(Clone, Debug)]
+pub struct SidecarChecksumStore<F> {
+    inner: F,
+    sidecar: F,
+}
+
+impl<F> SidecarChecksumStore<F> {
+    pub fn new(inner: F, sidecar: F) -> Self {
+        Self { inner, sidecar }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Returns a reference to the store holding the checksum.
+    pub fn sidecar(&self) -> &F {
+        &self.sidecar
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for SidecarChecksumStore<F> {
+    type Read = F::Read;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.inner.exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.inner.size().await
+    }
+
+    /// Open the file for reading. Unlike `map`, this does not verify the checksum.
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        self.inner.open_read_from(offset).await
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        let contents = self.inner.map().await?;
+        let sidecar = self.sidecar.map().await?;
+        if sidecar.len() != 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected a 4 byte checksum sidecar, got {} bytes",
+                    sidecar.len()
+                ),
+            ));
+        }
+
+        let expected = u32::from_be_bytes([sidecar[0], sidecar[1], sidecar[2], sidecar[3]]);
+        let actual = crc32(&contents);
+        if actual != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "checksum mismatch: sidecar has {:#010x}, contents have {:#010x}",
+                    expected, actual
+                ),
+            ));
+        }
+
+        Ok(contents)
+    }
+
+    /// Read a range of the file. Unlike `map`, this does not verify the checksum.
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        self.inner.map_range(offset, len).await
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for SidecarChecksumStore<F> {
+    type Write = SidecarChecksumFile<F>;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        Ok(SidecarChecksumFile {
+            inner: self.inner.open_write().await?,
+            sidecar: self.sidecar.clone(),
+            crc: Crc32::new(),
+        })
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<Self::Write> {
+        Ok(SidecarChecksumFile {
+            inner: self.inner.open_write_sized(size).await?,
+            sidecar: self.sidecar.clone(),
+            crc: Crc32::new(),
+        })
+    }
+}
+
+/// A writer returned by `SidecarChecksumStore`, which writes the checksum of everything written
+/// to it to the sidecar on `sync_all`.
+pub struct SidecarChecksumFile<F: FileStore> {
+    inner: F::Write,
+    sidecar: F,
+    crc: Crc32,
+}
+
+// None of the fields are ever pinned, so moving the writer around is fine whatever the store is.
+impl<F: FileStore> Unpin for SidecarChecksumFile<F> {}
+
+impl<F: FileStore> AsyncWrite for SidecarChecksumFile<F> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.crc.update(&buf[..written]);
+
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> SyncableFile for SidecarChecksumFile<F> {
+    async fn sync_all(self) -> io::Result<()> {
+        self.inner.sync_all().await?;
+
+        let mut sidecar = self.sidecar.open_write().await?;
+        sidecar
+            .write_all(&self.crc.finalize().to_be_bytes())
+            .await?;
+        sidecar.flush().await?;
+        sidecar.sync_all().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+    use crate::storage::memory::MemoryBackedStore;
+
+    async fn build_logarray<F: FileStore>(store: &F) -> io::Result<()> {
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await?, 12);
+        builder.push_vec((0..500).map(|i| i * 8).collect()).await?;
+        builder.finalize().await
+    }
+
+    #[tokio::test]
+    async fn correct_sidecar_passes_verification() {
+        let store = SidecarChecksumStore::new(MemoryBackedStore::new(), MemoryBackedStore::new());
+        build_logarray(&store).await.unwrap();
+
+        let contents = store.inner().map().await.unwrap();
+        assert_eq!(
+            crc32(&contents).to_be_bytes(),
+            &store.sidecar().map().await.unwrap()[..]
+        );
+
+        let logarray = LogArray::parse(store.map().await.unwrap()).unwrap();
+        assert_eq!(500, logarray.len());
+        assert_eq!(8 * 499, logarray.entry(499));
+    }
+
+    #[tokio::test]
+    async fn tampered_sidecar_fails_map() {
+        let store = SidecarChecksumStore::new(MemoryBackedStore::new(), MemoryBackedStore::new());
+        build_logarray(&store).await.unwrap();
+
+        let mut sidecar = store.sidecar().map().await.unwrap().to_vec();
+        sidecar[3] ^= 1;
+        let mut writer = store.sidecar().open_write().await.unwrap();
+        writer.write_all(&sidecar).await.unwrap();
+        writer.sync_all().await.unwrap();
+
+        let err = store.map().await.unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[tokio::test]
+    async fn missing_sidecar_fails_map() {
+        let sidecar = MemoryBackedStore::new();
+        let store = SidecarChecksumStore::new(MemoryBackedStore::new(), sidecar);
+        build_logarray(store.inner()).await.unwrap();
+
+        assert!(store.map().await.is_err());
+    }
+}
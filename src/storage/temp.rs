@@ -0,0 +1,161 @@
+//! A file backed store at a generated temporary path, which is removed again once the store is
+//! dropped.
+//!
+//! This is meant for tests and scratch data that should go through the real file code paths.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::fs::File;
+use tokio::io::{self, BufWriter};
+
+use super::file::FileBackedStore;
+use super::{FileLoad, FileStore};
+
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn unique_temp_path() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let count = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    std::env::temp_dir().join(format!(
+        "tdb-succinct-{}-{}-{}",
+        std::process::id(),
+        nanos,
+        count
+    ))
+}
+
+/// Removes the file at `path` when dropped.
+#[derive(Debug)]
+struct TempPath {
+    path: PathBuf,
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // the file may never have been written, in which case there is nothing to remove
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A `FileBackedStore` at a unique temporary path.
+///
+/// The file is created on the first `open_write`, and removed when the last clone of the store is
+/// dropped.
+#[derive(Clone, Debug)]
+pub struct TempFileStore {
+    store: FileBackedStore,
+    path: Arc<TempPath>,
+}
+
+impl TempFileStore {
+    pub fn new() -> TempFileStore {
+        let path = unique_temp_path();
+
+        TempFileStore {
+            store: FileBackedStore::new(path.clone()),
+            path: Arc::new(TempPath { path }),
+        }
+    }
+
+    /// Returns the path of the temporary file.
+    pub fn path(&self) -> &Path {
+        &self.path.path
+    }
+}
+
+impl Default for TempFileStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl FileLoad for TempFileStore {
+    type Read = File;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.store.exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.store.size().await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<File> {
+        self.store.open_read_from(offset).await
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        self.store.map().await
+    }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        self.store.map_range(offset, len).await
+    }
+}
+
+#[async_trait]
+impl FileStore for TempFileStore {
+    type Write = BufWriter<File>;
+
+    async fn open_write(&self) -> io::Result<BufWriter<File>> {
+        self.store.open_write().await
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<BufWriter<File>> {
+        self.store.open_write_sized(size).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logarray::*;
+
+    #[tokio::test]
+    async fn build_and_parse_logarray_through_temp_file() {
+        let store = TempFileStore::new();
+        assert!(!store.exists().await.unwrap());
+
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 9);
+        builder.push_vec((0..400).collect()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        assert!(store.exists().await.unwrap());
+        let logarray = LogArray::parse(store.map().await.unwrap()).unwrap();
+        assert_eq!(
+            (0..400).collect::<Vec<u64>>(),
+            logarray.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn temp_file_is_removed_when_last_clone_drops() {
+        let store = TempFileStore::new();
+        let path = store.path().to_path_buf();
+        let mut builder = LogArrayFileBuilder::new(store.open_write().await.unwrap(), 4);
+        builder.push_vec(vec![1, 2, 3]).await.unwrap();
+        builder.finalize().await.unwrap();
+        assert!(path.exists());
+
+        let clone = store.clone();
+        drop(store);
+        assert!(path.exists());
+        drop(clone);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn temp_paths_are_unique() {
+        assert_ne!(TempFileStore::new().path(), TempFileStore::new().path());
+    }
+}
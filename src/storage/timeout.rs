@@ -0,0 +1,272 @@
+//! A store wrapper which puts a time limit on every storage operation.
+//!
+//! Backends like object storage can occasionally hang on a request. Wrapping such a store in a
+//! `TimeoutStore` turns a hung operation into an `io::Error` of kind `TimedOut`.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io::{self, AsyncWrite};
+
+use super::{FileLoad, FileStore, SyncableFile};
+
+async fn with_timeout<T, Fut: Future<Output = io::Result<T>>>(
+    timeout: Duration,
+    future: Fut,
+) -> io::Result<T> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("storage operation timed out after {:?}", timeout),
+        )),
+    }
+}
+
+/// A store that fails any operation on the inner store that takes longer than the given timeout.
+#[derive(Clone, Debug)]
+pub struct TimeoutStore<F> {
+    inner: F,
+    timeout: Duration,
+}
+
+impl<F> TimeoutStore<F> {
+    pub fn new(inner: F, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    /// Returns the timeout applied to each operation.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for TimeoutStore<F> {
+    type Read = F::Read;
+
+    async fn exists(&self) -> io::Result<bool> {
+        with_timeout(self.timeout, self.inner.exists()).await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        with_timeout(self.timeout, self.inner.size()).await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        with_timeout(self.timeout, self.inner.open_read_from(offset)).await
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        with_timeout(self.timeout, self.inner.map()).await
+    }
+
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        with_timeout(self.timeout, self.inner.map_range(offset, len)).await
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for TimeoutStore<F> {
+    type Write = TimeoutFile<F::Write>;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        let inner = with_timeout(self.timeout, self.inner.open_write()).await?;
+
+        Ok(TimeoutFile {
+            inner,
+            timeout: self.timeout,
+        })
+    }
+
+    async fn open_write_sized(&self, size: usize) -> io::Result<Self::Write> {
+        let inner = with_timeout(self.timeout, self.inner.open_write_sized(size)).await?;
+
+        Ok(TimeoutFile {
+            inner,
+            timeout: self.timeout,
+        })
+    }
+}
+
+/// A writer returned by `TimeoutStore`, which applies the store's timeout to `sync_all`.
+pub struct TimeoutFile<W> {
+    inner: W,
+    timeout: Duration,
+}
+
+impl<W: SyncableFile> AsyncWrite for TimeoutFile<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl<W: SyncableFile> SyncableFile for TimeoutFile<W> {
+    async fn sync_all(self) -> io::Result<()> {
+        with_timeout(self.timeout, self.inner.sync_all()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::{MemoryBackedStore, MemoryBackedStoreWriter};
+    use tokio::io::AsyncWriteExt;
+
+    /// A store which sleeps before every operation.
+    #[derive(Clone)]
+    struct SlowStore {
+        inner: MemoryBackedStore,
+        delay: Duration,
+    }
+
+    struct SlowWriter {
+        inner: MemoryBackedStoreWriter,
+        delay: Duration,
+    }
+
+    impl AsyncWrite for SlowWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context,
+            buf: &[u8],
+        ) -> Poll<Result<usize, io::Error>> {
+            Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+            Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+            Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+        }
+    }
+
+    #[async_trait]
+    impl SyncableFile for SlowWriter {
+        async fn sync_all(self) -> io::Result<()> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.sync_all().await
+        }
+    }
+
+    #[async_trait]
+    impl FileLoad for SlowStore {
+        type Read = <MemoryBackedStore as FileLoad>::Read;
+
+        async fn exists(&self) -> io::Result<bool> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.exists().await
+        }
+
+        async fn size(&self) -> io::Result<usize> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.size().await
+        }
+
+        async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.open_read_from(offset).await
+        }
+
+        async fn map(&self) -> io::Result<Bytes> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.map().await
+        }
+    }
+
+    #[async_trait]
+    impl FileStore for SlowStore {
+        type Write = SlowWriter;
+
+        async fn open_write(&self) -> io::Result<SlowWriter> {
+            Ok(SlowWriter {
+                inner: self.inner.open_write().await?,
+                delay: self.delay,
+            })
+        }
+    }
+
+    async fn write_contents<F: FileStore>(store: &F, contents: &[u8]) -> io::Result<()> {
+        let mut writer = store.open_write().await?;
+        writer.write_all(contents).await?;
+        writer.flush().await?;
+        writer.sync_all().await
+    }
+
+    #[tokio::test]
+    async fn fast_operations_pass_through() {
+        let inner = MemoryBackedStore::new();
+        write_contents(&inner, &[1, 2, 3]).await.unwrap();
+        let store = TimeoutStore::new(
+            SlowStore {
+                inner,
+                delay: Duration::from_millis(1),
+            },
+            Duration::from_secs(10),
+        );
+
+        assert!(store.exists().await.unwrap());
+        assert_eq!(3, store.size().await.unwrap());
+        assert_eq!(&[1, 2, 3][..], &store.map().await.unwrap()[..]);
+    }
+
+    #[tokio::test]
+    async fn slow_map_times_out() {
+        let inner = MemoryBackedStore::new();
+        write_contents(&inner, &[1, 2, 3]).await.unwrap();
+        let store = TimeoutStore::new(
+            SlowStore {
+                inner,
+                delay: Duration::from_secs(10),
+            },
+            Duration::from_millis(10),
+        );
+
+        let err = store.map().await.unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        let err = store.open_read().await.err().unwrap();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+    }
+
+    #[tokio::test]
+    async fn slow_sync_times_out() {
+        let store = TimeoutStore::new(
+            SlowStore {
+                inner: MemoryBackedStore::new(),
+                delay: Duration::from_secs(10),
+            },
+            Duration::from_millis(10),
+        );
+
+        let err = write_contents(&store, &[1, 2, 3]).await.unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+    }
+}
@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use bytes::{Buf, Bytes};
-use tokio::io::{self, AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use crate::{AdjacencyList, BitIndex};
 
@@ -13,6 +13,13 @@ pub trait SyncableFile: AsyncWrite + Unpin + Send {
 pub trait FileStore: Clone + Send + Sync {
     type Write: SyncableFile;
     async fn open_write(&self) -> io::Result<Self::Write>;
+
+    /// Open the file for writing, hinting that `size` bytes are going to be written.
+    ///
+    /// Backends may use this to reserve space up front. By default the hint is ignored.
+    async fn open_write_sized(&self, _size: usize) -> io::Result<Self::Write> {
+        self.open_write().await
+    }
 }
 
 #[async_trait]
@@ -27,6 +34,20 @@ pub trait FileLoad: Clone + Send + Sync {
     async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read>;
     async fn map(&self) -> io::Result<Bytes>;
 
+    /// Read exactly `len` bytes starting at `offset`, without mapping the whole file.
+    ///
+    /// Fails with `UnexpectedEof` if the file ends before `offset + len`. By default, this reads
+    /// from a reader opened at `offset`, which for a file on disk is a single seek and read.
+    async fn map_range(&self, offset: usize, len: usize) -> io::Result<Bytes> {
+        let mut buf = vec![0; len];
+        self.open_read_from(offset)
+            .await?
+            .read_exact(&mut buf)
+            .await?;
+
+        Ok(buf.into())
+    }
+
     async fn map_if_exists(&self) -> io::Result<Option<Bytes>> {
         match self.exists().await? {
             false => Ok(None),
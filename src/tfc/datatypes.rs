@@ -516,6 +516,7 @@ impl FromLexical<NaiveDateTime> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct DateTimeStamp(pub NaiveDateTime);
 
 impl TdbDataType for DateTimeStamp {
@@ -573,6 +574,7 @@ impl FromLexical<NaiveTime> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct Date {
     pub year: i64,
     pub month: u8,
@@ -622,6 +624,7 @@ impl FromLexical<Date> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct GYear {
     pub year: i64,
     pub offset: i16,
@@ -672,6 +675,7 @@ impl FromLexical<GYear> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct GMonth {
     pub month: u8,
     pub offset: i16,
@@ -708,6 +712,7 @@ impl FromLexical<GMonth> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct GDay {
     pub day: u8,
     pub offset: i16,
@@ -744,6 +749,7 @@ impl FromLexical<GDay> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct GYearMonth {
     pub year: i64,
     pub month: u8,
@@ -788,6 +794,7 @@ impl FromLexical<GYearMonth> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct GMonthDay {
     pub month: u8,
     pub day: u8,
@@ -828,6 +835,7 @@ impl FromLexical<GMonthDay> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct Duration {
     pub sign: i8,
     pub year: i64,
@@ -925,6 +933,7 @@ impl FromLexical<Duration> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct YearMonthDuration(pub Duration);
 
 impl TdbDataType for YearMonthDuration {
@@ -952,6 +961,7 @@ impl FromLexical<YearMonthDuration> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct DayTimeDuration(pub Duration);
 
 impl TdbDataType for DayTimeDuration {
@@ -979,6 +989,7 @@ impl FromLexical<DayTimeDuration> for String {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct Base64Binary(pub Vec<u8>);
 
 impl ToLexical<Base64Binary> for Base64Binary {
@@ -1010,6 +1021,7 @@ impl TdbDataType for Base64Binary {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct HexBinary(pub Vec<u8>);
 
 impl ToLexical<HexBinary> for HexBinary {
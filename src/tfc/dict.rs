@@ -2,9 +2,22 @@ use std::{borrow::Cow, cmp::Ordering};
 
 use crate::{util::calculate_width, LateLogArrayBufBuilder, LogArrayBufBuilder, MonotonicLogArray};
 use bytes::{BufMut, Bytes};
+use thiserror::Error;
 
 use super::block::*;
 
+#[derive(Debug, Error, PartialEq)]
+pub enum SizedDictBuilderError {
+    #[error("tried to add an entry equal to the previous entry")]
+    Duplicate,
+    #[error("tried to add an entry that sorts before the previous entry")]
+    OutOfOrder,
+}
+
+/// Builder for a `SizedDict`.
+///
+/// Entries have to be added in strictly increasing order. `add` does not check this, and will
+/// produce a corrupt dictionary if it is violated. Use `try_add` to have it checked.
 pub struct SizedDictBufBuilder<B1: BufMut, B2: BufMut> {
     pub(crate) record_size: Option<u8>,
     block_offset: u64,
@@ -12,6 +25,7 @@ pub struct SizedDictBufBuilder<B1: BufMut, B2: BufMut> {
     offsets: LateLogArrayBufBuilder<B1>,
     data_buf: B2,
     current_block: Vec<Bytes>,
+    last: Option<Bytes>,
 }
 
 impl<B1: BufMut, B2: BufMut> SizedDictBufBuilder<B1, B2> {
@@ -29,6 +43,7 @@ impl<B1: BufMut, B2: BufMut> SizedDictBufBuilder<B1, B2> {
             offsets,
             data_buf,
             current_block: Vec::with_capacity(8),
+            last: None,
         }
     }
 
@@ -41,6 +56,7 @@ impl<B1: BufMut, B2: BufMut> SizedDictBufBuilder<B1, B2> {
     }
 
     pub fn add(&mut self, value: Bytes) -> u64 {
+        self.last = Some(value.clone());
         self.current_block.push(value);
         self.id_offset += 1;
         if self.current_block.len() == BLOCK_SIZE {
@@ -55,6 +71,21 @@ impl<B1: BufMut, B2: BufMut> SizedDictBufBuilder<B1, B2> {
         self.id_offset
     }
 
+    /// Add an entry, checking that it sorts strictly after the previously added entry.
+    ///
+    /// Duplicates are rejected rather than deduplicated. On error, nothing is added.
+    pub fn try_add(&mut self, value: Bytes) -> Result<u64, SizedDictBuilderError> {
+        if let Some(last) = self.last.as_ref() {
+            match value.cmp(last) {
+                Ordering::Less => return Err(SizedDictBuilderError::OutOfOrder),
+                Ordering::Equal => return Err(SizedDictBuilderError::Duplicate),
+                Ordering::Greater => {}
+            }
+        }
+
+        Ok(self.add(value))
+    }
+
     pub fn add_entry(&mut self, e: &SizedDictEntry) -> u64 {
         self.add(e.to_bytes())
     }
@@ -218,6 +249,56 @@ impl SizedDict {
         result
     }
 
+    /// Returns the inclusive range of ids whose entries start with `prefix`, or `None` if no
+    /// entry does.
+    ///
+    /// As entries are sorted, the matching ids are contiguous. Both ends of the range are found
+    /// with a binary search over the block heads followed by a scan of a single block.
+    pub fn prefix_range(&self, prefix: &[u8]) -> Option<(u64, u64)> {
+        let start = self.partition_point(|entry| entry < prefix);
+        let end = self.partition_point(|entry| &entry[..entry.len().min(prefix.len())] <= prefix);
+
+        if start == end {
+            None
+        } else {
+            Some((start as u64 + 1, end as u64))
+        }
+    }
+
+    /// Returns the number of entries for which `pred` holds, where `pred` has to hold for all
+    /// entries before the first one for which it doesn't.
+    fn partition_point<P: Fn(&[u8]) -> bool>(&self, pred: P) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+
+        // find the number of blocks whose head satisfies the predicate
+        let mut min = 0;
+        let mut max = self.num_blocks();
+        while min < max {
+            let mid = (min + max) / 2;
+            if pred(&self.block_head(mid)) {
+                min = mid + 1;
+            } else {
+                max = mid;
+            }
+        }
+
+        if min == 0 {
+            return 0;
+        }
+
+        // only the last of those blocks can contain the boundary
+        let block_index = min - 1;
+        let in_block = self
+            .block(block_index)
+            .into_iter()
+            .take_while(|entry| pred(&entry.to_bytes()))
+            .count();
+
+        block_index * BLOCK_SIZE + in_block
+    }
+
     pub fn block_iter<'a>(&'a self) -> SizedDictBlockIterator<'a> {
         SizedDictBlockIterator {
             dict: Cow::Borrowed(self),
@@ -425,6 +506,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn try_add_rejects_unsorted_and_duplicate_entries() {
+        let mut array_buf = BytesMut::new();
+        let data_buf = BytesMut::new();
+        let offsets = LateLogArrayBufBuilder::new(&mut array_buf);
+        let mut builder = SizedDictBufBuilder::new(None, 0, 0, offsets, data_buf);
+
+        let strings: Vec<&'static [u8]> = vec![
+            b"aaaaaaaa",
+            b"bbbbbbbb",
+            b"bbbcccd",
+            b"f",
+            b"fafas",
+            b"gafovp",
+            b"gdfasfa",
+            b"gdfbbbbbb",
+            b"hello",
+        ];
+        for (ix, s) in strings.iter().enumerate() {
+            assert_eq!(Ok(ix as u64 + 1), builder.try_add(Bytes::from_static(s)));
+        }
+
+        // the previous entry crossed a block boundary, so these are checked against a
+        // value that is no longer in the current block.
+        assert_eq!(
+            Err(SizedDictBuilderError::Duplicate),
+            builder.try_add(Bytes::from_static(b"hello"))
+        );
+        assert_eq!(
+            Err(SizedDictBuilderError::OutOfOrder),
+            builder.try_add(Bytes::from_static(b"abc"))
+        );
+        assert_eq!(Ok(10), builder.try_add(Bytes::from_static(b"iguana")));
+
+        let (mut logarray_builder, data_buf, _, _) = builder.finalize();
+        logarray_builder.pop();
+        logarray_builder.finalize();
+        let dict = SizedDict::parse(array_buf.freeze(), data_buf.freeze(), 0);
+
+        assert_eq!(10, dict.num_entries());
+        assert_eq!(b"iguana", &dict.entry(10).unwrap().to_bytes()[..]);
+    }
+
     #[test]
     fn lookup_entries_by_slice() {
         let strings: Vec<&[u8]> = vec![
@@ -498,4 +622,57 @@ mod tests {
         assert_eq!(IdLookupResult::Closest(9), dict.id(b"hello!"));
         assert_eq!(IdLookupResult::Closest(14), dict.id(b"zebra"));
     }
+
+    #[test]
+    fn prefix_range_matches_filtered_entries() {
+        // every string of length 1 to 3 over "abc", which spans several blocks
+        let mut strings: Vec<Vec<u8>> = vec![Vec::new()];
+        let mut last: Vec<Vec<u8>> = vec![Vec::new()];
+        for _ in 0..3 {
+            last = last
+                .iter()
+                .flat_map(|s| {
+                    b"abc".iter().map(move |c| {
+                        let mut s = s.clone();
+                        s.push(*c);
+                        s
+                    })
+                })
+                .collect();
+            strings.extend(last.iter().cloned());
+        }
+        strings.remove(0);
+        strings.sort();
+
+        let mut array_buf = BytesMut::new();
+        let mut data_buf = BytesMut::new();
+        build_dict_and_offsets(
+            &mut array_buf,
+            &mut data_buf,
+            strings.clone().into_iter().map(Bytes::from),
+        );
+        let dict = SizedDict::parse(array_buf.freeze(), data_buf.freeze(), 0);
+        assert_eq!(5, dict.num_blocks());
+
+        assert_eq!(Some((1, 39)), dict.prefix_range(b""));
+        assert_eq!(Some((14, 26)), dict.prefix_range(b"b"));
+        assert_eq!(Some((6, 9)), dict.prefix_range(b"ab"));
+        assert_eq!(Some((39, 39)), dict.prefix_range(b"ccc"));
+        assert_eq!(None, dict.prefix_range(b"d"));
+        assert_eq!(None, dict.prefix_range(b"aaaa"));
+
+        let mut prefixes: Vec<&[u8]> = vec![b"", b"0", b"abd", b"bz", b"cccc"];
+        prefixes.extend(strings.iter().map(|s| &s[..]));
+        for prefix in prefixes {
+            let ids: Vec<u64> = strings
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.starts_with(prefix))
+                .map(|(ix, _)| ix as u64 + 1)
+                .collect();
+            let expected = ids.first().map(|&first| (first, *ids.last().unwrap()));
+
+            assert_eq!(expected, dict.prefix_range(prefix), "prefix {:?}", prefix);
+        }
+    }
 }
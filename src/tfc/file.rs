@@ -1,16 +1,17 @@
 use byteorder::{BigEndian, ByteOrder};
 use bytes::{Bytes, BytesMut};
-use futures::{Stream, TryStreamExt};
+use futures::{future, stream, Stream, StreamExt, TryStreamExt};
 use std::fmt::Debug;
 use std::io;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use crate::{
+    logarray_stream_entries,
     storage::{DictionaryFiles, FileLoad, FileStore, SyncableFile, TypedDictionaryFiles},
     util::{heap_sorted_stream, sorted_iterator},
 };
 
-use super::*;
+use super::{block::SizedDictBlock, *};
 
 pub async fn dedup_merge_string_dictionaries_stream<
     'a,
@@ -286,3 +287,92 @@ pub async fn dict_file_get_count<F: 'static + FileLoad>(file: F) -> io::Result<u
         .await?;
     Ok(BigEndian::read_u64(&result))
 }
+
+/// Stream the entries of a string dictionary together with their ids.
+///
+/// Rather than mapping the whole dictionary, the block offsets are streamed from the offsets
+/// file and every block is read from the blocks file on its own, so only a single block is kept
+/// in memory at a time.
+pub async fn stream_entries<F: 'static + FileLoad + FileStore>(
+    files: &DictionaryFiles<F>,
+) -> io::Result<impl Stream<Item = io::Result<(u64, Bytes)>> + Unpin + Send> {
+    let blocks_file = files.blocks_file.clone();
+    // the blocks file ends in the number of entries, which is not part of the last block
+    let data_len = blocks_file.size().await?.saturating_sub(8);
+
+    let block_ends = logarray_stream_entries(files.offsets_file.clone())
+        .await?
+        .map_ok(|end| end as usize)
+        .chain(stream::once(future::ready(Ok(data_len))));
+
+    let blocks = block_ends
+        .scan(0, |start, end| {
+            let range = end.map(|end| (std::mem::replace(start, end), end));
+            future::ready(Some(range))
+        })
+        .try_filter(|(start, end)| future::ready(start != end))
+        .and_then(move |(start, end)| {
+            let blocks_file = blocks_file.clone();
+            async move {
+                let mut block_bytes = blocks_file.map_range(start, end - start).await?;
+                SizedDictBlock::parse(&mut block_bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        });
+
+    Ok(blocks
+        .map_ok(|block| stream::iter(block.into_iter().map(Ok)))
+        .try_flatten()
+        .enumerate()
+        .map(|(ix, entry)| entry.map(|entry| (ix as u64 + 1, entry.to_bytes())))
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryBackedStore;
+
+    async fn stream_all(strings: &[String]) -> Vec<(u64, Bytes)> {
+        let mut builder = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        builder.add_all(strings.iter().cloned().map(Bytes::from));
+        let (mut offsets_buf, mut data_buf) = builder.finalize();
+
+        let files = DictionaryFiles {
+            blocks_file: MemoryBackedStore::new(),
+            offsets_file: MemoryBackedStore::new(),
+        };
+        files
+            .write_all_from_bufs(&mut data_buf, &mut offsets_buf)
+            .await
+            .unwrap();
+
+        stream_entries(&files)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn stream_entries_yields_ids_and_values() {
+        let strings: Vec<String> = (0..100).map(|i| format!("string {:03}", i)).collect();
+        let expected: Vec<(u64, Bytes)> = strings
+            .iter()
+            .enumerate()
+            .map(|(ix, s)| (ix as u64 + 1, Bytes::from(s.clone())))
+            .collect();
+
+        assert_eq!(expected, stream_all(&strings).await);
+    }
+
+    #[tokio::test]
+    async fn stream_entries_of_small_dictionaries() {
+        assert!(stream_all(&[]).await.is_empty());
+        assert_eq!(
+            vec![(1, Bytes::from("only"))],
+            stream_all(&["only".to_string()]).await
+        );
+    }
+}
@@ -6,9 +6,11 @@ pub mod dict;
 pub mod file;
 pub mod integer;
 pub mod stream;
+pub mod substring;
 pub mod typed;
 
 pub use block::{OwnedSizedDictEntryBuf, SizedDictEntry, SizedDictEntryBuf};
 pub use datatypes::*;
 pub use file::*;
+pub use substring::*;
 pub use typed::*;
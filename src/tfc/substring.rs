@@ -0,0 +1,307 @@
+//! An optional trigram index over a `StringDict`, for finding the entries that contain a substring.
+//!
+//! Every distinct 3-byte sequence occurring in an entry is a trigram. The sorted trigrams are
+//! stored in a `MonotonicLogArray`, and for every trigram an `AdjacencyList` records the ids of
+//! the entries it occurs in. A query intersects the posting lists of the trigrams of the needle,
+//! and then checks each remaining candidate against the dictionary itself, as sharing all
+//! trigrams with the needle does not guarantee containing it.
+//!
+//! Needles shorter than a trigram can't use the index, and are answered by scanning the
+//! dictionary.
+use bytes::{Bytes, BytesMut};
+use tokio::io::{self, AsyncWriteExt};
+
+use crate::adjacencylist::*;
+use crate::bitindex::*;
+use crate::logarray::*;
+use crate::storage::{
+    AdjacencyListFiles, AdjacencyListMaps, BitIndexMaps, FileLoad, FileStore, SyncableFile,
+};
+use crate::util::calculate_width;
+
+use super::typed::StringDict;
+
+const GRAM_SIZE: usize = 3;
+
+fn gram_code(gram: &[u8]) -> u64 {
+    gram.iter().fold(0, |code, &b| (code << 8) | b as u64)
+}
+
+fn contains_slice(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// A trigram index over the entries of a `StringDict`.
+#[derive(Clone)]
+pub struct SubstringIndex {
+    dict: StringDict,
+    grams: MonotonicLogArray,
+    postings: AdjacencyList,
+}
+
+impl SubstringIndex {
+    pub fn from_parts(
+        dict: StringDict,
+        grams: MonotonicLogArray,
+        postings: AdjacencyList,
+    ) -> SubstringIndex {
+        SubstringIndex {
+            dict,
+            grams,
+            postings,
+        }
+    }
+
+    pub fn from_maps(dict: StringDict, maps: SubstringIndexMaps) -> SubstringIndex {
+        let grams = MonotonicLogArray::parse(maps.grams_map).unwrap();
+        let postings = maps.postings_maps.into();
+
+        Self::from_parts(dict, grams, postings)
+    }
+
+    pub fn from_buffers(dict: StringDict, buffers: SubstringIndexBuffers) -> SubstringIndex {
+        Self::from_maps(dict, buffers.into_maps())
+    }
+
+    /// Build the index for the given dictionary in memory.
+    pub fn build(dict: StringDict) -> SubstringIndex {
+        let buffers = build_substring_index_bufs(&dict);
+        Self::from_buffers(dict, buffers)
+    }
+
+    pub fn dict(&self) -> &StringDict {
+        &self.dict
+    }
+
+    /// Returns the ids of all entries in the dictionary containing `needle`, in increasing order.
+    pub fn contains_substring(&self, needle: &str) -> impl Iterator<Item = u64> + '_ {
+        let needle = needle.as_bytes();
+        let candidates: Vec<u64> = if needle.len() < GRAM_SIZE {
+            (1..=self.dict.num_entries() as u64).collect()
+        } else {
+            self.candidates(needle)
+        };
+
+        let needle = needle.to_vec();
+        candidates.into_iter().filter(move |&id| {
+            let entry = self.dict.get(id as usize).unwrap();
+            contains_slice(entry.as_bytes(), &needle)
+        })
+    }
+
+    /// Returns the ids of the entries that contain every trigram of `needle`.
+    fn candidates(&self, needle: &[u8]) -> Vec<u64> {
+        let mut candidates: Option<Vec<u64>> = None;
+        for gram in needle.windows(GRAM_SIZE) {
+            let postings = match self.grams.index_of(gram_code(gram)) {
+                Some(index) => self.postings.get(index as u64 + 1),
+                None => return Vec::new(),
+            };
+
+            candidates = Some(match candidates {
+                None => postings.iter().collect(),
+                Some(previous) => {
+                    let mut postings = postings.iter().peekable();
+                    previous
+                        .into_iter()
+                        .filter(|&id| {
+                            while postings.next_if(|&p| p < id).is_some() {}
+                            postings.peek() == Some(&id)
+                        })
+                        .collect()
+                }
+            });
+        }
+
+        candidates.unwrap_or_default()
+    }
+}
+
+pub struct SubstringIndexBuffers {
+    grams: Bytes,
+    nums: Bytes,
+    bits: Bytes,
+    bitindex_blocks: Bytes,
+    bitindex_sblocks: Bytes,
+}
+
+impl SubstringIndexBuffers {
+    fn into_maps(self) -> SubstringIndexMaps {
+        SubstringIndexMaps {
+            grams_map: self.grams,
+            postings_maps: AdjacencyListMaps {
+                bitindex_maps: BitIndexMaps {
+                    bits_map: self.bits,
+                    blocks_map: self.bitindex_blocks,
+                    sblocks_map: self.bitindex_sblocks,
+                },
+                nums_map: self.nums,
+            },
+        }
+    }
+}
+
+/// Build the buffers of a `SubstringIndex` over the given dictionary.
+pub fn build_substring_index_bufs(dict: &StringDict) -> SubstringIndexBuffers {
+    let mut pairs: Vec<(u64, u64)> = Vec::new();
+    for (index, entry) in dict.iter().enumerate() {
+        let id = index as u64 + 1;
+        let bytes = entry.to_bytes();
+        pairs.extend(bytes.windows(GRAM_SIZE).map(|gram| (gram_code(gram), id)));
+    }
+    pairs.sort_unstable();
+    pairs.dedup();
+
+    let mut grams_builder = LogArrayBufBuilder::new(BytesMut::new(), (GRAM_SIZE * 8) as u8);
+    let mut postings_builder =
+        UnindexedAdjacencyListBufBuilder::new(calculate_width(dict.num_entries() as u64));
+    let mut last_gram = None;
+    let mut gram_index = 0;
+    for (gram, id) in pairs {
+        if last_gram != Some(gram) {
+            grams_builder.push(gram);
+            gram_index += 1;
+            last_gram = Some(gram);
+        }
+        postings_builder.push(gram_index, id);
+    }
+
+    let grams = grams_builder.finalize().freeze();
+    let (bits, nums) = postings_builder.finalize();
+    let mut bitindex_blocks = BytesMut::new();
+    let mut bitindex_sblocks = BytesMut::new();
+    build_bitindex_from_buf(&bits[..], &mut bitindex_blocks, &mut bitindex_sblocks);
+
+    SubstringIndexBuffers {
+        grams,
+        nums,
+        bits,
+        bitindex_blocks: bitindex_blocks.freeze(),
+        bitindex_sblocks: bitindex_sblocks.freeze(),
+    }
+}
+
+#[derive(Clone)]
+pub struct SubstringIndexMaps {
+    pub grams_map: Bytes,
+    pub postings_maps: AdjacencyListMaps,
+}
+
+#[derive(Clone)]
+pub struct SubstringIndexFiles<F: 'static + FileLoad + FileStore> {
+    pub grams_file: F,
+    pub postings_files: AdjacencyListFiles<F>,
+}
+
+impl<F: 'static + FileLoad + FileStore> SubstringIndexFiles<F> {
+    pub async fn map_all(&self) -> io::Result<SubstringIndexMaps> {
+        let grams_map = self.grams_file.map().await?;
+        let postings_maps = self.postings_files.map_all().await?;
+
+        Ok(SubstringIndexMaps {
+            grams_map,
+            postings_maps,
+        })
+    }
+
+    pub async fn write_all_from_bufs(&self, buffers: SubstringIndexBuffers) -> io::Result<()> {
+        let bitindex_files = &self.postings_files.bitindex_files;
+        let files = [
+            (&self.grams_file, buffers.grams),
+            (&self.postings_files.nums_file, buffers.nums),
+            (&bitindex_files.bits_file, buffers.bits),
+            (&bitindex_files.blocks_file, buffers.bitindex_blocks),
+            (&bitindex_files.sblocks_file, buffers.bitindex_sblocks),
+        ];
+        for (file, mut buf) in files {
+            let mut writer = file.open_write().await?;
+            writer.write_all_buf(&mut buf).await?;
+            writer.flush().await?;
+            writer.sync_all().await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::BitIndexFiles;
+    use crate::tfc::typed::StringDictBufBuilder;
+
+    fn build_dict(entries: &[&str]) -> StringDict {
+        let mut builder = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        builder.add_all(entries.iter().map(|e| Bytes::copy_from_slice(e.as_bytes())));
+        let (offsets, data) = builder.finalize();
+
+        StringDict::parse(offsets.freeze(), data.freeze())
+    }
+
+    fn expected_ids(entries: &[&str], needle: &str) -> Vec<u64> {
+        entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.contains(needle))
+            .map(|(i, _)| i as u64 + 1)
+            .collect()
+    }
+
+    const ENTRIES: [&str; 12] = [
+        "abracadabra",
+        "bar",
+        "barbara",
+        "cabaret",
+        "candelabra",
+        "debark",
+        "embargo",
+        "rabbit",
+        "sabbatical",
+        "scarab",
+        "tab",
+        "zebra",
+    ];
+
+    #[test]
+    fn contains_substring_finds_all_and_only_matching_entries() {
+        let index = SubstringIndex::build(build_dict(&ENTRIES));
+
+        for needle in [
+            "bra", "abra", "bar", "arb", "ab", "a", "", "abb", "cadab", "xyz", "rab", "raba",
+        ] {
+            assert_eq!(
+                expected_ids(&ENTRIES, needle),
+                index.contains_substring(needle).collect::<Vec<_>>(),
+                "needle {:?}",
+                needle
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn substring_index_can_be_persisted() {
+        let dict = build_dict(&ENTRIES);
+        let files = SubstringIndexFiles {
+            grams_file: MemoryBackedStore::new(),
+            postings_files: AdjacencyListFiles {
+                bitindex_files: BitIndexFiles {
+                    bits_file: MemoryBackedStore::new(),
+                    blocks_file: MemoryBackedStore::new(),
+                    sblocks_file: MemoryBackedStore::new(),
+                },
+                nums_file: MemoryBackedStore::new(),
+            },
+        };
+        files
+            .write_all_from_bufs(build_substring_index_bufs(&dict))
+            .await
+            .unwrap();
+
+        let index = SubstringIndex::from_maps(dict, files.map_all().await.unwrap());
+        assert_eq!(
+            expected_ids(&ENTRIES, "abra"),
+            index.contains_substring("abra").collect::<Vec<_>>()
+        );
+    }
+}
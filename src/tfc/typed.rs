@@ -3,13 +3,20 @@ use crate::{
     LateLogArrayBufBuilder, MonotonicLogArray,
 };
 use bytes::{BufMut, Bytes};
+use chrono::{NaiveDateTime, NaiveTime};
 use num_traits::FromPrimitive;
+use rug::Integer;
 use std::{borrow::Cow, marker::PhantomData};
 
 use super::{
     block::{IdLookupResult, SizedDictBlock, SizedDictEntry},
-    dict::{SizedDict, SizedDictBufBuilder},
-    Datatype, FromLexical, OwnedSizedDictEntryBuf, SizedDictEntryBuf, TdbDataType, ToLexical,
+    decimal::Decimal,
+    dict::{SizedDict, SizedDictBufBuilder, SizedDictBuilderError},
+    AnySimpleType, AnyURI, Base64Binary, Datatype, Date, DateTimeStamp, DayTimeDuration, Duration,
+    Entity, FromLexical, GDay, GMonth, GMonthDay, GYear, GYearMonth, HexBinary, IDRef, LangString,
+    Language, NCName, NMToken, Name, NegativeInteger, NonNegativeInteger, NonPositiveInteger,
+    NormalizedString, Notation, OwnedSizedDictEntryBuf, PositiveInteger, QName, SizedDictEntryBuf,
+    TdbDataType, ToLexical, Token, YearMonthDuration, ID,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -56,6 +63,32 @@ impl TypedDictEntry {
         }
     }
 
+    /// Returns the value as a `u64` if it is of an unsigned integer type.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.datatype {
+            Datatype::UInt64 => Some(self.as_val::<u64, u64>()),
+            Datatype::UInt32 => Some(self.as_casted_val::<u64, u32>()),
+            Datatype::UInt16 => Some(self.as_casted_val::<u64, u16>()),
+            Datatype::UInt8 => Some(self.as_casted_val::<u64, u8>()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an `i64` if it is of a signed integer type, or of an unsigned integer
+    /// type narrower than 64 bits.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.datatype {
+            Datatype::Int64 => Some(self.as_val::<i64, i64>()),
+            Datatype::Int32 => Some(self.as_casted_val::<i64, i32>()),
+            Datatype::Int16 => Some(self.as_casted_val::<i64, i16>()),
+            Datatype::Int8 => Some(self.as_casted_val::<i64, i8>()),
+            Datatype::UInt32 => Some(self.as_casted_val::<i64, u32>()),
+            Datatype::UInt16 => Some(self.as_casted_val::<i64, u16>()),
+            Datatype::UInt8 => Some(self.as_casted_val::<i64, u8>()),
+            _ => None,
+        }
+    }
+
     #[doc(hidden)]
     pub fn as_f64(&self) -> Option<f64> {
         match self.datatype {
@@ -70,6 +103,83 @@ impl TypedDictEntry {
     }
 }
 
+macro_rules! typed_values {
+    ($($datatype:ident($ty:ty)),* $(,)?) => {
+        /// A value decoded from a typed dictionary, as the Rust type belonging to its datatype.
+        #[derive(PartialEq, Debug)]
+        pub enum TypedValue {
+            $($datatype($ty),)*
+        }
+
+        impl TypedValue {
+            /// Returns the datatype this value was stored as.
+            pub fn datatype(&self) -> Datatype {
+                match self {
+                    $(TypedValue::$datatype(_) => Datatype::$datatype,)*
+                }
+            }
+        }
+
+        impl TypedDictEntry {
+            /// Decode this entry into the Rust type belonging to its datatype.
+            pub fn to_value(&self) -> TypedValue {
+                match self.datatype {
+                    $(Datatype::$datatype => TypedValue::$datatype(self.as_val::<$ty, $ty>()),)*
+                }
+            }
+        }
+    };
+}
+
+typed_values!(
+    String(String),
+    UInt32(u32),
+    Int32(i32),
+    Float32(f32),
+    UInt64(u64),
+    Int64(i64),
+    Float64(f64),
+    Decimal(Decimal),
+    BigInt(Integer),
+    Boolean(bool),
+    LangString(LangString),
+    AnyURI(AnyURI),
+    Language(Language),
+    NormalizedString(NormalizedString),
+    Token(Token),
+    NMToken(NMToken),
+    Name(Name),
+    NCName(NCName),
+    Notation(Notation),
+    QName(QName),
+    ID(ID),
+    IDRef(IDRef),
+    Entity(Entity),
+    PositiveInteger(PositiveInteger),
+    NonNegativeInteger(NonNegativeInteger),
+    NonPositiveInteger(NonPositiveInteger),
+    NegativeInteger(NegativeInteger),
+    Date(Date),
+    DateTime(NaiveDateTime),
+    DateTimeStamp(DateTimeStamp),
+    Time(NaiveTime),
+    GYear(GYear),
+    GMonth(GMonth),
+    GDay(GDay),
+    GYearMonth(GYearMonth),
+    GMonthDay(GMonthDay),
+    Duration(Duration),
+    YearMonthDuration(YearMonthDuration),
+    DayTimeDuration(DayTimeDuration),
+    UInt8(u8),
+    Int8(i8),
+    UInt16(u16),
+    Int16(i16),
+    Base64Binary(Base64Binary),
+    HexBinary(HexBinary),
+    AnySimpleType(AnySimpleType),
+);
+
 #[derive(Clone, Debug)]
 pub struct TypedDict {
     types_present: MonotonicLogArray,
@@ -155,6 +265,56 @@ impl TypedDict {
         result.map(|entry| entry.datatype.cast(entry.into_buf()))
     }
 
+    /// Returns the entry with the given id, or `None` if there is no such id. Unlike `entry`,
+    /// this accepts id 0, which is never in use.
+    fn entry_for_id(&self, id: u64) -> Option<TypedDictEntry> {
+        if id == 0 {
+            return None;
+        }
+
+        self.entry(id as usize)
+    }
+
+    /// Returns the value with the given id as a `u64`, or `None` if there is no such id or it is
+    /// not an unsigned integer.
+    pub fn get_u64(&self, id: u64) -> Option<u64> {
+        self.entry_for_id(id).and_then(|entry| entry.as_u64())
+    }
+
+    /// Returns the value with the given id as an `i64`, or `None` if there is no such id or it is
+    /// not an integer that fits.
+    pub fn get_i64(&self, id: u64) -> Option<i64> {
+        self.entry_for_id(id).and_then(|entry| entry.as_i64())
+    }
+
+    /// Returns the value with the given id as an `f64`, or `None` if there is no such id or it is
+    /// not a float.
+    pub fn get_f64(&self, id: u64) -> Option<f64> {
+        self.entry_for_id(id).and_then(|entry| entry.as_f64())
+    }
+
+    /// Returns the value with the given id, decoded into the Rust type belonging to its
+    /// datatype, or `None` if there is no such id.
+    pub fn id_to_value(&self, id: u64) -> Option<TypedValue> {
+        self.entry_for_id(id).map(|entry| entry.to_value())
+    }
+
+    /// Returns an iterator over the ids and decoded values of all entries of the given
+    /// datatype, in id order.
+    ///
+    /// Only the blocks of this datatype are read. If the datatype does not occur in this
+    /// dictionary, the iterator is empty.
+    pub fn iter_type(&self, datatype: Datatype) -> impl Iterator<Item = (u64, TypedValue)> {
+        self.type_segment(datatype)
+            .into_iter()
+            .flat_map(move |(dict, offset)| {
+                (offset + 1..).zip(
+                    dict.into_iter()
+                        .map(move |entry| TypedDictEntry::new(datatype, entry).to_value()),
+                )
+            })
+    }
+
     fn inner_type_segment(&self, i: usize) -> (SizedDict, u64) {
         let type_offset;
         let block_offset;
@@ -387,6 +547,12 @@ impl StringDict {
         self.0.id(val)
     }
 
+    /// Returns the inclusive range of ids whose strings start with `prefix`, or `None` if no
+    /// string does.
+    pub fn prefix_range(&self, prefix: &str) -> Option<(u64, u64)> {
+        self.0.dict.prefix_range(prefix.as_bytes())
+    }
+
     pub fn num_entries(&self) -> usize {
         self.0.num_entries()
     }
@@ -420,6 +586,10 @@ impl<B1: BufMut, B2: BufMut> StringDictBufBuilder<B1, B2> {
         self.0.add(value)
     }
 
+    pub fn try_add(&mut self, value: Bytes) -> Result<u64, SizedDictBuilderError> {
+        self.0.try_add(value)
+    }
+
     pub fn add_entry(&mut self, e: &SizedDictEntry) -> u64 {
         self.0.add_entry(e)
     }
@@ -525,10 +695,7 @@ impl<B1: BufMut, B2: BufMut, B3: BufMut, B4: BufMut> TypedDictBufBuilder<B1, B2,
 #[cfg(test)]
 mod tests {
     use bytes::BytesMut;
-    use chrono::{NaiveDate, NaiveDateTime};
-    use rug::Integer;
-
-    use crate::Decimal;
+    use chrono::NaiveDate;
 
     use super::*;
     fn build_multiple_segments<
@@ -1184,6 +1351,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn numeric_values_decode_to_native_types() {
+        let mut vec = vec![
+            String::make_entry(&"not a number"),
+            u64::make_entry(&(1_u64 << 40)),
+            u64::make_entry(&3_u64),
+            u32::make_entry(&70_000_u32),
+            u8::make_entry(&200_u8),
+            i64::make_entry(&-5_000_000_000_i64),
+            i32::make_entry(&-17_i32),
+            i8::make_entry(&-3_i8),
+            f64::make_entry(&-2.5_f64),
+            f64::make_entry(&1e100_f64),
+            f32::make_entry(&1.5_f32),
+        ];
+        vec.sort();
+
+        let mut typed_builder = TypedDictBufBuilder::new(
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+        );
+        typed_builder.add_all(vec.clone().into_iter());
+        let (used_types, type_offsets, block_offsets, data) = typed_builder.finalize();
+        let dict = TypedDict::from_parts(
+            used_types.freeze(),
+            type_offsets.freeze(),
+            block_offsets.freeze(),
+            data.freeze(),
+        );
+
+        let id_of = |entry: TypedDictEntry| match dict.id_entry(&entry) {
+            IdLookupResult::Found(id) => id,
+            _ => panic!("entry not found"),
+        };
+
+        assert_eq!(
+            Some(1 << 40),
+            dict.get_u64(id_of(u64::make_entry(&(1_u64 << 40))))
+        );
+        assert_eq!(Some(3), dict.get_u64(id_of(u64::make_entry(&3_u64))));
+        assert_eq!(
+            Some(70_000),
+            dict.get_u64(id_of(u32::make_entry(&70_000_u32)))
+        );
+        assert_eq!(Some(200), dict.get_u64(id_of(u8::make_entry(&200_u8))));
+        assert_eq!(Some(200), dict.get_i64(id_of(u8::make_entry(&200_u8))));
+        assert_eq!(
+            Some(-5_000_000_000),
+            dict.get_i64(id_of(i64::make_entry(&-5_000_000_000_i64)))
+        );
+        assert_eq!(Some(-17), dict.get_i64(id_of(i32::make_entry(&-17_i32))));
+        assert_eq!(Some(-3), dict.get_i64(id_of(i8::make_entry(&-3_i8))));
+        assert_eq!(Some(-2.5), dict.get_f64(id_of(f64::make_entry(&-2.5_f64))));
+        assert_eq!(
+            Some(1e100),
+            dict.get_f64(id_of(f64::make_entry(&1e100_f64)))
+        );
+        assert_eq!(Some(1.5), dict.get_f64(id_of(f32::make_entry(&1.5_f32))));
+
+        // type mismatches
+        let string_id = id_of(String::make_entry(&"not a number"));
+        assert_eq!(None, dict.get_u64(string_id));
+        assert_eq!(None, dict.get_i64(string_id));
+        assert_eq!(None, dict.get_f64(string_id));
+        assert_eq!(None, dict.get_u64(id_of(i32::make_entry(&-17_i32))));
+        assert_eq!(None, dict.get_i64(id_of(u64::make_entry(&3_u64))));
+        assert_eq!(None, dict.get_f64(id_of(u64::make_entry(&3_u64))));
+        assert_eq!(None, dict.get_u64(id_of(f64::make_entry(&-2.5_f64))));
+        assert_eq!(None, dict.get_u64(dict.num_entries() as u64 + 1));
+
+        // id 0 is never in use
+        assert_eq!(None, dict.get_u64(0));
+        assert_eq!(None, dict.get_i64(0));
+        assert_eq!(None, dict.get_f64(0));
+    }
+
     #[test]
     fn test_two_blocks() {
         let mut vec: Vec<TypedDictEntry> = vec![
@@ -1299,6 +1544,280 @@ mod tests {
         }
     }
 
+    #[test]
+    fn id_to_value_decodes_every_datatype() {
+        let datetime = NaiveDate::from_ymd_opt(2002, 11, 4)
+            .unwrap()
+            .and_hms_nano_opt(11, 30, 12, 300_000_000)
+            .unwrap();
+        let time = NaiveTime::from_hms_opt(23, 59, 1).unwrap();
+        let stringy = |s: &str| Bytes::copy_from_slice(s.as_bytes());
+        let duration = || Duration {
+            sign: 1,
+            year: 3,
+            month: 2,
+            day: 1,
+            hour: 0,
+            minute: 30,
+            second: 1.5,
+        };
+
+        let mut values: Vec<(TypedDictEntry, TypedValue)> = vec![
+            (u32::make_entry(&7_u32), TypedValue::UInt32(7)),
+            (i32::make_entry(&-7_i32), TypedValue::Int32(-7)),
+            (f32::make_entry(&0.5_f32), TypedValue::Float32(0.5)),
+            (i64::make_entry(&-1_i64), TypedValue::Int64(-1)),
+            (f64::make_entry(&-1e10_f64), TypedValue::Float64(-1e10)),
+            (
+                Decimal::make_entry(&Decimal("12.5".to_string())),
+                TypedValue::Decimal(Decimal("12.5".to_string())),
+            ),
+            (
+                Integer::make_entry(&Integer::from(-123_i64)),
+                TypedValue::BigInt(Integer::from(-123_i64)),
+            ),
+            (bool::make_entry(&true), TypedValue::Boolean(true)),
+            (
+                PositiveInteger::make_entry(&PositiveInteger(Integer::from(5_u64))),
+                TypedValue::PositiveInteger(PositiveInteger(Integer::from(5_u64))),
+            ),
+            (
+                NonNegativeInteger::make_entry(&NonNegativeInteger(Integer::from(0_u64))),
+                TypedValue::NonNegativeInteger(NonNegativeInteger(Integer::from(0_u64))),
+            ),
+            (
+                NonPositiveInteger::make_entry(&NonPositiveInteger(Integer::from(-2_i64))),
+                TypedValue::NonPositiveInteger(NonPositiveInteger(Integer::from(-2_i64))),
+            ),
+            (
+                NegativeInteger::make_entry(&NegativeInteger(Integer::from(-9_i64))),
+                TypedValue::NegativeInteger(NegativeInteger(Integer::from(-9_i64))),
+            ),
+            (
+                Date::make_entry(&Date {
+                    year: 2020,
+                    month: 2,
+                    day: 29,
+                    offset: 60,
+                }),
+                TypedValue::Date(Date {
+                    year: 2020,
+                    month: 2,
+                    day: 29,
+                    offset: 60,
+                }),
+            ),
+            (
+                NaiveDateTime::make_entry(&datetime),
+                TypedValue::DateTime(datetime),
+            ),
+            (
+                DateTimeStamp::make_entry(&DateTimeStamp(datetime)),
+                TypedValue::DateTimeStamp(DateTimeStamp(datetime)),
+            ),
+            (NaiveTime::make_entry(&time), TypedValue::Time(time)),
+            (
+                GYear::make_entry(&GYear {
+                    year: -44,
+                    offset: 0,
+                }),
+                TypedValue::GYear(GYear {
+                    year: -44,
+                    offset: 0,
+                }),
+            ),
+            (
+                GMonth::make_entry(&GMonth {
+                    month: 12,
+                    offset: -30,
+                }),
+                TypedValue::GMonth(GMonth {
+                    month: 12,
+                    offset: -30,
+                }),
+            ),
+            (
+                GDay::make_entry(&GDay { day: 31, offset: 0 }),
+                TypedValue::GDay(GDay { day: 31, offset: 0 }),
+            ),
+            (
+                GYearMonth::make_entry(&GYearMonth {
+                    year: 1999,
+                    month: 1,
+                    offset: 0,
+                }),
+                TypedValue::GYearMonth(GYearMonth {
+                    year: 1999,
+                    month: 1,
+                    offset: 0,
+                }),
+            ),
+            (
+                GMonthDay::make_entry(&GMonthDay {
+                    month: 7,
+                    day: 4,
+                    offset: 0,
+                }),
+                TypedValue::GMonthDay(GMonthDay {
+                    month: 7,
+                    day: 4,
+                    offset: 0,
+                }),
+            ),
+            (
+                Duration::make_entry(&duration()),
+                TypedValue::Duration(duration()),
+            ),
+            (
+                YearMonthDuration::make_entry(&YearMonthDuration(duration())),
+                TypedValue::YearMonthDuration(YearMonthDuration(duration())),
+            ),
+            (
+                DayTimeDuration::make_entry(&DayTimeDuration(duration())),
+                TypedValue::DayTimeDuration(DayTimeDuration(duration())),
+            ),
+            (u8::make_entry(&255_u8), TypedValue::UInt8(255)),
+            (i8::make_entry(&-128_i8), TypedValue::Int8(-128)),
+            (u16::make_entry(&1_u16), TypedValue::UInt16(1)),
+            (i16::make_entry(&-300_i16), TypedValue::Int16(-300)),
+            (
+                Base64Binary::make_entry(&Base64Binary(vec![0, 1, 2])),
+                TypedValue::Base64Binary(Base64Binary(vec![0, 1, 2])),
+            ),
+            (
+                HexBinary::make_entry(&HexBinary(vec![0xff])),
+                TypedValue::HexBinary(HexBinary(vec![0xff])),
+            ),
+        ];
+        macro_rules! stringy_value {
+            ($ty:ident, $s:expr) => {
+                values.push((
+                    $ty::make_entry(&$s),
+                    TypedValue::$ty($ty::from_lexical(stringy($s))),
+                ))
+            };
+        }
+        stringy_value!(LangString, "en@hello");
+        stringy_value!(AnyURI, "http://example.com/");
+        stringy_value!(Language, "en");
+        stringy_value!(NormalizedString, "normalized");
+        stringy_value!(Token, "token");
+        stringy_value!(NMToken, "nmtoken");
+        stringy_value!(Name, "name");
+        stringy_value!(NCName, "ncname");
+        stringy_value!(Notation, "notation");
+        stringy_value!(QName, "ex:qname");
+        stringy_value!(ID, "id");
+        stringy_value!(IDRef, "idref");
+        stringy_value!(Entity, "entity");
+        stringy_value!(AnySimpleType, "anything");
+        // enough strings and integers to span several blocks of their type
+        for i in 0..20_u64 {
+            values.push((
+                String::make_entry(&format!("string {:02}", i)),
+                TypedValue::String(format!("string {:02}", i)),
+            ));
+            values.push((u64::make_entry(&(i * 1000)), TypedValue::UInt64(i * 1000)));
+        }
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut typed_builder = TypedDictBufBuilder::new(
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+        );
+        typed_builder.add_all(values.iter().map(|(entry, _)| entry.clone()));
+        let (used_types, type_offsets, block_offsets, data) = typed_builder.finalize();
+        let dict = TypedDict::from_parts(
+            used_types.freeze(),
+            type_offsets.freeze(),
+            block_offsets.freeze(),
+            data.freeze(),
+        );
+
+        assert_eq!(values.len(), dict.num_entries());
+        for (i, (entry, expected)) in values.iter().enumerate() {
+            let value = dict.id_to_value(i as u64 + 1).unwrap();
+            assert_eq!(entry.datatype(), value.datatype());
+            assert_eq!(expected, &value);
+        }
+
+        // the boundaries between the string and uint64 blocks and their neighbours
+        let first_uint64 = values
+            .iter()
+            .position(|(entry, _)| entry.datatype() == Datatype::UInt64)
+            .unwrap() as u64;
+        assert_eq!(
+            Some(TypedValue::Float32(0.5)),
+            dict.id_to_value(first_uint64)
+        );
+        assert_eq!(
+            Some(TypedValue::UInt64(0)),
+            dict.id_to_value(first_uint64 + 1)
+        );
+        assert_eq!(
+            Some(TypedValue::UInt64(19_000)),
+            dict.id_to_value(first_uint64 + 20)
+        );
+        assert_eq!(
+            Some(TypedValue::Int64(-1)),
+            dict.id_to_value(first_uint64 + 21)
+        );
+
+        assert_eq!(None, dict.id_to_value(0));
+        assert_eq!(None, dict.id_to_value(values.len() as u64 + 1));
+    }
+
+    #[test]
+    fn iter_type_only_yields_entries_of_that_type() {
+        let mut vec: Vec<TypedDictEntry> = (0..30_u64)
+            .flat_map(|i| {
+                [
+                    String::make_entry(&format!("s{:02}", i)),
+                    u64::make_entry(&(i * 7)),
+                    i32::make_entry(&(-(i as i32))),
+                ]
+            })
+            .collect();
+        vec.push(f64::make_entry(&0.25_f64));
+        vec.sort();
+
+        let mut typed_builder = TypedDictBufBuilder::new(
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+        );
+        typed_builder.add_all(vec.into_iter());
+        let (used_types, type_offsets, block_offsets, data) = typed_builder.finalize();
+        let dict = TypedDict::from_parts(
+            used_types.freeze(),
+            type_offsets.freeze(),
+            block_offsets.freeze(),
+            data.freeze(),
+        );
+
+        for datatype in [
+            Datatype::String,
+            Datatype::Int32,
+            Datatype::UInt64,
+            Datatype::Float64,
+        ] {
+            let expected: Vec<(u64, TypedValue)> = (1..=dict.num_entries() as u64)
+                .map(|id| (id, dict.id_to_value(id).unwrap()))
+                .filter(|(_, value)| value.datatype() == datatype)
+                .collect();
+            assert!(!expected.is_empty());
+            assert_eq!(expected, dict.iter_type(datatype).collect::<Vec<_>>());
+        }
+
+        let uint64s: Vec<_> = dict.iter_type(Datatype::UInt64).collect();
+        assert_eq!(30, uint64s.len());
+        assert_eq!((63, TypedValue::UInt64(14)), uint64s[2]);
+        assert_eq!(0, dict.iter_type(Datatype::Boolean).count());
+    }
+
     #[test]
     fn test_datetime() {
         let year = 2002;
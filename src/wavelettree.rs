@@ -1,10 +1,11 @@
 //! A succinct data structure for quick lookup of entry positions in a sequence.
 
 use bitvec::vec::BitVec;
+use futures::stream;
 use futures::Stream;
 use futures::TryStreamExt;
 
-use crate::storage::{FileLoad, FileStore};
+use crate::storage::{FileLoad, FileStore, SyncableFile};
 
 use super::bitarray::*;
 use super::bitindex::*;
@@ -12,7 +13,9 @@ use super::logarray::*;
 use super::util;
 
 use std::convert::TryInto;
+use std::future::Future;
 use std::io;
+use std::ops::Range;
 
 /// A wavelet tree, encoding a u64 array for fast lookup of number positions.
 ///
@@ -88,6 +91,53 @@ impl WaveletLookup {
     }
 }
 
+/// A cursor over all positions of a symbol in a wavelet tree, in increasing order.
+///
+/// The ranges of the symbol in every layer are calculated once when the cursor is created. Every
+/// call to `next` only has to select its way back up through those ranges.
+#[derive(Clone)]
+pub struct OccurrenceCursor {
+    lookup: Option<WaveletLookup>,
+    /// the number of occurrences already returned.
+    index: usize,
+    /// the total number of occurrences.
+    len: usize,
+}
+
+impl OccurrenceCursor {
+    /// Returns the symbol this cursor iterates over.
+    pub fn symbol(&self) -> Option<u64> {
+        self.lookup.as_ref().map(|l| l.entry)
+    }
+
+    /// Returns the number of occurrences that have already been returned.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+}
+
+impl Iterator for OccurrenceCursor {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.index >= self.len {
+            return None;
+        }
+
+        let result = self.lookup.as_ref().unwrap().entry(self.index);
+        self.index += 1;
+
+        Some(result as usize)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for OccurrenceCursor {}
+
 impl WaveletTree {
     /// Construct a wavelet tree from a bitindex and a layer count.
     pub fn from_parts(bits: BitIndex, num_layers: u8) -> WaveletTree {
@@ -112,6 +162,18 @@ impl WaveletTree {
         self.num_layers as usize
     }
 
+    /// Returns the amount of levels. This is the same as the amount of layers.
+    pub fn num_levels(&self) -> usize {
+        self.num_layers()
+    }
+
+    /// Returns the number of bits needed to represent every symbol in the alphabet of this tree.
+    ///
+    /// Every symbol stored in this tree is smaller than `2^alphabet_bits`.
+    pub fn alphabet_bits(&self) -> u8 {
+        self.num_layers
+    }
+
     /// Decode the wavelet tree to the original u64 sequence. This returns an iterator.
     pub fn decode(&self) -> impl Iterator<Item = u64> {
         let owned = self.clone();
@@ -202,10 +264,285 @@ impl WaveletTree {
         })
     }
 
+    /// Returns a cursor over all positions of the given symbol.
+    ///
+    /// If the symbol does not occur in the tree, the cursor is empty.
+    pub fn occurrences(&self, symbol: u64) -> OccurrenceCursor {
+        let lookup = self.lookup(symbol);
+        let len = lookup.as_ref().map(|l| l.len()).unwrap_or(0);
+
+        OccurrenceCursor {
+            lookup,
+            index: 0,
+            len,
+        }
+    }
+
     /// Lookup the given entry. This returns a single result, even if there's multiple.
     pub fn lookup_one(&self, entry: u64) -> Option<u64> {
         self.lookup(entry).map(|l| l.entry(0))
     }
+
+    /// Returns the number of occurrences of `symbol` in the first `pos` positions.
+    ///
+    /// At every layer, the prefix is narrowed down to the part that goes into the same half of
+    /// the alphabet as `symbol`, using the rank of the layer bits within the current node.
+    ///
+    /// Panics if `pos` is larger than the length of the tree.
+    pub fn rank(&self, symbol: u64, pos: u64) -> u64 {
+        let len = self.len() as u64;
+        assert!(
+            pos <= len,
+            "expected position ({}) <= length ({})",
+            pos,
+            len
+        );
+        if symbol.checked_shr(self.num_layers as u32).unwrap_or(0) != 0 {
+            // symbols outside of the alphabet never occur
+            return 0;
+        }
+
+        // the prefix length, relative to the start of the current node
+        let mut prefix = pos;
+        let mut node_start = 0;
+        let mut node_end = len;
+        for layer in 0..self.num_layers as u64 {
+            if prefix == 0 {
+                return 0;
+            }
+
+            let offset = layer * len + node_start;
+            let ones_before = self.bits.rank1_from_range(offset, offset + prefix);
+            let node_zeros = self
+                .bits
+                .rank0_from_range(offset, offset + node_end - node_start);
+
+            if symbol & (1 << (self.num_layers as u64 - layer - 1)) != 0 {
+                prefix = ones_before;
+                node_start += node_zeros;
+            } else {
+                prefix -= ones_before;
+                node_end = node_start + node_zeros;
+            }
+        }
+
+        prefix
+    }
+
+    /// Returns the number of positions in `[pos_start, pos_end)` holding a symbol in
+    /// `[sym_start, sym_end]`.
+    ///
+    /// The tree is descended into both halves of the alphabet, skipping any node whose symbols
+    /// all fall outside of the symbol range, and counting any node whose symbols all fall inside
+    /// it without descending further.
+    ///
+    /// Panics if `pos_end` is larger than the length of the tree.
+    pub fn range_count(&self, pos_start: u64, pos_end: u64, sym_start: u64, sym_end: u64) -> u64 {
+        let len = self.len() as u64;
+        assert!(
+            pos_end <= len,
+            "expected range end ({}) <= length ({})",
+            pos_end,
+            len
+        );
+        if pos_start >= pos_end || sym_start > sym_end || self.num_layers == 0 {
+            return 0;
+        }
+
+        let alphabet_end = u64::MAX >> (64 - self.num_layers as u32);
+        self.range_count_node(
+            0,
+            0..len,
+            pos_start..pos_end,
+            (0, alphabet_end),
+            (sym_start, sym_end),
+        )
+    }
+
+    /// Count the positions in `range`, relative to the start of `node`, holding a symbol in
+    /// `symbols`. The node covers the inclusive symbol interval `alphabet`.
+    fn range_count_node(
+        &self,
+        layer: u64,
+        node: Range<u64>,
+        range: Range<u64>,
+        alphabet: (u64, u64),
+        symbols: (u64, u64),
+    ) -> u64 {
+        if range.start >= range.end || alphabet.1 < symbols.0 || alphabet.0 > symbols.1 {
+            return 0;
+        }
+        if symbols.0 <= alphabet.0 && alphabet.1 <= symbols.1 {
+            return range.end - range.start;
+        }
+
+        let offset = layer * self.len() as u64 + node.start;
+        let ones_before = self.bits.rank1_from_range(offset, offset + range.start);
+        let ones = self
+            .bits
+            .rank1_from_range(offset + range.start, offset + range.end);
+        let node_zeros = self
+            .bits
+            .rank0_from_range(offset, offset + node.end - node.start);
+        let middle = alphabet.0 + (alphabet.1 - alphabet.0) / 2;
+
+        let zeros_before = range.start - ones_before;
+        let zeros = range.end - range.start - ones;
+        self.range_count_node(
+            layer + 1,
+            node.start..node.start + node_zeros,
+            zeros_before..zeros_before + zeros,
+            (alphabet.0, middle),
+            symbols,
+        ) + self.range_count_node(
+            layer + 1,
+            node.start + node_zeros..node.end,
+            ones_before..ones_before + ones,
+            (middle + 1, alphabet.1),
+            symbols,
+        )
+    }
+
+    /// Returns the `k`th smallest symbol among the positions in `[pos_start, pos_end)`, counting
+    /// from 0, or `None` if the range holds no more than `k` positions.
+    ///
+    /// At every layer, the zeros in the range are the positions holding a symbol in the lower half
+    /// of the alphabet. If there are more than `k` of them, the answer is in the lower half,
+    /// otherwise it is in the upper half, skipping those zeros.
+    ///
+    /// Panics if `pos_end` is larger than the length of the tree.
+    pub fn quantile(&self, pos_start: u64, pos_end: u64, k: u64) -> Option<u64> {
+        let len = self.len() as u64;
+        assert!(
+            pos_end <= len,
+            "expected range end ({}) <= length ({})",
+            pos_end,
+            len
+        );
+        if pos_start >= pos_end || k >= pos_end - pos_start {
+            return None;
+        }
+
+        let mut k = k;
+        // the range, relative to the start of the current node
+        let mut start = pos_start;
+        let mut end = pos_end;
+        let mut node_start = 0;
+        let mut node_end = len;
+        let mut symbol = 0;
+        for layer in 0..self.num_layers as u64 {
+            let offset = layer * len + node_start;
+            let ones_before = self.bits.rank1_from_range(offset, offset + start);
+            let ones = self.bits.rank1_from_range(offset + start, offset + end);
+            let zeros = end - start - ones;
+            let node_zeros = self
+                .bits
+                .rank0_from_range(offset, offset + node_end - node_start);
+
+            symbol <<= 1;
+            if k < zeros {
+                let zeros_before = start - ones_before;
+                start = zeros_before;
+                end = zeros_before + zeros;
+                node_end = node_start + node_zeros;
+            } else {
+                k -= zeros;
+                symbol |= 1;
+                start = ones_before;
+                end = ones_before + ones;
+                node_start += node_zeros;
+            }
+        }
+
+        Some(symbol)
+    }
+
+    /// Returns the symbol occurring in more than half of the positions in `range`, if any.
+    ///
+    /// At every layer, only the half of the alphabet containing more than half of the range can
+    /// contain a majority, so the tree is descended along that half.
+    ///
+    /// Panics if the range extends beyond the end of the tree.
+    pub fn range_majority(&self, range: Range<usize>) -> Option<u64> {
+        assert!(
+            range.end <= self.len(),
+            "expected range end ({}) <= length ({})",
+            range.end,
+            self.len()
+        );
+        if range.start >= range.end || self.num_layers == 0 {
+            return None;
+        }
+
+        let len = self.len() as u64;
+        let total = (range.end - range.start) as u64;
+        // the range, relative to the start of the current node
+        let mut start = range.start as u64;
+        let mut end = range.end as u64;
+        let mut node_start = 0;
+        let mut node_end = len;
+        let mut symbol = 0;
+        for layer in 0..self.num_layers as u64 {
+            let offset = layer * len + node_start;
+            let ones_before = self.bits.rank1_from_range(offset, offset + start);
+            let ones = self.bits.rank1_from_range(offset + start, offset + end);
+            let zeros = end - start - ones;
+            let node_zeros = self
+                .bits
+                .rank0_from_range(offset, offset + node_end - node_start);
+
+            symbol <<= 1;
+            if ones * 2 > total {
+                symbol |= 1;
+                start = ones_before;
+                end = ones_before + ones;
+                node_start += node_zeros;
+            } else if zeros * 2 > total {
+                let zeros_before = start - ones_before;
+                start = zeros_before;
+                end = zeros_before + zeros;
+                node_end = node_start + node_zeros;
+            } else {
+                return None;
+            }
+        }
+
+        Some(symbol)
+    }
+
+    /// Returns the positions of the encoded sequence, ordered by their symbol.
+    ///
+    /// Positions with equal symbols keep their original order. Every layer of the tree is a stable
+    /// partition of the nodes of the layer above, so following the positions down through the
+    /// layers leaves them in the order of the leaves, which is the sorted order.
+    pub fn argsort(&self) -> Vec<usize> {
+        let len = self.len();
+        let mut order: Vec<usize> = (0..len).collect();
+        let mut nodes = vec![(0, len)];
+        for layer in 0..self.num_layers as usize {
+            let layer_start = layer * len;
+            let bit = |i: usize| self.bits.get((layer_start + i) as u64);
+            let mut next_order = Vec::with_capacity(len);
+            let mut next_nodes = Vec::with_capacity(nodes.len() * 2);
+            for &(start, end) in nodes.iter() {
+                next_order.extend((start..end).filter(|&i| !bit(i)).map(|i| order[i]));
+                let middle = next_order.len();
+                next_order.extend((start..end).filter(|&i| bit(i)).map(|i| order[i]));
+
+                if start != middle {
+                    next_nodes.push((start, middle));
+                }
+                if middle != end {
+                    next_nodes.push((middle, end));
+                }
+            }
+
+            order = next_order;
+            nodes = next_nodes;
+        }
+
+        order
+    }
 }
 
 #[derive(Debug)]
@@ -364,6 +701,154 @@ pub async fn build_wavelet_tree_from_logarray<
     Ok(())
 }
 
+/// Merge the two partitions of the previous layer into the order the next layer needs.
+///
+/// Both partitions are ordered by the bits above `shift`, and within equal bits in their original
+/// order. Merging them on all bits from `shift` up therefore gives a stable sort on those bits.
+async fn merge_partitions<S: Stream<Item = io::Result<u64>> + Unpin>(
+    mut zeros: S,
+    mut ones: S,
+    shift: u32,
+) -> io::Result<impl Stream<Item = io::Result<u64>> + Unpin> {
+    let heads = (zeros.try_next().await?, ones.try_next().await?);
+
+    Ok(Box::pin(stream::try_unfold(
+        (zeros, ones, heads),
+        move |(mut zeros, mut ones, heads)| async move {
+            let take_zero = match heads {
+                (Some(zero), Some(one)) => zero >> shift < one >> shift,
+                (zero, _) => zero.is_some(),
+            };
+
+            match heads {
+                (Some(zero), one) if take_zero => {
+                    let next = zeros.try_next().await?;
+                    Ok(Some((zero, (zeros, ones, (next, one)))))
+                }
+                (zero, Some(one)) => {
+                    let next = ones.try_next().await?;
+                    Ok(Some((one, (zeros, ones, (zero, next)))))
+                }
+                _ => Ok(None),
+            }
+        },
+    )))
+}
+
+/// Write the bits of a single layer, and partition the values on that bit for the next layer.
+async fn write_wavelet_layer<
+    S: Stream<Item = io::Result<u64>> + Unpin,
+    W: SyncableFile,
+    T: FileStore,
+>(
+    mut values: S,
+    width: u8,
+    bit: u8,
+    bits: &mut BitArrayFileBuilder<W>,
+    partitions: Option<&[T]>,
+) -> io::Result<()> {
+    let mut builders = match partitions {
+        Some(partitions) => Some((
+            LogArrayFileBuilder::new(partitions[0].open_write().await?, width),
+            LogArrayFileBuilder::new(partitions[1].open_write().await?, width),
+        )),
+        None => None,
+    };
+
+    while let Some(num) = values.try_next().await? {
+        if util::calculate_width(num) > width {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected value ({}) to fit in {} bits, as it did on the first pass",
+                    num, width
+                ),
+            ));
+        }
+
+        let is_set = (num >> bit) & 1 == 1;
+        bits.push(is_set).await?;
+        if let Some((zeros, ones)) = builders.as_mut() {
+            if is_set {
+                ones.push(num).await?;
+            } else {
+                zeros.push(num).await?;
+            }
+        }
+    }
+
+    if let Some((zeros, ones)) = builders {
+        zeros.finalize().await?;
+        ones.finalize().await?;
+    }
+
+    Ok(())
+}
+
+/// Build a wavelet tree from a stream, without holding the sequence or the tree in memory.
+///
+/// `source` is called twice, and both streams have to produce the same sequence. The first pass
+/// determines the width of the largest value. The second pass writes the first layer, and stably
+/// partitions the values on their highest bit into log arrays in two of the scratch stores. Each
+/// following layer is written in a single pass over the merged partitions of the layer before,
+/// while partitioning the values into the other two scratch stores.
+///
+/// Returns the number of layers of the tree.
+pub async fn build_wavelet_tree_from_stream_bounded<
+    S: Stream<Item = io::Result<u64>> + Unpin,
+    Fut: Future<Output = io::Result<S>>,
+    G: Fn() -> Fut,
+    T: 'static + FileLoad + FileStore,
+    F: 'static + FileLoad + FileStore,
+>(
+    source: G,
+    scratch: [T; 4],
+    destination_bits: F,
+    destination_blocks: F,
+    destination_sblocks: F,
+) -> io::Result<u8> {
+    let mut max = 0;
+    let mut first_pass = source().await?;
+    while let Some(num) = first_pass.try_next().await? {
+        max = std::cmp::max(max, num);
+    }
+    let width = util::calculate_width(max);
+
+    let mut bits = BitArrayFileBuilder::new(destination_bits.open_write().await?);
+    for layer in 0..width {
+        let bit = width - layer - 1;
+        // the last layer has no next layer to partition for
+        let partitions = if bit == 0 {
+            None
+        } else {
+            Some(&scratch[(layer as usize % 2) * 2..][..2])
+        };
+
+        if layer == 0 {
+            write_wavelet_layer(source().await?, width, bit, &mut bits, partitions).await?;
+        } else {
+            let previous = &scratch[((layer as usize - 1) % 2) * 2..];
+            let values = merge_partitions(
+                logarray_stream_entries(previous[0].clone()).await?,
+                logarray_stream_entries(previous[1].clone()).await?,
+                bit as u32 + 1,
+            )
+            .await?;
+            write_wavelet_layer(values, width, bit, &mut bits, partitions).await?;
+        }
+    }
+    bits.finalize().await?;
+
+    build_bitindex(
+        destination_bits.open_read().await?,
+        destination_blocks.open_write().await?,
+        destination_sblocks.open_write().await?,
+    )
+    .await?;
+
+    Ok(width)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::storage::memory::MemoryBackedStore;
@@ -371,6 +856,28 @@ mod tests {
     use super::*;
     use futures::executor::block_on;
 
+    fn wavelet_tree_from_vec(width: u8, contents: &[u64]) -> WaveletTree {
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        block_on(build_wavelet_tree_from_iter(
+            width,
+            contents.iter().copied(),
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        ))
+        .unwrap();
+
+        let wavelet_bits = block_on(wavelet_bits_file.map()).unwrap();
+        let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
+        let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        WaveletTree::from_parts(wavelet_bitindex, width)
+    }
+
     #[test]
     fn generate_and_decode_wavelet_tree_from_vec() {
         let contents = vec![21, 1, 30, 13, 23, 21, 3, 0, 21, 21, 12, 11];
@@ -504,6 +1011,7 @@ mod tests {
         let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 4);
 
         assert!(wavelet_tree.lookup(3).is_none());
+        assert!(wavelet_tree.argsort().is_empty());
     }
 
     #[test]
@@ -567,6 +1075,38 @@ mod tests {
         assert!(wavelet_tree.lookup(6).is_none());
     }
 
+    #[test]
+    fn wavelet_tree_shape_accessors() {
+        let contents = vec![21, 1, 30, 13, 23, 21, 3, 0, 21, 21, 12, 11];
+
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        block_on(build_wavelet_tree_from_iter(
+            5,
+            contents.clone().into_iter(),
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        ))
+        .unwrap();
+
+        let wavelet_bits = block_on(wavelet_bits_file.map()).unwrap();
+        let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
+        let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 5);
+
+        assert_eq!(contents.len(), wavelet_tree.len());
+        assert_eq!(5, wavelet_tree.num_levels());
+        assert_eq!(5, wavelet_tree.alphabet_bits());
+        assert!(contents
+            .iter()
+            .all(|&c| c < 2_u64.pow(wavelet_tree.alphabet_bits() as u32)));
+    }
+
     #[test]
     fn wavelet_lookup_one() {
         let contents = vec![3, 6, 2, 1, 8, 5, 4, 7];
@@ -600,4 +1140,288 @@ mod tests {
         assert_eq!(Some(7), wavelet_tree.lookup_one(7));
         assert_eq!(Some(4), wavelet_tree.lookup_one(8));
     }
+
+    #[test]
+    fn occurrence_cursor_yields_positions_in_order() {
+        let contents = vec![21, 1, 30, 13, 23, 21, 3, 0, 21, 21, 12, 11, 1, 21];
+
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        block_on(build_wavelet_tree_from_iter(
+            5,
+            contents.clone().into_iter(),
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        ))
+        .unwrap();
+
+        let wavelet_bits = block_on(wavelet_bits_file.map()).unwrap();
+        let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
+        let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 5);
+
+        for symbol in 0..32 {
+            let expected: Vec<usize> = contents
+                .iter()
+                .enumerate()
+                .filter(|(_, &c)| c == symbol)
+                .map(|(i, _)| i)
+                .collect();
+            let cursor = wavelet_tree.occurrences(symbol);
+            assert_eq!(expected.len(), cursor.len());
+            assert_eq!(expected, cursor.collect::<Vec<_>>());
+        }
+
+        let mut cursor = wavelet_tree.occurrences(21);
+        assert_eq!(Some(21), cursor.symbol());
+        assert_eq!(Some(0), cursor.next());
+        assert_eq!(Some(5), cursor.next());
+        assert_eq!(2, cursor.position());
+        assert_eq!(3, cursor.len());
+
+        assert_eq!(0, wavelet_tree.occurrences(100).count());
+    }
+
+    #[test]
+    fn argsort_is_stable_sort_order() {
+        let contents = vec![21, 1, 30, 13, 23, 21, 3, 0, 21, 21, 12, 11, 1, 21, 30, 0];
+
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        block_on(build_wavelet_tree_from_iter(
+            5,
+            contents.clone().into_iter(),
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        ))
+        .unwrap();
+
+        let wavelet_bits = block_on(wavelet_bits_file.map()).unwrap();
+        let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
+        let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 5);
+
+        let mut expected: Vec<usize> = (0..contents.len()).collect();
+        expected.sort_by_key(|&i| contents[i]);
+        assert_eq!(expected, wavelet_tree.argsort());
+    }
+
+    #[test]
+    fn range_majority_matches_brute_force() {
+        let contents: Vec<u64> = vec![
+            5, 5, 2, 5, 7, 5, 5, 1, 2, 2, 2, 2, 0, 2, 6, 6, 6, 3, 6, 6, 5, 4, 4, 4,
+        ];
+
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        block_on(build_wavelet_tree_from_iter(
+            3,
+            contents.clone().into_iter(),
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        ))
+        .unwrap();
+
+        let wavelet_bits = block_on(wavelet_bits_file.map()).unwrap();
+        let wavelet_blocks = block_on(wavelet_blocks_file.map()).unwrap();
+        let wavelet_sblocks = block_on(wavelet_sblocks_file.map()).unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 3);
+        let decoded: Vec<u64> = wavelet_tree.decode().collect();
+
+        let mut found_none = false;
+        for i in 0..=contents.len() {
+            for j in i..=contents.len() {
+                let window = &decoded[i..j];
+                let expected =
+                    (0..8).find(|&s| window.iter().filter(|&&c| c == s).count() * 2 > window.len());
+                found_none |= expected.is_none() && !window.is_empty();
+                assert_eq!(
+                    expected,
+                    wavelet_tree.range_majority(i..j),
+                    "range {}..{}",
+                    i,
+                    j
+                );
+            }
+        }
+        assert!(found_none);
+        assert_eq!(Some(5), wavelet_tree.range_majority(0..7));
+        assert_eq!(None, wavelet_tree.range_majority(0..24));
+    }
+
+    #[test]
+    fn rank_matches_naive_count() {
+        for width in [1, 2, 3, 5, 8] {
+            let alphabet = 1_u64 << width;
+            let contents: Vec<u64> =
+                LogArray::parse(LogArray::build_from_seed(width as u64, 700, alphabet - 1))
+                    .unwrap()
+                    .iter()
+                    // skew towards small symbols, so that some symbols don't occur at all
+                    .map(|r| r * r / alphabet)
+                    .collect();
+            let wavelet_tree = wavelet_tree_from_vec(width, &contents);
+
+            for symbol in 0..alphabet {
+                let mut expected = 0;
+                for pos in 0..=contents.len() {
+                    assert_eq!(
+                        expected,
+                        wavelet_tree.rank(symbol, pos as u64),
+                        "rank of {} at {}",
+                        symbol,
+                        pos
+                    );
+                    if pos < contents.len() && contents[pos] == symbol {
+                        expected += 1;
+                    }
+                }
+            }
+            assert_eq!(0, wavelet_tree.rank(alphabet, contents.len() as u64));
+        }
+    }
+
+    #[test]
+    fn range_count_matches_brute_force() {
+        let contents = LogArray::parse(LogArray::build_from_seed(5, 300, 26))
+            .unwrap()
+            .to_vec();
+        let wavelet_tree = wavelet_tree_from_vec(5, &contents);
+
+        let len = contents.len() as u64;
+        for (pos_start, pos_end) in [(0, len), (0, 1), (17, 18), (13, 250), (100, 101), (5, 5)] {
+            for sym_start in 0..33 {
+                for sym_end in sym_start..33 {
+                    let expected = contents[pos_start as usize..pos_end as usize]
+                        .iter()
+                        .filter(|&&s| sym_start <= s && s <= sym_end)
+                        .count() as u64;
+                    assert_eq!(
+                        expected,
+                        wavelet_tree.range_count(pos_start, pos_end, sym_start, sym_end),
+                        "positions {}..{}, symbols {}..={}",
+                        pos_start,
+                        pos_end,
+                        sym_start,
+                        sym_end
+                    );
+                }
+            }
+        }
+        assert_eq!(len, wavelet_tree.range_count(0, len, 0, u64::MAX));
+        assert_eq!(0, wavelet_tree.range_count(0, len, 20, 10));
+    }
+
+    #[test]
+    fn quantile_matches_sorted_range() {
+        let contents = LogArray::parse(LogArray::build_from_seed(9, 200, 49))
+            .unwrap()
+            .to_vec();
+        let wavelet_tree = wavelet_tree_from_vec(6, &contents);
+
+        for pos_start in (0..200).step_by(7) {
+            for pos_end in (pos_start..=200).step_by(5) {
+                let mut sorted = contents[pos_start as usize..pos_end as usize].to_vec();
+                sorted.sort();
+                for (k, &expected) in sorted.iter().enumerate() {
+                    assert_eq!(
+                        Some(expected),
+                        wavelet_tree.quantile(pos_start, pos_end, k as u64),
+                        "{}th smallest in {}..{}",
+                        k,
+                        pos_start,
+                        pos_end
+                    );
+                }
+                assert_eq!(
+                    None,
+                    wavelet_tree.quantile(pos_start, pos_end, sorted.len() as u64)
+                );
+            }
+        }
+        assert_eq!(None, wavelet_tree.quantile(10, 10, 0));
+        assert_eq!(None, wavelet_tree.quantile(12, 10, 0));
+    }
+
+    #[tokio::test]
+    async fn bounded_stream_build_matches_in_memory_build() {
+        let contents: Vec<u64> = (0..1000u64).map(|i| (i * 7919) % 613).collect();
+        let source = MemoryBackedStore::new();
+        let mut builder = LogArrayFileBuilder::new(source.open_write().await.unwrap(), 10);
+        builder.push_vec(contents.clone()).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let bits_file = MemoryBackedStore::new();
+        let blocks_file = MemoryBackedStore::new();
+        let sblocks_file = MemoryBackedStore::new();
+        let width = build_wavelet_tree_from_stream_bounded(
+            || logarray_stream_entries(source.clone()),
+            std::array::from_fn(|_| MemoryBackedStore::new()),
+            bits_file.clone(),
+            blocks_file.clone(),
+            sblocks_file.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(10, width);
+
+        let expected_bits_file = MemoryBackedStore::new();
+        build_wavelet_tree_from_iter(
+            width,
+            contents.iter().copied(),
+            expected_bits_file.clone(),
+            MemoryBackedStore::new(),
+            MemoryBackedStore::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            expected_bits_file.map().await.unwrap(),
+            bits_file.map().await.unwrap()
+        );
+
+        let wavelet_bitindex = BitIndex::from_maps(
+            bits_file.map().await.unwrap(),
+            blocks_file.map().await.unwrap(),
+            sblocks_file.map().await.unwrap(),
+        );
+        let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, width);
+        for (i, &value) in contents.iter().enumerate() {
+            assert_eq!(value, wavelet_tree.decode_one(i));
+        }
+    }
+
+    #[tokio::test]
+    async fn bounded_stream_build_rejects_changed_source() {
+        let passes = std::sync::atomic::AtomicUsize::new(0);
+        let err = build_wavelet_tree_from_stream_bounded(
+            || {
+                let pass = passes.fetch_add(1, std::sync::atomic::Ordering::Relaxed) as u64;
+                futures::future::ok(util::stream_iter_ok(vec![1, 2, 3 + pass * 8]))
+            },
+            std::array::from_fn(|_| MemoryBackedStore::new()),
+            MemoryBackedStore::new(),
+            MemoryBackedStore::new(),
+            MemoryBackedStore::new(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
 }